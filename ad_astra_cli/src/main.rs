@@ -0,0 +1,272 @@
+extern crate ad_astra_runtime;
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::process;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ad_astra_runtime::{from_sexpr, to_sexpr, NeighborhoodError, NeighborhoodRuntime, Predicates, Traced, Tree};
+
+const USAGE: &str = "\
+usage:
+    ad-astra check <grammar> <trees>
+    ad-astra print-grammar [--lowering] <grammar>
+    ad-astra dot <grammar> [<trees>]
+    ad-astra generate [--count <n>] [--depth <n>] [--seed <n>] <grammar>
+
+The grammar is read in the syntax of `neighborhood!`. Trees are read as
+s-expressions, one neighborhood per file. A step is matched by the name of its
+variant, so `Int` in the grammar matches both `Int` and `Int(420)`.";
+
+/// A step read from a file, matched by the text before its payload.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd)]
+enum Atom {
+    Step(String),
+    Trace(usize),
+}
+
+impl Traced for Atom {
+    fn trace(&self) -> Option<usize> {
+        match self {
+            &Atom::Trace(idx) => Some(idx),
+            _ => None,
+        }
+    }
+
+    fn from_trace(idx: usize) -> Self {
+        Atom::Trace(idx)
+    }
+
+    fn variant_name(&self) -> Option<&str> {
+        match self {
+            &Atom::Step(ref text) => {
+                let end = text.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(text.len());
+                Some(&text[.. end])
+            }
+            &Atom::Trace(_) => None,
+        }
+    }
+}
+
+impl fmt::Debug for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Atom::Step(ref text) => write!(f, "{}", text),
+            &Atom::Trace(idx) => write!(f, "Trace({})", idx),
+        }
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl FromStr for Atom {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, ()> {
+        Ok(Atom::Step(text.to_string()))
+    }
+}
+
+/// A reason to stop, with the exit code to stop with.
+struct Failure {
+    code: i32,
+    messages: Vec<String>,
+}
+
+impl Failure {
+    fn usage(message: &str) -> Self {
+        Failure { code: 2, messages: vec![message.to_string(), USAGE.to_string()] }
+    }
+
+    fn error(messages: Vec<String>) -> Self {
+        Failure { code: 1, messages }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(failure) = run(args) {
+        for message in failure.messages {
+            eprintln!("{}", message);
+        }
+        process::exit(failure.code);
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), Failure> {
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (command.clone(), args.to_vec()),
+        None => return Err(Failure::usage("missing command")),
+    };
+    let mut options = Options::parse(args)?;
+    match &command[..] {
+        "check" => {
+            let (grammar, trees) = (options.file()?, options.file()?);
+            options.finish()?;
+            check(&load(&grammar)?, &trees)
+        }
+        "print-grammar" => {
+            let grammar = options.file()?;
+            options.finish()?;
+            let runtime = load(&grammar)?;
+            if options.lowering {
+                print!("{}", runtime.dump_lowering());
+            } else {
+                print!("{}", runtime);
+            }
+            Ok(())
+        }
+        "dot" => {
+            let grammar = options.file()?;
+            let trees = options.files.pop();
+            options.finish()?;
+            let runtime = load(&grammar)?;
+            match trees {
+                Some(trees) => print!("{}", runtime.to_dot(&read_paths(&trees)?)),
+                None => print!("{}", runtime.grammar_to_dot()),
+            }
+            Ok(())
+        }
+        "generate" => {
+            let grammar = options.file()?;
+            options.finish()?;
+            let runtime = load(&grammar)?;
+            let mut random = Random::new(options.seed);
+            for _ in 0 .. options.count {
+                let paths = runtime.generate(options.depth, |n| random.below(n), |name| Atom::Step(name.to_string()));
+                if paths.is_empty() {
+                    return Err(Failure::error(vec![format!("{}: the grammar never finishes a tree", grammar)]));
+                }
+                println!("{}", to_sexpr(&Tree::from_paths(&paths)));
+            }
+            Ok(())
+        }
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(Failure::usage(&format!("unknown command `{}`", command))),
+    }
+}
+
+struct Options {
+    files: Vec<String>,
+    lowering: bool,
+    count: usize,
+    depth: usize,
+    seed: u64,
+}
+
+impl Options {
+    fn parse(args: Vec<String>) -> Result<Self, Failure> {
+        let mut options = Options {
+            files: vec![],
+            lowering: false,
+            count: 1,
+            depth: 4,
+            seed: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0),
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match &arg[..] {
+                "--lowering" => options.lowering = true,
+                "--count" => options.count = number(&arg, args.next())? as usize,
+                "--depth" => options.depth = number(&arg, args.next())? as usize,
+                "--seed" => options.seed = number(&arg, args.next())?,
+                _ if arg.starts_with("--") => return Err(Failure::usage(&format!("unknown option `{}`", arg))),
+                _ => options.files.push(arg),
+            }
+        }
+        options.files.reverse();
+        Ok(options)
+    }
+
+    fn file(&mut self) -> Result<String, Failure> {
+        self.files.pop().ok_or_else(|| Failure::usage("missing file"))
+    }
+
+    fn finish(&self) -> Result<(), Failure> {
+        match self.files.last() {
+            Some(file) => Err(Failure::usage(&format!("unexpected argument `{}`", file))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn number(option: &str, value: Option<String>) -> Result<u64, Failure> {
+    match value.as_ref().and_then(|value| value.parse().ok()) {
+        Some(number) => Ok(number),
+        None => Err(Failure::usage(&format!("`{}` takes a number", option))),
+    }
+}
+
+fn load(grammar: &str) -> Result<NeighborhoodRuntime<Atom>, Failure> {
+    NeighborhoodRuntime::from_grammar_file(grammar, &Predicates::new()).map_err(|errors|
+        Failure::error(errors.iter().map(|error| format!("{}: {}", grammar, error)).collect())
+    )
+}
+
+fn read_paths(file: &str) -> Result<Vec<Vec<Atom>>, Failure> {
+    let text = fs::read_to_string(file).map_err(|error| Failure::error(vec![format!("{}: {}", file, error)]))?;
+    let trees = from_sexpr::<Atom>(&text).map_err(|error| Failure::error(vec![format!("{}: {}", file, error)]))?;
+    Ok(Tree::to_paths(&trees))
+}
+
+/// Checks the neighborhood in the file and reports each error with the path
+/// it was found in, marking the step it was found at.
+fn check(runtime: &NeighborhoodRuntime<Atom>, file: &str) -> Result<(), Failure> {
+    let paths = read_paths(file)?;
+    let errors = match runtime.check(&paths) {
+        Ok(()) => return Ok(()),
+        Err(errors) => errors,
+    };
+    let messages = errors.iter().map(|error| {
+        let (path, step) = location(error);
+        let mut line = String::new();
+        let mut marker = String::new();
+        for (i, atom) in paths[path].iter().enumerate() {
+            let text = atom.to_string();
+            if i == step {
+                marker = format!("{}{}", " ".repeat(line.chars().count()), "^".repeat(text.chars().count()));
+            }
+            line.push_str(&text);
+            line.push(' ');
+        }
+        if step == paths[path].len() {
+            marker = format!("{}^", " ".repeat(line.chars().count()));
+        }
+        format!("{}: {}\n    {}\n    {}", file, error, line.trim_end(), marker)
+    }).collect();
+    Err(Failure::error(messages))
+}
+
+fn location(error: &NeighborhoodError) -> (usize, usize) {
+    match error {
+        &NeighborhoodError::Validation(ref error) => (error.path, error.step),
+        &NeighborhoodError::Unification(ref error) => (error.path, error.step),
+        &NeighborhoodError::Ambiguity(ref error) => (error.path, error.step),
+        &NeighborhoodError::Arity(ref error) => (error.path, error.step),
+    }
+}
+
+/// A xorshift generator, which is enough to pick among the rules of a grammar.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Random((seed ^ 0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
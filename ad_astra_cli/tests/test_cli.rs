@@ -0,0 +1,79 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+const GRAMMAR: &str = "
+Expr<bool> ::= Bool | EqExpr for<T> (Expr<T> ^ Expr<T>) | LtExpr (Expr<isize> ^ Expr<isize>);
+Expr<isize> ::= Int;
+for<T> Expr<T> ::= IfExpr (Expr<bool> ^ Expr<T> ^ Expr<T>);
+@allow for<T> Expr<T>
+";
+
+fn write(name: &str, text: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("ad-astra-{}-{}", std::process::id(), name));
+    fs::write(&path, text).unwrap();
+    path
+}
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ad-astra")).args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_check() {
+    let grammar = write("check.ast", GRAMMAR);
+    let grammar = grammar.to_str().unwrap();
+    let valid = write("valid.sexpr", "(IfExpr (LtExpr Int(420) Int(130)) Int(1) Int(2))");
+    let output = run(&["check", grammar, valid.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let invalid = write("invalid.sexpr", "(IfExpr (LtExpr Int(420) Bool(true)) Int(1) Int(2))");
+    let output = run(&["check", grammar, invalid.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("path 1, step 4: unexpected Bool(true)"), "{}", stderr);
+    assert!(stderr.contains("    IfExpr Trace(0) LtExpr Trace(1) Bool(true)\n"), "{}", stderr);
+    assert!(stderr.contains("                                    ^^^^^^^^^^\n"), "{}", stderr);
+}
+
+#[test]
+fn test_print_grammar() {
+    let grammar = write("print.ast", GRAMMAR);
+    let output = run(&["print-grammar", grammar.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("for<T> Expr<T> ::= IfExpr (Expr<bool> ^ Expr<T> ^ Expr<T>);\n"));
+
+    let output = run(&["dot", grammar.to_str().unwrap()]);
+    assert!(stdout(&output).starts_with("digraph grammar {"));
+}
+
+#[test]
+fn test_generate() {
+    let grammar = write("generate.ast", GRAMMAR);
+    let grammar = grammar.to_str().unwrap();
+    let output = run(&["generate", "--count", "5", "--seed", "42", grammar]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let trees = stdout(&output);
+    assert_eq!(trees.lines().count(), 5);
+    for (i, line) in trees.lines().enumerate() {
+        let tree = write(&format!("generated-{}.sexpr", i), line);
+        let output = run(&["check", grammar, tree.to_str().unwrap()]);
+        assert!(output.status.success(), "{}: {}", line, stderr(&output));
+    }
+}
+
+#[test]
+fn test_usage() {
+    let output = run(&["frobnicate"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("unknown command `frobnicate`"));
+}
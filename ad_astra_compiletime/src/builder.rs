@@ -0,0 +1,397 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{Error, Ident, Pat, Result, Token, Type};
+
+use super::grammar::{NeighborhoodInput, Rhs, Stmt, StepVariants};
+use super::types::camel_case;
+
+/// A constructor, made from one alternative of a rule.
+struct Constructor {
+    name: Ident,
+    /// Parameters of `for<T>` within the alternative.
+    generics: Vec<Ident>,
+    /// Steps of the node, each either fixed, built from the values of the
+    /// wildcards in its pattern, or given as an argument and checked against
+    /// a pattern with literals.
+    labels: Vec<TokenStream>,
+    args: Vec<(Ident, TokenStream)>,
+    checks: Vec<TokenStream>,
+    children: Children,
+}
+
+/// What follows the steps of a node.
+enum Children {
+    /// Offshoots, given as arguments in order.
+    Offshoots(Vec<Ident>),
+    /// The rest of the node, given as an argument of another nonterminal.
+    Chain(Ident),
+}
+
+/// Emits a module `build` with a type per nonterminal, e.g. `Expr<T>` for
+/// `Expr<bool>` and `Expr<isize>`, and a constructor per alternative, e.g.
+/// `Expr::<bool>::lt_expr`. Arguments have the types of the offshoots and of
+/// the wildcards in patterns, so the trees built from allowed nonterminals are
+/// valid.
+pub fn builders(input: &NeighborhoodInput, variants: &StepVariants) -> Result<TokenStream> {
+    let step = &input.step_name;
+    let neighborhood = &input.neighborhood_name;
+    let mut generator = Generator { input, variants, methods: vec![] };
+    let mut types: Vec<(&Ident, bool)> = vec![];
+    let mut impls = vec![];
+    for stmt in &input.stmts {
+        match types.iter().find(|&&(name, _)| *name == stmt.lhs) {
+            Some(&(_, typed)) if typed != stmt.lhs_ty.is_some() => {
+                return Err(Error::new_spanned(&stmt.lhs, "builders expect a nonterminal to be typed in every rule or in none"));
+            }
+            Some(_) => {}
+            None => types.push((&stmt.lhs, stmt.lhs_ty.is_some())),
+        }
+        impls.push(generator.stmt_impl(stmt)?);
+    }
+    let definitions = types.iter().map(|&(name, typed)| if typed {
+        quote! {
+            #[derive(Clone, Debug, Eq, PartialEq)]
+            pub struct #name<T>(::ad_astra_runtime::Tree<#step>, ::std::marker::PhantomData<T>);
+
+            impl<T> ::ad_astra_runtime::IntoTree<#step> for #name<T> {
+                fn to_tree(&self) -> ::ad_astra_runtime::Tree<#step> {
+                    self.0.clone()
+                }
+            }
+        }
+    } else {
+        quote! {
+            #[derive(Clone, Debug, Eq, PartialEq)]
+            pub struct #name(::ad_astra_runtime::Tree<#step>);
+
+            impl ::ad_astra_runtime::IntoTree<#step> for #name {
+                fn to_tree(&self) -> ::ad_astra_runtime::Tree<#step> {
+                    self.0.clone()
+                }
+            }
+        }
+    });
+    let mut conversions = vec![];
+    for allow in &input.allow {
+        generator.conversions(allow, &mut vec![], &mut conversions)?;
+    }
+    let conversions = conversions.iter().map(|&(ref params, ref ty)| quote! {
+        impl<#(#params),*> From<#ty> for super::#neighborhood {
+            fn from(value: #ty) -> Self {
+                super::#neighborhood::from_tree(&[value.0])
+            }
+        }
+    });
+    Ok(quote! {
+        pub mod build {
+            use super::*;
+
+            #(#definitions)*
+            #(#impls)*
+            #(#conversions)*
+        }
+    })
+}
+
+struct Generator<'a> {
+    input: &'a NeighborhoodInput,
+    variants: &'a StepVariants,
+    /// Constructors so far, by nonterminal and type, or `None` for a type
+    /// left generic.
+    methods: Vec<(String, Option<String>, String)>,
+}
+
+/// Lower snake case of a name, e.g. `lt_expr` for `LtExpr`, followed by `_`
+/// for a keyword.
+pub fn snake_case(name: &str) -> Ident {
+    let mut snake = String::new();
+    for (i, c) in name.chars().filter(|&c| c.is_alphanumeric() || c == '_').enumerate() {
+        if c.is_uppercase() && i > 0 && !snake.ends_with('_') {
+            snake.push('_');
+        }
+        snake.extend(c.to_lowercase());
+    }
+    match syn::parse_str::<Ident>(&snake) {
+        Ok(ident) => ident,
+        Err(_) => format_ident!("{}_", snake),
+    }
+}
+
+impl<'a> Generator<'a> {
+    fn is_nonterminal(&self, name: &Ident) -> bool {
+        !self.variants.all.contains(&name.to_string())
+    }
+
+    fn step(&self) -> &'a Ident {
+        &self.input.step_name
+    }
+
+    fn stmt_impl(&mut self, stmt: &Stmt) -> Result<TokenStream> {
+        let lhs = &stmt.lhs;
+        let params = &stmt.params;
+        let (self_ty, ty_key) = match stmt.lhs_ty {
+            Some(ref ty) => {
+                let key = quote!(#ty).to_string();
+                let generic = params.iter().any(|param| *param == key);
+                (quote!(#lhs<#ty>), if generic { None } else { Some(key) })
+            }
+            None => (quote!(#lhs), Some(String::new())),
+        };
+        let mut constructors = vec![];
+        for alternative in alternatives(&stmt.rhs) {
+            let constructor = self.constructor(alternative)?;
+            let name = constructor.name.to_string();
+            let clash = self.methods.iter().any(|&(ref other_lhs, ref other_ty, ref other)| {
+                *other_lhs == lhs.to_string() && *other == name
+                    && (other_ty.is_none() || ty_key.is_none() || *other_ty == ty_key)
+            });
+            if clash {
+                return Err(Error::new_spanned(
+                    &constructor.name,
+                    format!("two alternatives of `{}` would have the same constructor `{}`", lhs, name),
+                ));
+            }
+            self.methods.push((lhs.to_string(), ty_key.clone(), name));
+            constructors.push(self.constructor_fn(&constructor, stmt.lhs_ty.is_some()));
+        }
+        Ok(quote! {
+            impl<#(#params),*> #self_ty {
+                #(#constructors)*
+            }
+        })
+    }
+
+    fn constructor(&self, alternative: &Rhs) -> Result<Constructor> {
+        let step = self.step();
+        let items = items(alternative);
+        let mut constructor = Constructor {
+            name: format_ident!("new"),
+            generics: vec![],
+            labels: vec![],
+            args: vec![],
+            checks: vec![],
+            children: Children::Offshoots(vec![]),
+        };
+        let mut name = None;
+        let mut rest = &items[..];
+        while let Some((&item, tail)) = rest.split_first() {
+            match item {
+                &Rhs::Symbol(ref variant) if !self.is_nonterminal(variant) => {
+                    constructor.labels.push(quote!(#step::#variant));
+                    name = name.or_else(|| Some(snake_case(&variant.to_string())));
+                }
+                &Rhs::Pattern(ref variant, ref fields) => {
+                    match self.pattern_step(variant, fields, &mut constructor.args) {
+                        Some(label) => constructor.labels.push(label),
+                        None => {
+                            let arg = format_ident!("step{}", constructor.checks.len());
+                            constructor.labels.push(quote!(#arg));
+                            // The step is only tested here, so the fields of
+                            // the pattern are bound by reference and left
+                            // unused.
+                            constructor.checks.push(quote! {
+                                #[allow(unused_variables)]
+                                match &#arg {
+                                    #step::#variant(#fields) => {}
+                                    _ => return None,
+                                }
+                            });
+                            constructor.args.push((arg, quote!(#step)));
+                        }
+                    }
+                    name = name.or_else(|| Some(snake_case(&variant.to_string())));
+                }
+                _ => break,
+            }
+            rest = tail;
+        }
+        match rest {
+            [] => {}
+            [child] => {
+                let child: &Rhs = child;
+                let (generics, child) = match child {
+                    &Rhs::ForAll(ref param, ref inner) => (vec![param.clone()], &**inner),
+                    child => (vec![], child),
+                };
+                constructor.generics = generics;
+                match child {
+                    &Rhs::Offshoots(_) if !constructor.labels.is_empty() => {
+                        constructor.children = Children::Offshoots(self.offshoots(child, &mut constructor.args)?);
+                    }
+                    _ => {
+                        let ty = self.child_type(child)?;
+                        name = name.or_else(|| Some(snake_case(&camel_case(&ty.to_string()))));
+                        let arg = format_ident!("value");
+                        constructor.args.push((arg.clone(), ty));
+                        constructor.children = Children::Chain(arg);
+                    }
+                }
+            }
+            _ => return Err(Error::new_spanned(step, "builders support alternatives of steps followed by offshoots")),
+        }
+        match name {
+            Some(name) => constructor.name = name,
+            None => return Err(Error::new_spanned(step, "an alternative without steps")),
+        }
+        Ok(constructor)
+    }
+
+    /// Builds the step of a pattern from an argument per wildcard, e.g.
+    /// `Step::Value(Value::Int(field0))` for `Value(Value::Int(_))`. Returns
+    /// `None` for a pattern with literals, or with fields of types outside
+    /// the module.
+    fn pattern_step(&self, variant: &Ident, fields: &TokenStream, args: &mut Vec<(Ident, TokenStream)>) -> Option<TokenStream> {
+        let step = self.step();
+        let pats = Punctuated::<Pat, Token![,]>::parse_terminated.parse2(fields.clone()).ok()?;
+        let mut wildcards = vec![];
+        let values = self.field_values(&variant.to_string(), &pats, args.len(), &mut wildcards)?;
+        args.extend(wildcards);
+        Some(quote!(#step::#variant(#(#values),*)))
+    }
+
+    /// The values of the fields of a variant, given by its path.
+    fn field_values(
+        &self,
+        path: &str,
+        pats: &Punctuated<Pat, Token![,]>,
+        first: usize,
+        wildcards: &mut Vec<(Ident, TokenStream)>,
+    ) -> Option<Vec<TokenStream>> {
+        let types = self.variants.field_types.get(path)?;
+        if types.len() != pats.len() {
+            return None;
+        }
+        pats.iter().zip(types).map(|(pat, ty)| self.field_value(pat, ty, first, wildcards)).collect()
+    }
+
+    fn field_value(&self, pat: &Pat, ty: &Type, first: usize, wildcards: &mut Vec<(Ident, TokenStream)>) -> Option<TokenStream> {
+        match pat {
+            &Pat::Wild(_) => {}
+            &Pat::Ident(ref binding) if binding.subpat.is_none() => {
+                // Capitalized names are unit variants or constants.
+                if binding.ident.to_string().starts_with(char::is_uppercase) {
+                    return None;
+                }
+            }
+            &Pat::TupleStruct(ref tuple) => {
+                let path = &tuple.path;
+                let key: Vec<String> = path.segments.iter().map(|segment| segment.ident.to_string()).collect();
+                let values = self.field_values(&key.join("::"), &tuple.pat.elems, first, wildcards)?;
+                return Some(quote!(#path(#(#values),*)));
+            }
+            _ => return None,
+        }
+        let arg = format_ident!("field{}", first + wildcards.len());
+        wildcards.push((arg.clone(), quote!(#ty)));
+        Some(quote!(#arg))
+    }
+
+    /// Adds an argument for each offshoot, and returns their names in order.
+    fn offshoots(&self, rhs: &Rhs, args: &mut Vec<(Ident, TokenStream)>) -> Result<Vec<Ident>> {
+        let offshoots = match rhs {
+            &Rhs::Offshoots(ref offshoots) => &offshoots[..],
+            other => ::std::slice::from_ref(other),
+        };
+        let mut names = vec![];
+        for (i, offshoot) in offshoots.iter().enumerate() {
+            let arg = format_ident!("offshoot{}", i);
+            args.push((arg.clone(), self.child_type(offshoot)?));
+            names.push(arg);
+        }
+        Ok(names)
+    }
+
+    /// The builder type of a nonterminal in an offshoot.
+    fn child_type(&self, rhs: &Rhs) -> Result<TokenStream> {
+        match rhs {
+            &Rhs::Apply(ref name, ref ty) => Ok(quote!(#name<#ty>)),
+            &Rhs::Symbol(ref name) if self.is_nonterminal(name) => Ok(quote!(#name)),
+            _ => Err(Error::new_spanned(self.step(), "builders expect a nonterminal in each offshoot")),
+        }
+    }
+
+    fn constructor_fn(&self, constructor: &Constructor, typed: bool) -> TokenStream {
+        let &Constructor { ref name, ref generics, ref labels, ref args, ref checks, ref children } = constructor;
+        let generics = if generics.is_empty() { quote!() } else { quote!(<#(#generics),*>) };
+        let params = args.iter().map(|&(ref arg, ref ty)| quote!(#arg: #ty));
+        let tree = match children {
+            &Children::Offshoots(ref offshoots) => {
+                let indices = 0 .. offshoots.len();
+                quote! {
+                    ::ad_astra_runtime::Tree {
+                        steps: vec![#(#labels),*],
+                        children: vec![#((#indices, #offshoots.0)),*],
+                    }
+                }
+            }
+            &Children::Chain(ref arg) => quote! {
+                ::ad_astra_runtime::Tree {
+                    steps: vec![#(#labels),*].into_iter().chain(#arg.0.steps).collect(),
+                    children: #arg.0.children,
+                }
+            },
+        };
+        let value = if typed {
+            quote!(Self(#tree, ::std::marker::PhantomData))
+        } else {
+            quote!(Self(#tree))
+        };
+        if checks.is_empty() {
+            quote! {
+                pub fn #name #generics(#(#params),*) -> Self {
+                    #value
+                }
+            }
+        } else {
+            quote! {
+                /// Returns `None` if a step does not match its pattern with
+                /// literals.
+                pub fn #name #generics(#(#params),*) -> Option<Self> {
+                    #(#checks)*
+                    Some(#value)
+                }
+            }
+        }
+    }
+
+    /// Finds the builder types of allowed nonterminals, which convert into the
+    /// neighborhood.
+    fn conversions(&self, allow: &'a Rhs, params: &mut Vec<&'a Ident>, out: &mut Vec<(Vec<Ident>, TokenStream)>) -> Result<()> {
+        match allow {
+            &Rhs::Alternatives(ref items) => {
+                for item in items {
+                    self.conversions(item, params, out)?;
+                }
+            }
+            &Rhs::ForAll(ref param, ref inner) => {
+                params.push(param);
+                self.conversions(inner, params, out)?;
+                params.pop();
+            }
+            &Rhs::Apply(ref name, ref ty) => {
+                let key = quote!(#ty).to_string();
+                let used = params.iter().filter(|param| param.to_string() == key).map(|&param| param.clone()).collect();
+                out.push((used, quote!(#name<#ty>)));
+            }
+            &Rhs::Symbol(ref name) if self.is_nonterminal(name) => out.push((vec![], quote!(#name))),
+            _ => return Err(Error::new_spanned(self.step(), "builders expect nonterminals in `@allow`")),
+        }
+        Ok(())
+    }
+}
+
+fn alternatives(rhs: &Rhs) -> Vec<&Rhs> {
+    match rhs {
+        &Rhs::Alternatives(ref alternatives) => alternatives.iter().collect(),
+        rhs => vec![rhs],
+    }
+}
+
+fn items(alternative: &Rhs) -> Vec<&Rhs> {
+    match alternative {
+        &Rhs::Sequence(ref items) => items.iter().collect(),
+        item => vec![item],
+    }
+}
@@ -0,0 +1,312 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::punctuated::Punctuated;
+use syn::{
+    Attribute, Data, DeriveInput, Error, Fields, GenericArgument, Ident, Member, PathArguments, Result, Token,
+    Type,
+};
+
+/// The target of the derive, from `#[neighborhood(Neighborhood, Step)]`.
+struct Target {
+    neighborhood: Ident,
+    step: Ident,
+}
+
+/// A variant, or a struct, which becomes a single step.
+struct Node {
+    /// The path that constructs the value, e.g. `Expr::IfExpr`.
+    constructor: TokenStream,
+    /// The variant of the step type with the same name.
+    step: Ident,
+    named: bool,
+    unit: bool,
+    fields: Vec<Field>,
+}
+
+struct Field {
+    member: Member,
+    kind: Kind,
+}
+
+enum Kind {
+    /// Kept in the step.
+    Payload,
+    /// Followed into an offshoot.
+    Offshoot(Type),
+    /// `Box<T>`, followed into an offshoot.
+    Boxed(Type),
+    /// `Vec<T>`, followed into consecutive offshoots. The flag is set for
+    /// `Vec<Box<T>>`.
+    List(Type, bool),
+}
+
+pub fn into_neighborhood(input: DeriveInput) -> Result<TokenStream> {
+    let target = target(&input)?;
+    let nodes = nodes(&input)?;
+    let ident = &input.ident;
+    let &Target { ref neighborhood, ref step } = &target;
+    let arms = nodes.iter().map(|node| node.to_tree_arm(step));
+    Ok(quote! {
+        impl ::ad_astra_runtime::IntoTree<#step> for #ident {
+            fn to_tree(&self) -> ::ad_astra_runtime::Tree<#step> {
+                match *self {
+                    #(#arms),*
+                }
+            }
+        }
+
+        impl From<#ident> for #neighborhood {
+            fn from(value: #ident) -> Self {
+                #neighborhood::from_tree(&[::ad_astra_runtime::IntoTree::to_tree(&value)])
+            }
+        }
+    })
+}
+
+pub fn from_neighborhood(input: DeriveInput) -> Result<TokenStream> {
+    let target = target(&input)?;
+    let nodes = nodes(&input)?;
+    let ident = &input.ident;
+    let &Target { ref neighborhood, ref step } = &target;
+    let blocks = nodes.iter().map(|node| node.from_tree_block(step));
+    let expected = ident.to_string();
+    Ok(quote! {
+        impl ::ad_astra_runtime::FromTree<#step> for #ident {
+            fn from_tree(tree: &::ad_astra_runtime::Tree<#step>) -> Result<Self, ::ad_astra_runtime::ConversionError> {
+                #(#blocks)*
+                Err(::ad_astra_runtime::ConversionError {
+                    found: format!("{:?}", tree.steps),
+                    expected: #expected.to_string(),
+                })
+            }
+        }
+
+        impl<'a> ::std::convert::TryFrom<&'a #neighborhood> for #ident {
+            type Error = ::ad_astra_runtime::ConversionError;
+
+            fn try_from(neighborhood: &'a #neighborhood) -> Result<Self, Self::Error> {
+                let trees = neighborhood.to_tree();
+                if trees.len() != 1 {
+                    return Err(::ad_astra_runtime::ConversionError {
+                        found: format!("{} trees", trees.len()),
+                        expected: #expected.to_string(),
+                    });
+                }
+                <#ident as ::ad_astra_runtime::FromTree<#step>>::from_tree(&trees[0])
+            }
+        }
+    })
+}
+
+fn neighborhood_args(attrs: &[Attribute]) -> Result<Option<Vec<Ident>>> {
+    match attrs.iter().find(|attr| attr.path.is_ident("neighborhood")) {
+        Some(attr) => {
+            let args = attr.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)?;
+            Ok(Some(args.into_iter().collect()))
+        }
+        None => Ok(None),
+    }
+}
+
+fn target(input: &DeriveInput) -> Result<Target> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics, "generic types cannot become neighborhoods"));
+    }
+    match neighborhood_args(&input.attrs)? {
+        Some(ref args) if args.len() == 2 => Ok(Target { neighborhood: args[0].clone(), step: args[1].clone() }),
+        _ => Err(Error::new_spanned(&input.ident, "expected `#[neighborhood(Neighborhood, Step)]`")),
+    }
+}
+
+fn nodes(input: &DeriveInput) -> Result<Vec<Node>> {
+    let ident = &input.ident;
+    match input.data {
+        Data::Enum(ref data) => data.variants.iter().map(|variant| {
+            let name = &variant.ident;
+            node(quote!(#ident::#name), name, &variant.fields)
+        }).collect(),
+        Data::Struct(ref data) => Ok(vec![node(quote!(#ident), ident, &data.fields)?]),
+        Data::Union(_) => Err(Error::new_spanned(ident, "unions cannot become neighborhoods")),
+    }
+}
+
+fn node(constructor: TokenStream, step: &Ident, fields: &Fields) -> Result<Node> {
+    let mut node = Node {
+        constructor,
+        step: step.clone(),
+        named: match *fields { Fields::Named(_) => true, _ => false },
+        unit: match *fields { Fields::Unit => true, _ => false },
+        fields: vec![],
+    };
+    for (i, field) in fields.iter().enumerate() {
+        let member = match field.ident {
+            Some(ref name) => Member::Named(name.clone()),
+            None => Member::Unnamed(i.into()),
+        };
+        let offshoot = match neighborhood_args(&field.attrs)? {
+            Some(ref args) if args.len() == 1 && args[0] == "offshoot" => true,
+            Some(_) => return Err(Error::new_spanned(field, "expected `#[neighborhood(offshoot)]`")),
+            None => false,
+        };
+        let kind = if let Some(inner) = type_argument(&field.ty, "Box") {
+            Kind::Boxed(inner)
+        } else if let Some(inner) = type_argument(&field.ty, "Vec") {
+            match type_argument(&inner, "Box") {
+                Some(boxed) => Kind::List(boxed, true),
+                None => Kind::List(inner, false),
+            }
+        } else if offshoot {
+            Kind::Offshoot(field.ty.clone())
+        } else {
+            Kind::Payload
+        };
+        node.fields.push(Field { member, kind });
+    }
+    if node.fields.iter().filter(|field| match field.kind { Kind::List(..) => true, _ => false }).count() > 1 {
+        return Err(Error::new_spanned(step, "at most one `Vec` can be followed per variant"));
+    }
+    Ok(node)
+}
+
+/// The `T` of `Box<T>` or `Vec<T>`.
+fn type_argument(ty: &Type, wrapper: &str) -> Option<Type> {
+    let path = match *ty {
+        Type::Path(ref ty) if ty.qself.is_none() => &ty.path,
+        _ => return None,
+    };
+    let segment = path.segments.last()?;
+    if segment.ident != wrapper {
+        return None;
+    }
+    match segment.arguments {
+        PathArguments::AngleBracketed(ref args) if args.args.len() == 1 => match args.args[0] {
+            GenericArgument::Type(ref inner) => Some(inner.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl Node {
+    fn binds(&self) -> Vec<Ident> {
+        (0 .. self.fields.len()).map(|i| format_ident!("f{}", i)).collect()
+    }
+
+    /// A pattern or an expression with the given fields of the node.
+    fn shape<F: Fn(&Field) -> bool>(&self, path: &TokenStream, values: &[TokenStream], keep: F) -> TokenStream {
+        let fields: Vec<_> = self.fields.iter().zip(values).filter(|&(field, _)| keep(field)).collect();
+        if self.unit || fields.is_empty() {
+            quote!(#path)
+        } else if self.named {
+            let members = fields.iter().map(|&(field, _)| &field.member);
+            let values = fields.iter().map(|&(_, value)| value);
+            quote!(#path { #(#members: #values),* })
+        } else {
+            let values = fields.iter().map(|&(_, value)| value);
+            quote!(#path(#(#values),*))
+        }
+    }
+
+    fn step_path(&self, step: &Ident) -> TokenStream {
+        let name = &self.step;
+        quote!(#step::#name)
+    }
+
+    fn to_tree_arm(&self, step: &Ident) -> TokenStream {
+        let binds = self.binds();
+        let refs: Vec<_> = binds.iter().map(|bind| quote!(ref #bind)).collect();
+        let pattern = self.shape(&self.constructor, &refs, |_| true);
+        let clones: Vec<_> = binds.iter().map(|bind| quote!(#bind.clone())).collect();
+        let step_value = self.shape(&self.step_path(step), &clones, is_payload);
+        let children = self.fields.iter().zip(&binds).filter_map(|(field, bind)| match field.kind {
+            Kind::Payload => None,
+            Kind::Offshoot(_) => Some(quote!(children.push(::ad_astra_runtime::IntoTree::to_tree(#bind));)),
+            Kind::Boxed(_) => Some(quote!(children.push(::ad_astra_runtime::IntoTree::to_tree(&**#bind));)),
+            Kind::List(_, false) => Some(quote! {
+                children.extend(#bind.iter().map(|item| ::ad_astra_runtime::IntoTree::to_tree(item)));
+            }),
+            Kind::List(_, true) => Some(quote! {
+                children.extend(#bind.iter().map(|item| ::ad_astra_runtime::IntoTree::to_tree(&**item)));
+            }),
+        });
+        quote! {
+            #pattern => {
+                #[allow(unused_mut)]
+                let mut children: Vec<::ad_astra_runtime::Tree<#step>> = vec![];
+                #(#children)*
+                ::ad_astra_runtime::Tree {
+                    steps: vec![#step_value],
+                    children: children.into_iter().enumerate().collect(),
+                }
+            }
+        }
+    }
+
+    fn from_tree_block(&self, step: &Ident) -> TokenStream {
+        let binds = self.binds();
+        let refs: Vec<_> = binds.iter().map(|bind| quote!(ref #bind)).collect();
+        let step_pattern = self.shape(&self.step_path(step), &refs, is_payload);
+        let payload: Vec<_> = self.fields.iter().zip(&binds).filter(|&(field, _)| is_payload(field))
+            .map(|(_, bind)| bind).collect();
+        let num_fixed = self.fields.iter().filter(|field| match field.kind {
+            Kind::Offshoot(_) | Kind::Boxed(_) => true,
+            _ => false,
+        }).count();
+        let has_list = self.fields.iter().any(|field| match field.kind { Kind::List(..) => true, _ => false });
+        let count_check = if has_list {
+            quote!(tree.children.len() < #num_fixed)
+        } else {
+            quote!(tree.children.len() != #num_fixed)
+        };
+        let offshoots = self.fields.iter().zip(&binds).filter_map(|(field, bind)| match field.kind {
+            Kind::Payload => None,
+            Kind::Offshoot(ref ty) => Some(quote! {
+                let #bind = <#ty as ::ad_astra_runtime::FromTree<#step>>::from_tree(children.next()?).ok()?;
+            }),
+            Kind::Boxed(ref ty) => Some(quote! {
+                let #bind = Box::new(<#ty as ::ad_astra_runtime::FromTree<#step>>::from_tree(children.next()?).ok()?);
+            }),
+            Kind::List(ref ty, boxed) => {
+                let item = if boxed {
+                    quote!(<#ty as ::ad_astra_runtime::FromTree<#step>>::from_tree(child).ok().map(Box::new))
+                } else {
+                    quote!(<#ty as ::ad_astra_runtime::FromTree<#step>>::from_tree(child).ok())
+                };
+                Some(quote! {
+                    let #bind = children.by_ref().take(tree.children.len() - #num_fixed)
+                        .map(|child| #item)
+                        .collect::<Option<Vec<_>>>()?;
+                })
+            }
+        });
+        let values: Vec<_> = binds.iter().map(|bind| quote!(#bind)).collect();
+        let value = self.shape(&self.constructor, &values, |_| true);
+        quote! {
+            if let Some(value) = (|| -> Option<Self> {
+                if tree.steps.len() != 1 || #count_check {
+                    return None;
+                }
+                if tree.children.iter().enumerate().any(|(i, &(idx, _))| idx != i) {
+                    return None;
+                }
+                let (#(#payload,)*) = match tree.steps[0] {
+                    #step_pattern => (#(#payload.clone(),)*),
+                    _ => return None,
+                };
+                #[allow(unused_mut, unused_variables)]
+                let mut children = tree.children.iter().map(|&(_, ref child)| child);
+                #(#offshoots)*
+                Some(#value)
+            })() {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+fn is_payload(field: &Field) -> bool {
+    match field.kind {
+        Kind::Payload => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::parse::ParseStream;
+use syn::{parenthesized, token, Error, Ident, Result, Token, Type};
+
+/// The contents of `neighborhood!`.
+pub struct NeighborhoodInput {
+    pub neighborhood_name: Ident,
+    pub path_name: Ident,
+    pub step_name: Ident,
+    pub stmts: Vec<Stmt>,
+    pub allow: Vec<Rhs>,
+}
+
+/// A rule such as `for<T> Expr<T> ::= IfExpr (Expr<bool> ^ Expr<T> ^ Expr<T>)`.
+pub struct Stmt {
+    /// The parentheses around the rule, which errors in it point at.
+    pub span: Span,
+    pub params: Vec<Ident>,
+    pub lhs: Ident,
+    pub lhs_ty: Option<Type>,
+    pub rhs: Rhs,
+}
+
+pub enum Rhs {
+    Alternatives(Vec<Rhs>),
+    Sequence(Vec<Rhs>),
+    Offshoots(Vec<Rhs>),
+    ForAll(Ident, Box<Rhs>),
+    Repeat(Box<Rhs>),
+    /// A nonterminal applied to a type, e.g. `Expr<bool>`.
+    Apply(Ident, Type),
+    /// A nonterminal, or a variant of the step type.
+    Symbol(Ident),
+    /// A variant of the step type with a pattern for its fields.
+    Pattern(Ident, TokenStream),
+}
+
+/// Variants of the step type. A variant with fields followed by parentheses
+/// is read as a pattern.
+pub struct StepVariants {
+    pub all: BTreeSet<String>,
+    pub with_fields: BTreeSet<String>,
+    /// Types of the fields of tuple variants in the module, by path, e.g.
+    /// `Value` for a variant of the step enum and `Value::Int` for others.
+    pub field_types: BTreeMap<String, Vec<Type>>,
+}
+
+/// Reads the `Neighborhood, Path, Step =>` header and returns the rest.
+pub fn parse_header(input: ParseStream) -> Result<(Ident, Ident, Ident, TokenStream)> {
+    let neighborhood_name = input.parse()?;
+    input.parse::<Token![,]>()?;
+    let path_name = input.parse()?;
+    input.parse::<Token![,]>()?;
+    let step_name = input.parse()?;
+    input.parse::<Token![=>]>()?;
+    Ok((neighborhood_name, path_name, step_name, input.parse()?))
+}
+
+pub fn parse_rules(input: ParseStream, variants: &StepVariants) -> Result<(Vec<Stmt>, Vec<Rhs>)> {
+    let mut stmts = vec![];
+    let mut allow = vec![];
+    while !input.is_empty() {
+        let content;
+        let paren = parenthesized!(content in input);
+        if content.peek(Token![@]) {
+            content.parse::<Token![@]>()?;
+            let keyword: Ident = content.parse()?;
+            if keyword == "allow" {
+                allow.push(parse_alternatives(&content, variants)?);
+                continue;
+            } else if keyword != "stmt" {
+                return Err(Error::new(keyword.span(), "expected `@stmt` or `@allow`"));
+            }
+        }
+        stmts.push(parse_stmt(&content, paren.span, variants)?);
+    }
+    Ok((stmts, allow))
+}
+
+fn parse_stmt(input: ParseStream, span: Span, variants: &StepVariants) -> Result<Stmt> {
+    let mut params = vec![];
+    while input.peek(Token![for]) {
+        params.push(parse_for(input)?);
+    }
+    let lhs = input.parse()?;
+    let lhs_ty = if input.peek(Token![<]) {
+        Some(parse_type_arg(input)?)
+    } else {
+        None
+    };
+    input.parse::<Token![::]>()?;
+    input.parse::<Token![=]>()?;
+    let rhs = parse_alternatives(input, variants)?;
+    Ok(Stmt { span, params, lhs, lhs_ty, rhs })
+}
+
+fn parse_for(input: ParseStream) -> Result<Ident> {
+    input.parse::<Token![for]>()?;
+    input.parse::<Token![<]>()?;
+    let param = input.parse()?;
+    input.parse::<Token![>]>()?;
+    Ok(param)
+}
+
+fn parse_type_arg(input: ParseStream) -> Result<Type> {
+    input.parse::<Token![<]>()?;
+    let ty = input.parse()?;
+    input.parse::<Token![>]>()?;
+    Ok(ty)
+}
+
+fn parse_alternatives(input: ParseStream, variants: &StepVariants) -> Result<Rhs> {
+    let mut alternatives = vec![parse_sequence(input, variants)?];
+    while input.peek(Token![|]) {
+        input.parse::<Token![|]>()?;
+        alternatives.push(parse_sequence(input, variants)?);
+    }
+    Ok(single_or(alternatives, Rhs::Alternatives))
+}
+
+fn parse_sequence(input: ParseStream, variants: &StepVariants) -> Result<Rhs> {
+    let mut items = vec![];
+    while !input.is_empty() && !input.peek(Token![|]) {
+        items.push(parse_offshoots(input, variants)?);
+    }
+    if items.is_empty() {
+        return Err(input.error("expected a step or a nonterminal"));
+    }
+    Ok(single_or(items, Rhs::Sequence))
+}
+
+fn parse_offshoots(input: ParseStream, variants: &StepVariants) -> Result<Rhs> {
+    let mut offshoots = vec![parse_repeat(input, variants)?];
+    while input.peek(Token![^]) {
+        input.parse::<Token![^]>()?;
+        offshoots.push(parse_repeat(input, variants)?);
+    }
+    Ok(single_or(offshoots, Rhs::Offshoots))
+}
+
+fn parse_repeat(input: ParseStream, variants: &StepVariants) -> Result<Rhs> {
+    let mut rhs = parse_atom(input, variants)?;
+    while input.peek(Token![*]) {
+        input.parse::<Token![*]>()?;
+        rhs = Rhs::Repeat(Box::new(rhs));
+    }
+    Ok(rhs)
+}
+
+fn parse_atom(input: ParseStream, variants: &StepVariants) -> Result<Rhs> {
+    if input.peek(Token![for]) {
+        let param = parse_for(input)?;
+        let inner = parse_atom(input, variants)?;
+        Ok(Rhs::ForAll(param, Box::new(inner)))
+    } else if input.peek(token::Paren) {
+        let content;
+        parenthesized!(content in input);
+        parse_alternatives(&content, variants)
+    } else {
+        let name: Ident = input.parse()?;
+        if input.peek(Token![<]) {
+            let ty = parse_type_arg(input)?;
+            Ok(Rhs::Apply(name, ty))
+        } else if input.peek(token::Paren) && variants.with_fields.contains(&name.to_string()) {
+            let content;
+            parenthesized!(content in input);
+            Ok(Rhs::Pattern(name, content.parse()?))
+        } else {
+            Ok(Rhs::Symbol(name))
+        }
+    }
+}
+
+fn single_or(mut items: Vec<Rhs>, many: fn(Vec<Rhs>) -> Rhs) -> Rhs {
+    if items.len() == 1 {
+        items.pop().unwrap()
+    } else {
+        many(items)
+    }
+}
+
+impl Rhs {
+    /// The expression in the syntax of `rule!`, as a single token tree.
+    pub fn to_rule_tokens(&self, step: &Ident) -> TokenStream {
+        match self {
+            &Rhs::Alternatives(ref items) => {
+                let items = items.iter().map(|rhs| rhs.to_rule_tokens(step));
+                quote!((#(#items)|*))
+            }
+            &Rhs::Sequence(ref items) => {
+                let items = items.iter().map(|rhs| rhs.to_rule_tokens(step));
+                quote!((#(#items)*))
+            }
+            &Rhs::Offshoots(ref items) => {
+                let items = items.iter().map(|rhs| rhs.to_rule_tokens(step));
+                quote!((#(#items)^*))
+            }
+            &Rhs::ForAll(ref param, ref inner) => {
+                let inner = inner.to_rule_tokens(step);
+                quote!((for<#param> #inner))
+            }
+            &Rhs::Repeat(ref inner) => {
+                let inner = inner.to_rule_tokens(step);
+                quote!((#inner *))
+            }
+            &Rhs::Apply(ref name, ref ty) => quote!((#name<#ty>)),
+            &Rhs::Symbol(ref name) => quote!(#name),
+            &Rhs::Pattern(ref name, ref fields) => quote!((@m #step::#name(#fields))),
+        }
+    }
+}
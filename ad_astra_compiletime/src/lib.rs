@@ -1,99 +1,237 @@
 extern crate proc_macro;
 
-use std::mem;
+mod builder;
+mod derive;
+mod grammar;
+mod literal;
+mod lowering;
+mod types;
+mod visit;
+
+use std::collections::BTreeMap;
 
 use proc_macro::TokenStream;
-use quote::{format_ident, quote};
-use std::collections::HashMap;
-use syn::{
-    parse::{Parse, ParseStream, Result as SynResult},
-    parse_macro_input, Expr, Ident, ItemFn, ItemMod, Item, ItemStruct, Lit, Meta, Token, Type,
-    ItemMacro, ItemEnum,
-};
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::parse::{Parse, ParseStream, Parser, Result as SynResult};
+use syn::{parse_macro_input, DeriveInput, Error, Fields, Ident, Item, ItemMod};
+
+use self::grammar::{NeighborhoodInput, StepVariants};
 
-enum DefinitionKind {
-    AstNeighborhoodStruct,
-    AstPath,
-    AstStep,
-    NeighborhoodDefinition,
+/// Options of `#[ast(...)]`.
+struct AstArgs {
+    /// Emit typed enums that mirror the productions.
+    types: bool,
+    /// Emit typed constructors in a module `build`.
+    builder: bool,
+    /// Emit the traits `Visitor` and `Fold`.
+    visit: bool,
 }
 
-struct Stmt {
-    // ...
+impl Parse for AstArgs {
+    fn parse(input: ParseStream) -> SynResult<Self> {
+        let mut args = AstArgs { types: false, builder: false, visit: false };
+        while !input.is_empty() {
+            let arg: Ident = input.parse()?;
+            if arg == "types" {
+                args.types = true;
+            } else if arg == "builder" {
+                args.builder = true;
+            } else if arg == "visit" {
+                args.visit = true;
+            } else {
+                return Err(Error::new(arg.span(), "unknown option; expected `types`, `builder` or `visit`"));
+            }
+            if !input.is_empty() {
+                input.parse::<syn::Token![,]>()?;
+            }
+        }
+        Ok(args)
+    }
 }
 
-struct NeighborhoodInput {
-    neighborhood_name: Ident,
-    path_name: Ident,
-    step_name: Ident,
-    stmts: Vec<Stmt>,
-    allow: Vec<TokenStream>,
+#[proc_macro_attribute]
+pub fn ast(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AstArgs);
+    let input = parse_macro_input!(item as ItemMod);
+    match expand(args, input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
 }
 
-fn classify_and_strip(item_content: &[Item]) -> Vec<(DefinitionKind, Item)> {
-    let is_macro = |name| {
-        |item| match item { &Item::Macro(ItemMacro { ref mac, .. }) => mac.path.is_ident(name), _ => false }
-    };
-    let is_definition = |name| {
-        |item| match item { &Item::Enum { ref mac, .. } => mac.path.is_ident(name), _ => false }
-    };
+/// Expands the `neighborhood_lit!` that `#[ast]` defines. Not meant to be
+/// called directly.
+#[doc(hidden)]
+#[proc_macro]
+pub fn __neighborhood_lit(item: TokenStream) -> TokenStream {
+    match literal::neighborhood_lit.parse(item) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
 
-    let item_macro = item_content.iter().find(is_macro("neighborhood")).unwrap().clone();
-    let macro_tokens = item_macro.tokens;
-    parse_macro_input!(macro_tokens as NeighborhoodInput);
+/// Flattens values into a neighborhood. Each variant becomes a step of the same
+/// name. Fields of type `Box<T>`, `Vec<T>` or marked `#[neighborhood(offshoot)]`
+/// become offshoots, and the rest are kept in the step.
+#[proc_macro_derive(IntoNeighborhood, attributes(neighborhood))]
+pub fn into_neighborhood(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    match derive::into_neighborhood(input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
 
-    // let 
+/// Rebuilds values from a neighborhood, the inverse of `IntoNeighborhood`.
+#[proc_macro_derive(FromNeighborhood, attributes(neighborhood))]
+pub fn from_neighborhood(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as DeriveInput);
+    match derive::from_neighborhood(input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
 
-    for item in item_content {
+fn expand(args: AstArgs, mut input: ItemMod) -> SynResult<TokenStream2> {
+    let items = match input.content.take() {
+        Some((_, items)) => items,
+        None => return Err(Error::new_spanned(&input, "#[ast] expects a module with a body")),
+    };
+    let mut content = vec![];
+    let mut neighborhood = None;
+    for item in items {
         match item {
-            &Item::Enum(ref mut item_enum) => {
-                let ast_parts: Vec<_> = item_enum.attrs.iter().filter_map(|attr|
-                    attr.path.segments.first.unwrap_or(None,
-                        |segment| if segment.ident == "ast_path" {
-                            Some(DefinitionKind::AstPath)
-                        } else if segment.ident == "ast_step" {
-                            Some(DefinitionKind::AstStep)
-                        }
-                    )
-                ).collect();
-                assert_eq!(ast_parts.len(), 1);
-            }
-            &Item::Struct(ref mut item_struct) => {
-                let ast_parts: Vec<_> = item_enum.attrs.iter().filter_map(|attr|
-                    attr.path.segments.first.unwrap_or(None,
-                        |segment| if segment.ident == "ast_neighborhood" {
-                            Some(DefinitionKind::AstNeighborhoodStruct)
-                        }
-                    )
-                ).collect();
-                assert_eq!(ast_parts.len(), 1);
+            Item::Macro(ref item_macro) if item_macro.mac.path.is_ident("neighborhood") => {
+                neighborhood = Some(item_macro.mac.tokens.clone());
             }
-            &Item::Macro { ref mut ident, ref mut mac, .. } => {
-                if ident == "neighborhood" {
+            item => content.push(item),
+        }
+    }
+    let neighborhood = match neighborhood {
+        Some(tokens) => tokens,
+        None => return Err(Error::new_spanned(&input.ident, "missing `neighborhood!` in #[ast] module")),
+    };
+    let (neighborhood_name, path_name, step_name, rules) = grammar::parse_header.parse2(neighborhood.clone())?;
+    let (variants, trace) = strip_step(&mut content, &step_name)?;
+    let (stmts, allow) = (|input: ParseStream| grammar::parse_rules(input, &variants)).parse2(rules)?;
+    let input_grammar = NeighborhoodInput { neighborhood_name, path_name, step_name, stmts, allow };
 
-                } else {
-                    panic!();
+    let tables_name = Ident::new("__AD_ASTRA_TABLES", Span::call_site());
+    let runtime = runtime_ast(&input_grammar, &trace, &variants, &tables_name)?;
+    let tables = lowering::static_tables(&input_grammar, &tables_name)?;
+    let literal = literal_macro(&neighborhood, &trace, &variants);
+    let types = if args.types {
+        types::typed_enums(&input_grammar, &variants)?
+    } else {
+        quote!()
+    };
+    let builders = if args.builder {
+        builder::builders(&input_grammar, &variants)?
+    } else {
+        quote!()
+    };
+    let visitors = if args.visit {
+        visit::visitors(&input_grammar, &variants)?
+    } else {
+        quote!()
+    };
+    let ItemMod { attrs, vis, ident, .. } = input;
+    Ok(quote! {
+        #(#attrs)*
+        #[macro_use]
+        #vis mod #ident {
+            #(#content)*
+            #tables
+            #runtime
+            #literal
+            #types
+            #builders
+            #visitors
+        }
+    })
+}
+
+/// Finds the step enum, collects its variants, and removes the `#[trace]`
+/// attribute from the trace variant.
+fn strip_step(content: &mut [Item], step_name: &Ident) -> SynResult<(StepVariants, Ident)> {
+    let mut field_types = BTreeMap::new();
+    for item in content.iter() {
+        if let &Item::Enum(ref item_enum) = item {
+            for variant in &item_enum.variants {
+                if let Fields::Unnamed(ref fields) = variant.fields {
+                    let types: Vec<_> = fields.unnamed.iter().map(|field| field.ty.clone()).collect();
+                    if item_enum.ident == *step_name {
+                        field_types.insert(variant.ident.to_string(), types.clone());
+                    }
+                    field_types.insert(format!("{}::{}", item_enum.ident, variant.ident), types);
                 }
             }
-            _ => {}
         }
     }
+    let item_enum = content.iter_mut().filter_map(|item| match item {
+        &mut Item::Enum(ref mut item_enum) if item_enum.ident == *step_name => Some(item_enum),
+        _ => None,
+    }).next();
+    let item_enum = match item_enum {
+        Some(item_enum) => item_enum,
+        None => return Err(Error::new_spanned(step_name, "missing the step enum in #[ast] module")),
+    };
+    let mut variants = StepVariants { all: Default::default(), with_fields: Default::default(), field_types };
+    let mut trace = None;
+    for variant in &mut item_enum.variants {
+        let len = variant.attrs.len();
+        variant.attrs.retain(|attr| !attr.path.is_ident("trace"));
+        if variant.attrs.len() != len {
+            trace = Some(variant.ident.clone());
+        }
+        variants.all.insert(variant.ident.to_string());
+        match variant.fields {
+            Fields::Unit => {}
+            _ => {
+                variants.with_fields.insert(variant.ident.to_string());
+            }
+        }
+    }
+    match trace {
+        Some(trace) => Ok((variants, trace)),
+        None => Err(Error::new_spanned(&item_enum.ident, "the step enum needs a #[trace] variant")),
+    }
 }
 
-#[proc_macro_attribute]
-pub fn ast(args: TokenStream, item: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(item as ItemMod);
-    input.content.as_mut().map(|&mut (_, ref mut item_content)| {
-        let mut new_content = classify_and_strip(&item_content[..]);
-        mem::replace(item_content, new_content);
+/// Lowers the grammar onto the run-time `ast!` macro, which builds the
+/// processed grammar from the static tables. The rules are left out, since
+/// the tables already hold them.
+fn runtime_ast(input: &NeighborhoodInput, trace: &Ident, variants: &StepVariants, tables: &Ident) -> SynResult<TokenStream2> {
+    let &NeighborhoodInput { ref neighborhood_name, ref path_name, ref step_name, .. } = input;
+    let start = match input.allow.len() {
+        1 => input.allow[0].to_rule_tokens(step_name),
+        0 => return Err(Error::new_spanned(neighborhood_name, "missing `(@allow ...)`")),
+        _ => return Err(Error::new_spanned(neighborhood_name, "expected a single `(@allow ...)`")),
+    };
+    let variants = variants.all.iter().map(|name| Ident::new(name, Span::call_site()));
+    Ok(quote! {
+        ::ad_astra_runtime::ast! {
+            #[tables(#tables)]
+            #[variants(#(#variants),*)]
+            #neighborhood_name, #path_name, #step_name, #[trace] #trace, (#start) =>
+        }
     })
-    TokenStream::from(input)
 }
 
-// #[cfg(test)]
-// mod tests {
-//     #[test]
-//     fn it_works() {
-//         assert_eq!(2 + 2, 4);
-//     }
-// }
+/// Defines `neighborhood_lit!`, which passes the grammar along with the literal
+/// so that it can be checked while it expands.
+fn literal_macro(neighborhood: &TokenStream2, trace: &Ident, variants: &StepVariants) -> TokenStream2 {
+    let all = variants.all.iter().map(|name| Ident::new(name, Span::call_site()));
+    let with_fields = variants.with_fields.iter().map(|name| Ident::new(name, Span::call_site()));
+    quote! {
+        #[allow(unused_macros)]
+        macro_rules! neighborhood_lit {
+            ($($paths:tt)*) => {
+                ::ad_astra::__neighborhood_lit! {
+                    { #neighborhood } #trace { #(#all)* } { #(#with_fields)* } $($paths)*
+                }
+            };
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use ad_astra_runtime::{Matcher, NeighborhoodError, Traced};
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::parse::ParseStream;
+use syn::punctuated::Punctuated;
+use syn::{braced, bracketed, Error, Expr, Ident, Pat, Result, Token};
+
+use crate::grammar::{self, NeighborhoodInput, StepVariants};
+use crate::lowering;
+
+/// A step of a literal, known from its expression.
+#[derive(Clone, Eq, PartialEq)]
+enum LitStep {
+    Step {
+        variant: String,
+        /// The fields of a variant such as `Value(Value::Int(1))`.
+        fields: Option<Vec<Expr>>,
+    },
+    Trace(usize),
+}
+
+impl Traced for LitStep {
+    fn trace(&self) -> Option<usize> {
+        match self {
+            &LitStep::Trace(idx) => Some(idx),
+            _ => None,
+        }
+    }
+
+    fn from_trace(idx: usize) -> Self {
+        LitStep::Trace(idx)
+    }
+
+    fn variant_name(&self) -> Option<&str> {
+        match self {
+            &LitStep::Step { ref variant, .. } => Some(variant),
+            &LitStep::Trace(_) => None,
+        }
+    }
+}
+
+/// Written the way the step was, so that the variant comes first.
+impl fmt::Debug for LitStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &LitStep::Step { ref variant, fields: None } => write!(f, "{}", variant),
+            &LitStep::Step { ref variant, fields: Some(ref fields) } => {
+                let fields: Vec<String> = fields.iter().map(|field| field.to_token_stream().to_string()).collect();
+                write!(f, "{}({})", variant, fields.join(", "))
+            }
+            &LitStep::Trace(idx) => write!(f, "Trace({})", idx),
+        }
+    }
+}
+
+/// Expands `__neighborhood_lit!`, which `neighborhood_lit!` calls with the
+/// grammar of its `#[ast]` module before the literal:
+///
+/// ```text
+/// { Neighborhood, Path, Step => rules... } Trace { variants... } { variants with fields... }
+/// [Step::IfExpr, Step::Trace(0), ...], [...]
+/// ```
+///
+/// When every step is a variant of the step type written out, the literal is
+/// checked against the grammar, and errors point at the offending steps.
+pub fn neighborhood_lit(input: ParseStream) -> Result<TokenStream> {
+    let grammar_tokens;
+    braced!(grammar_tokens in input);
+    let (neighborhood_name, path_name, step_name, rules) = grammar::parse_header(&grammar_tokens)?;
+    let trace: Ident = input.parse()?;
+    let all = parse_names(input)?;
+    let with_fields = parse_names(input)?;
+    // Literals build no constructors, so field types are left out.
+    let variants = StepVariants { all, with_fields, field_types: Default::default() };
+    let (stmts, allow) = syn::parse::Parser::parse2(|input: ParseStream| grammar::parse_rules(input, &variants), rules)?;
+    let grammar = NeighborhoodInput { neighborhood_name, path_name, step_name, stmts, allow };
+
+    let mut paths: Vec<Vec<Expr>> = vec![];
+    while !input.is_empty() {
+        let content;
+        bracketed!(content in input);
+        paths.push(Punctuated::<Expr, Token![,]>::parse_terminated(&content)?.into_iter().collect());
+        if !input.is_empty() {
+            input.parse::<Token![,]>()?;
+        }
+    }
+
+    let errors = check(&grammar, &trace, &variants, &paths)?;
+    let &NeighborhoodInput { ref neighborhood_name, ref path_name, .. } = &grammar;
+    let errors = errors.map(|error| error.to_compile_error());
+    Ok(quote! {
+        {
+            #errors
+            #neighborhood_name::with_paths(vec![
+                #(#path_name::with_steps(vec![#(#paths),*])),*
+            ])
+        }
+    })
+}
+
+fn parse_names(input: ParseStream) -> Result<BTreeSet<String>> {
+    let content;
+    braced!(content in input);
+    let mut names = BTreeSet::new();
+    while !content.is_empty() {
+        names.insert(content.parse::<Ident>()?.to_string());
+    }
+    Ok(names)
+}
+
+/// Checks the literal against the grammar, unless some step is not written out.
+fn check(grammar: &NeighborhoodInput, trace: &Ident, variants: &StepVariants, paths: &[Vec<Expr>]) -> Result<Option<Error>> {
+    let steps: Option<Vec<Vec<LitStep>>> = paths.iter().map(|path|
+        path.iter().map(|expr| lit_step(expr, trace, variants)).collect()
+    ).collect();
+    let steps = match steps {
+        Some(steps) => steps,
+        None => return Ok(None),
+    };
+    let step_name = grammar.step_name.clone();
+    let pattern = |name: &Ident, fields: &TokenStream| {
+        let pat = syn::parse2::<Pat>(quote!(#step_name::#name(#fields))).ok();
+        let variant = name.to_string();
+        Matcher::match_pattern(&format!("{}::{}({})", step_name, name, fields), move |step: &LitStep| match step {
+            &LitStep::Step { variant: ref found, ref fields } if *found == variant => match (&pat, fields) {
+                (&Some(Pat::TupleStruct(ref pat)), &Some(ref fields)) => elems_match(&pat.pat.elems, fields),
+                _ => true,
+            },
+            _ => false,
+        })
+    };
+    let runtime = lowering::runtime(grammar, &pattern)?;
+    let errors = match runtime.check(&steps) {
+        Ok(()) => return Ok(None),
+        Err(errors) => errors,
+    };
+    let mut combined: Option<Error> = None;
+    for error in errors {
+        let (path, step) = location(&error);
+        let error = match paths[path].get(step) {
+            Some(expr) => Error::new_spanned(expr, format!("invalid neighborhood: {}", error)),
+            None => Error::new_spanned(&paths[path].last(), format!("invalid neighborhood: {}", error)),
+        };
+        match combined {
+            Some(ref mut combined) => combined.combine(error),
+            None => combined = Some(error),
+        }
+    }
+    Ok(combined)
+}
+
+fn location(error: &NeighborhoodError) -> (usize, usize) {
+    match error {
+        &NeighborhoodError::Validation(ref error) => (error.path, error.step),
+        &NeighborhoodError::Unification(ref error) => (error.path, error.step),
+        &NeighborhoodError::Ambiguity(ref error) => (error.path, error.step),
+        &NeighborhoodError::Arity(ref error) => (error.path, error.step),
+    }
+}
+
+/// The step an expression such as `Step::LtExpr` or `Step::Trace(0)` makes,
+/// if it is written out.
+fn lit_step(expr: &Expr, trace: &Ident, variants: &StepVariants) -> Option<LitStep> {
+    let (path, fields) = match expr {
+        &Expr::Path(ref expr) => (&expr.path, None),
+        &Expr::Call(ref call) => match *call.func {
+            Expr::Path(ref func) => (&func.path, Some(call.args.iter().cloned().collect::<Vec<_>>())),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let variant = path.segments.last()?.ident.clone();
+    if !variants.all.contains(&variant.to_string()) {
+        return None;
+    }
+    if variant == *trace {
+        return match fields.as_ref().map(|fields| &fields[..]) {
+            Some(&[Expr::Lit(ref lit)]) => match lit.lit {
+                syn::Lit::Int(ref int) => int.base10_parse().ok().map(LitStep::Trace),
+                _ => None,
+            },
+            _ => None,
+        };
+    }
+    Some(LitStep::Step { variant: variant.to_string(), fields })
+}
+
+fn elems_match(pats: &Punctuated<Pat, Token![,]>, exprs: &[Expr]) -> bool {
+    let mut exprs = exprs.iter();
+    for pat in pats {
+        if let &Pat::Rest(_) = pat {
+            return true;
+        }
+        match exprs.next() {
+            Some(expr) if pat_matches(pat, expr) => {}
+            _ => return false,
+        }
+    }
+    exprs.next().is_none()
+}
+
+/// Whether the pattern matches the value of the expression. Variables, calls
+/// and other expressions that cannot be decided from the tokens are taken to
+/// match.
+fn pat_matches(pat: &Pat, expr: &Expr) -> bool {
+    match (pat, expr) {
+        (&Pat::Or(ref pat), _) => pat.cases.iter().any(|pat| pat_matches(pat, expr)),
+        (&Pat::Lit(ref pat), &Expr::Lit(_)) | (&Pat::Lit(ref pat), &Expr::Unary(_)) => {
+            pat.expr.to_token_stream().to_string() == expr.to_token_stream().to_string()
+        }
+        (&Pat::Path(ref pat), &Expr::Path(ref expr)) if is_variant(&expr.path) => same_variant(&pat.path, &expr.path),
+        (&Pat::Path(_), &Expr::Call(ref call)) | (&Pat::TupleStruct(_), &Expr::Call(ref call)) => match *call.func {
+            Expr::Path(ref func) if is_variant(&func.path) => match pat {
+                &Pat::TupleStruct(ref pat) => {
+                    let args: Vec<Expr> = call.args.iter().cloned().collect();
+                    same_variant(&pat.path, &func.path) && elems_match(&pat.pat.elems, &args)
+                }
+                _ => false,
+            },
+            _ => true,
+        },
+        (&Pat::TupleStruct(_), &Expr::Path(ref expr)) => !is_variant(&expr.path),
+        (&Pat::Tuple(ref pat), &Expr::Tuple(ref tuple)) => {
+            let elems: Vec<Expr> = tuple.elems.iter().cloned().collect();
+            elems_match(&pat.elems, &elems)
+        }
+        (&Pat::Reference(ref pat), &Expr::Reference(ref expr)) => pat_matches(&pat.pat, &expr.expr),
+        _ => true,
+    }
+}
+
+/// Whether the path names a variant, as in `Value::Int`, rather than a
+/// variable.
+fn is_variant(path: &syn::Path) -> bool {
+    path.segments.len() > 1
+}
+
+/// Paths are compared by their last segment, since either may be imported.
+fn same_variant(pat: &syn::Path, expr: &syn::Path) -> bool {
+    match (pat.segments.last(), expr.segments.last()) {
+        (Some(pat), Some(expr)) => pat.ident == expr.ident,
+        _ => false,
+    }
+}
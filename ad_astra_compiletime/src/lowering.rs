@@ -0,0 +1,127 @@
+use ad_astra_runtime::{GrammarError, Matcher, Neighborhood, NeighborhoodRuntime};
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{Error, Ident, Result};
+
+use crate::grammar::{NeighborhoodInput, Rhs, Stmt};
+
+/// Processes the grammar while the macro expands and emits the result as a
+/// static `Tables` named `name`. Patterns never match here; each is named by
+/// its source, which the tables turn back into a matching function.
+pub fn static_tables(input: &NeighborhoodInput, name: &Ident) -> Result<TokenStream> {
+    let step_name = &input.step_name;
+    let never = |name: &Ident, fields: &TokenStream| {
+        Matcher::match_pattern(&quote!(#step_name::#name(#fields)).to_string(), |_: &()| false)
+    };
+    let runtime = runtime(input, &never)?;
+    let tables: TokenStream = runtime.tables_source().parse()
+        .map_err(|_| Error::new_spanned(&input.neighborhood_name, "cannot embed the processed grammar"))?;
+    Ok(quote! {
+        #[doc(hidden)]
+        static #name: ::ad_astra_runtime::Tables<#step_name> = #tables;
+    })
+}
+
+/// Builds and processes the grammar the same way `ast!` would, with patterns
+/// made by `pattern` from the variant and the pattern for its fields. Errors in
+/// the grammar become compile errors.
+pub fn runtime<T, P>(input: &NeighborhoodInput, pattern: &P) -> Result<NeighborhoodRuntime<T>>
+    where P: Fn(&Ident, &TokenStream) -> Matcher<T>
+{
+    let mut runtime = NeighborhoodRuntime::new();
+    for stmt in &input.stmts {
+        runtime.rule(stmt.to_neighborhood(pattern));
+    }
+    for allow in &input.allow {
+        runtime.allow(allow.to_neighborhood(pattern));
+    }
+    if let Err(errors) = runtime.process_rules() {
+        let mut errors = errors.into_iter().map(|error| {
+            let message = format!("invalid grammar: {}", error);
+            match error_stmt(input, &error) {
+                Some(stmt) => Error::new(stmt.span, message),
+                None => Error::new_spanned(&input.neighborhood_name, message),
+            }
+        });
+        let mut combined = errors.next().unwrap();
+        for error in errors {
+            combined.combine(error);
+        }
+        return Err(combined);
+    }
+    Ok(runtime)
+}
+
+/// The rule that a grammar error is about. An undefined nonterminal is blamed
+/// on the first rule that uses it.
+fn error_stmt<'a>(input: &'a NeighborhoodInput, error: &GrammarError) -> Option<&'a Stmt> {
+    match error {
+        &GrammarError::Unproductive { rule, .. } |
+        &GrammarError::Unreachable { rule, .. } |
+        &GrammarError::NeverInstantiated { rule, .. } |
+        &GrammarError::UnusedParam { rule, .. } => input.stmts.get(rule),
+        &GrammarError::Undefined { ref name } => input.stmts.iter().find(|stmt| stmt.rhs.uses(name)),
+        _ => None,
+    }
+}
+
+impl Stmt {
+    /// The rule built with the same combinators as `rule!`.
+    fn to_neighborhood<T, P>(&self, pattern: &P) -> Neighborhood<T>
+        where P: Fn(&Ident, &TokenStream) -> Matcher<T>
+    {
+        let lhs = match self.lhs_ty {
+            Some(ref ty) => Matcher::apply(&self.lhs.to_string(), &ty.to_token_stream().to_string()),
+            None => Matcher::variant(&self.lhs.to_string()),
+        };
+        let mut rule = lhs.into_neighborhood().lhs_then(self.rhs.to_neighborhood(pattern));
+        for param in self.params.iter().rev() {
+            rule = rule.introduce_param(&param.to_string());
+        }
+        rule
+    }
+}
+
+impl Rhs {
+    /// The expression built with the same combinators as `rule!`.
+    fn to_neighborhood<T, P>(&self, pattern: &P) -> Neighborhood<T>
+        where P: Fn(&Ident, &TokenStream) -> Matcher<T>
+    {
+        let fold = |items: &[Rhs], combine: fn(Neighborhood<T>, Neighborhood<T>) -> Neighborhood<T>| {
+            let mut items = items.iter().map(|rhs| rhs.to_neighborhood(pattern));
+            let first = items.next().expect("empty list of expressions");
+            items.fold(first, combine)
+        };
+        match self {
+            &Rhs::Alternatives(ref items) => fold(items, Neighborhood::or),
+            &Rhs::Sequence(ref items) => fold(items, Neighborhood::then),
+            &Rhs::Offshoots(ref items) => fold(items, Neighborhood::offshoot),
+            &Rhs::ForAll(ref param, ref inner) => inner.to_neighborhood(pattern).introduce_param(&param.to_string()),
+            &Rhs::Repeat(ref inner) => inner.to_neighborhood(pattern).repeat(),
+            &Rhs::Apply(ref name, ref ty) => {
+                Matcher::apply(&name.to_string(), &ty.to_token_stream().to_string()).into_neighborhood()
+            }
+            &Rhs::Symbol(ref name) => Matcher::variant(&name.to_string()).into_neighborhood(),
+            &Rhs::Pattern(ref name, ref fields) => pattern(name, fields).into_neighborhood(),
+        }
+    }
+
+    /// Whether the expression refers to the nonterminal, named the way the
+    /// runtime names it.
+    fn uses(&self, nonterminal: &str) -> bool {
+        match self {
+            &Rhs::Alternatives(ref items) | &Rhs::Sequence(ref items) | &Rhs::Offshoots(ref items) => {
+                items.iter().any(|rhs| rhs.uses(nonterminal))
+            }
+            &Rhs::ForAll(_, ref inner) | &Rhs::Repeat(ref inner) => inner.uses(nonterminal),
+            &Rhs::Apply(ref name, ref ty) => {
+                match Matcher::<()>::apply(&name.to_string(), &ty.to_token_stream().to_string()) {
+                    Matcher::ParamApply { ref rhs, ref ty_param } => format!("{}<{}>", rhs, ty_param) == nonterminal,
+                    _ => false,
+                }
+            }
+            &Rhs::Symbol(ref name) => name == nonterminal,
+            &Rhs::Pattern(..) => false,
+        }
+    }
+}
@@ -0,0 +1,555 @@
+use std::collections::BTreeSet;
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, ToTokens};
+use syn::{Error, Ident, Result, Type};
+
+use super::grammar::{NeighborhoodInput, Rhs, Stmt, StepVariants};
+
+/// A variant of a typed enum, built from one alternative of a rule.
+struct Variant {
+    name: Ident,
+    labels: Vec<Label>,
+    children: Children,
+}
+
+/// A step of a node.
+enum Label {
+    /// A unit variant of the step type.
+    Unit(Ident),
+    /// A variant with fields, kept in the typed enum.
+    Pattern(Ident, TokenStream),
+}
+
+/// What follows the steps of a node.
+enum Children {
+    None,
+    /// Offshoots, with the typed enum of each.
+    Offshoots(Vec<TokenStream>),
+    /// Offshoots under `for<T>`, held by a helper enum with one variant per type.
+    Exists(Ident),
+    /// The whole node, derived from another nonterminal.
+    Chain(TokenStream),
+}
+
+/// Type arguments of a rule, either concrete or left generic.
+type Subst<'a> = Vec<(String, Option<&'a Type>)>;
+
+struct Generator<'a> {
+    input: &'a NeighborhoodInput,
+    variants: &'a StepVariants,
+    type_args: Vec<&'a Type>,
+    /// Concrete nonterminals, e.g. `Expr<bool>`, each with its own enum.
+    instances: Vec<(&'a Ident, Option<&'a Type>)>,
+    helpers: Vec<TokenStream>,
+}
+
+/// Emits an enum per concrete nonterminal, e.g. `ExprBool` for `Expr<bool>`,
+/// and a generic enum per `for<T>` rule, e.g. `Expr<T>`. Each converts to and
+/// from trees of steps, and the concrete ones to and from the neighborhood.
+pub fn typed_enums(input: &NeighborhoodInput, variants: &StepVariants) -> Result<TokenStream> {
+    let mut generator = Generator {
+        input,
+        variants,
+        type_args: vec![],
+        instances: vec![],
+        helpers: vec![],
+    };
+    generator.collect();
+    let mut enums = vec![];
+    for i in 0 .. generator.instances.len() {
+        enums.push(generator.instance_enum(i)?);
+    }
+    for stmt in input.stmts.iter().filter(|stmt| !stmt.params.is_empty()) {
+        enums.push(generator.generic_enum(stmt)?);
+    }
+    let helpers = &generator.helpers;
+    Ok(quote! {
+        #(#enums)*
+        #(#helpers)*
+    })
+}
+
+fn type_key(ty: &Type) -> String {
+    quote!(#ty).to_string()
+}
+
+fn is_param(ty: &Type, params: &BTreeSet<String>) -> bool {
+    params.contains(&type_key(ty))
+}
+
+/// Upper camel case of the words in a name, e.g. `StaticStr` for `&'static str`.
+pub fn camel_case(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric()).map(|word| {
+        let mut chars = word.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    }).collect()
+}
+
+fn instance_ident(name: &Ident, ty: Option<&Type>) -> Ident {
+    match ty {
+        Some(ty) => format_ident!("{}{}", camel_case(&name.to_string()), camel_case(&type_key(ty))),
+        None => format_ident!("{}", camel_case(&name.to_string())),
+    }
+}
+
+impl<'a> Generator<'a> {
+    fn collect(&mut self) {
+        let input = self.input;
+        for stmt in &input.stmts {
+            let mut params: BTreeSet<String> = stmt.params.iter().map(|param| param.to_string()).collect();
+            if stmt.params.is_empty() {
+                self.add_instance(&stmt.lhs, stmt.lhs_ty.as_ref());
+            }
+            self.collect_rhs(&stmt.rhs, &mut params);
+        }
+    }
+
+    fn collect_rhs(&mut self, rhs: &'a Rhs, params: &mut BTreeSet<String>) {
+        match rhs {
+            &Rhs::Alternatives(ref items) | &Rhs::Sequence(ref items) | &Rhs::Offshoots(ref items) => {
+                for item in items {
+                    self.collect_rhs(item, params);
+                }
+            }
+            &Rhs::ForAll(ref param, ref inner) => {
+                params.insert(param.to_string());
+                self.collect_rhs(inner, params);
+            }
+            &Rhs::Repeat(ref inner) => self.collect_rhs(inner, params),
+            &Rhs::Apply(ref name, ref ty) => {
+                if !is_param(ty, params) {
+                    if self.type_args.iter().all(|&arg| type_key(arg) != type_key(ty)) {
+                        self.type_args.push(ty);
+                    }
+                    self.add_instance(name, Some(ty));
+                }
+            }
+            &Rhs::Symbol(ref name) => {
+                if self.is_nonterminal(name) {
+                    self.add_instance(name, None);
+                }
+            }
+            &Rhs::Pattern(..) => {}
+        }
+    }
+
+    fn add_instance(&mut self, name: &'a Ident, ty: Option<&'a Type>) {
+        let ident = instance_ident(name, ty);
+        if self.instances.iter().all(|&(name, ty)| instance_ident(name, ty) != ident) {
+            self.instances.push((name, ty));
+        }
+    }
+
+    fn has_instance(&self, ident: &Ident) -> bool {
+        self.instances.iter().any(|&(name, ty)| instance_ident(name, ty) == *ident)
+    }
+
+    fn is_nonterminal(&self, name: &Ident) -> bool {
+        !self.variants.all.contains(&name.to_string())
+    }
+
+    fn step(&self) -> &'a Ident {
+        &self.input.step_name
+    }
+
+    fn instance_enum(&mut self, i: usize) -> Result<TokenStream> {
+        let (name, ty) = self.instances[i];
+        let ident = instance_ident(name, ty);
+        let input = self.input;
+        let mut variants = vec![];
+        for stmt in &input.stmts {
+            let same_ty = match (ty, stmt.lhs_ty.as_ref()) {
+                (Some(ty), Some(lhs_ty)) => type_key(ty) == type_key(lhs_ty),
+                (None, None) => true,
+                _ => false,
+            };
+            if stmt.lhs != *name {
+                continue;
+            }
+            if stmt.params.is_empty() && same_ty {
+                for alternative in alternatives(&stmt.rhs) {
+                    variants.push(self.variant(&ident, alternative, &vec![])?);
+                }
+            } else if !stmt.params.is_empty() {
+                let generic = instance_ident(&stmt.lhs, None);
+                let variant_name = match alternatives(&stmt.rhs)[..] {
+                    [alternative] => self.variant_name(alternative).unwrap_or_else(|| generic.clone()),
+                    _ => generic.clone(),
+                };
+                variants.push(Variant {
+                    name: variant_name,
+                    labels: vec![],
+                    children: Children::Chain(quote!(#generic<#ident>)),
+                });
+            }
+        }
+        let expected = match ty {
+            Some(ty) => format!("{}<{}>", name, type_key(ty)),
+            None => name.to_string(),
+        };
+        let definition = self.definition(&ident, quote!(), &variants, &expected);
+        let step = self.step();
+        let neighborhood = &input.neighborhood_name;
+        Ok(quote! {
+            #definition
+
+            impl<'a> ::std::convert::TryFrom<&'a #neighborhood> for #ident {
+                type Error = ::ad_astra_runtime::ConversionError;
+
+                fn try_from(neighborhood: &'a #neighborhood) -> Result<Self, Self::Error> {
+                    let trees = neighborhood.to_tree();
+                    if trees.len() != 1 {
+                        return Err(::ad_astra_runtime::ConversionError {
+                            found: format!("{} trees", trees.len()),
+                            expected: #expected.to_string(),
+                        });
+                    }
+                    <#ident as ::ad_astra_runtime::FromTree<#step>>::from_tree(&trees[0])
+                }
+            }
+
+            impl From<#ident> for #neighborhood {
+                fn from(value: #ident) -> Self {
+                    #neighborhood::from_tree(&[::ad_astra_runtime::IntoTree::to_tree(&value)])
+                }
+            }
+        })
+    }
+
+    fn generic_enum(&mut self, stmt: &'a Stmt) -> Result<TokenStream> {
+        let param = match stmt.params[..] {
+            [ref param] => param,
+            _ => return Err(Error::new_spanned(&stmt.lhs, "typed enums support one type parameter per rule")),
+        };
+        let ident = instance_ident(&stmt.lhs, None);
+        let subst = vec![(param.to_string(), None)];
+        let mut variants = vec![];
+        for alternative in alternatives(&stmt.rhs) {
+            variants.push(self.variant(&ident, alternative, &subst)?);
+        }
+        let expected = format!("{}<{}>", stmt.lhs, param);
+        Ok(self.definition(&ident, quote!(#param), &variants, &expected))
+    }
+
+    fn variant_name(&self, alternative: &Rhs) -> Option<Ident> {
+        items(alternative).into_iter().filter_map(|item| match item {
+            &Rhs::Symbol(ref name) if !self.is_nonterminal(name) => Some(name.clone()),
+            &Rhs::Pattern(ref name, _) => Some(name.clone()),
+            _ => None,
+        }).next()
+    }
+
+    fn variant(&mut self, ident: &Ident, alternative: &'a Rhs, subst: &Subst<'a>) -> Result<Variant> {
+        let items = items(alternative);
+        let mut labels = vec![];
+        let mut rest = &items[..];
+        while let Some((&item, tail)) = rest.split_first() {
+            match item {
+                &Rhs::Symbol(ref name) if !self.is_nonterminal(name) => labels.push(Label::Unit(name.clone())),
+                &Rhs::Pattern(ref name, ref fields) => labels.push(Label::Pattern(name.clone(), fields.clone())),
+                _ => break,
+            }
+            rest = tail;
+        }
+        let name = self.variant_name(alternative);
+        let children = match (rest, labels.is_empty()) {
+            ([], _) => Children::None,
+            ([&Rhs::Offshoots(ref offshoots)], _) => {
+                let mut types = vec![];
+                for offshoot in offshoots {
+                    types.push(self.child_type(offshoot, subst)?);
+                }
+                Children::Offshoots(types)
+            }
+            ([&Rhs::ForAll(ref param, ref inner)], _) if subst.is_empty() => {
+                let helper = format_ident!("{}{}", ident, name.as_ref().unwrap_or(ident));
+                self.exists(&helper, param, inner)?;
+                Children::Exists(helper)
+            }
+            ([child], true) => Children::Chain(self.child_type(child, subst)?),
+            _ => return Err(Error::new_spanned(
+                self.step(),
+                "typed enums support alternatives of steps followed by offshoots",
+            )),
+        };
+        let name = match (name, &children) {
+            (Some(name), _) => name,
+            (None, &Children::Chain(ref ty)) => format_ident!("{}", camel_case(&ty.to_string())),
+            (None, _) => return Err(Error::new_spanned(self.step(), "an alternative without steps")),
+        };
+        Ok(Variant { name, labels, children })
+    }
+
+    /// The typed enum of a nonterminal in an offshoot.
+    fn child_type(&self, rhs: &Rhs, subst: &Subst) -> Result<TokenStream> {
+        match rhs {
+            &Rhs::Apply(ref name, ref ty) => {
+                match subst.iter().find(|&&(ref param, _)| *param == type_key(ty)) {
+                    Some(&(_, Some(arg))) => Ok(instance_ident(name, Some(arg)).into_token_stream()),
+                    Some(&(_, None)) => Ok(quote!(#ty)),
+                    None => Ok(instance_ident(name, Some(ty)).into_token_stream()),
+                }
+            }
+            &Rhs::Symbol(ref name) if self.is_nonterminal(name) => {
+                Ok(instance_ident(name, None).into_token_stream())
+            }
+            _ => Err(Error::new_spanned(self.step(), "typed enums expect a nonterminal in each offshoot")),
+        }
+    }
+
+    /// Emits the helper enum for offshoots under `for<T>`, with a variant for
+    /// each type argument whose nonterminals are all defined.
+    fn exists(&mut self, helper: &Ident, param: &'a Ident, inner: &'a Rhs) -> Result<()> {
+        let offshoots = match inner {
+            &Rhs::Offshoots(ref offshoots) => &offshoots[..],
+            other => ::std::slice::from_ref(other),
+        };
+        let step = self.step();
+        let mut variants = vec![];
+        for &arg in &self.type_args {
+            let subst = vec![(param.to_string(), Some(arg))];
+            let mut types = vec![];
+            for offshoot in offshoots {
+                types.push(self.child_type(offshoot, &subst)?);
+            }
+            if types.iter().all(|ty| self.has_instance(&format_ident!("{}", ty.to_string()))) {
+                variants.push((format_ident!("{}", camel_case(&type_key(arg))), types));
+            }
+        }
+        let declarations = variants.iter().map(|&(ref name, ref types)| quote!(#name(#(Box<#types>),*)));
+        let to_children = variants.iter().map(|&(ref name, ref types)| {
+            let binds: Vec<_> = (0 .. types.len()).map(|i| format_ident!("f{}", i)).collect();
+            let indices = 0 .. types.len();
+            quote! {
+                #helper::#name(#(ref #binds),*) => vec![
+                    #((#indices, ::ad_astra_runtime::IntoTree::to_tree(&**#binds))),*
+                ]
+            }
+        });
+        let from_children = variants.iter().map(|&(ref name, ref types)| {
+            let check = children_check(quote!(children), types.len());
+            let indices = 0 .. types.len();
+            quote! {
+                if let Some(value) = (|| -> Option<#helper> {
+                    #check
+                    Some(#helper::#name(#(
+                        Box::new(<#types as ::ad_astra_runtime::FromTree<#step>>::from_tree(&children[#indices].1).ok()?)
+                    ),*))
+                })() {
+                    return Some(value);
+                }
+            }
+        });
+        self.helpers.push(quote! {
+            #[derive(Clone, Debug, Eq, PartialEq)]
+            pub enum #helper {
+                #(#declarations),*
+            }
+
+            impl #helper {
+                pub fn children(&self) -> Vec<(usize, ::ad_astra_runtime::Tree<#step>)> {
+                    match *self {
+                        #(#to_children),*
+                    }
+                }
+
+                pub fn from_children(children: &[(usize, ::ad_astra_runtime::Tree<#step>)]) -> Option<Self> {
+                    #(#from_children)*
+                    None
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// The enum and its `IntoTree` and `FromTree` impls.
+    fn definition(&self, ident: &Ident, param: TokenStream, variants: &[Variant], expected: &str) -> TokenStream {
+        let step = self.step();
+        let generics = if param.is_empty() { quote!() } else { quote!(<#param>) };
+        let impl_generics = if param.is_empty() {
+            quote!()
+        } else {
+            quote!(<#param: ::ad_astra_runtime::IntoTree<#step> + ::ad_astra_runtime::FromTree<#step>>)
+        };
+        let declarations = variants.iter().map(|variant| {
+            let name = &variant.name;
+            let fields = variant.field_types(step);
+            if fields.is_empty() {
+                quote!(#name)
+            } else {
+                quote!(#name(#(#fields),*))
+            }
+        });
+        let to_tree = variants.iter().map(|variant| variant.to_tree_arm(ident, step));
+        let from_tree = variants.iter().map(|variant| variant.from_tree_block(ident, &generics, step));
+        quote! {
+            #[derive(Clone, Debug, Eq, PartialEq)]
+            pub enum #ident #generics {
+                #(#declarations),*
+            }
+
+            impl #impl_generics ::ad_astra_runtime::IntoTree<#step> for #ident #generics {
+                fn to_tree(&self) -> ::ad_astra_runtime::Tree<#step> {
+                    match *self {
+                        #(#to_tree),*
+                    }
+                }
+            }
+
+            impl #impl_generics ::ad_astra_runtime::FromTree<#step> for #ident #generics {
+                fn from_tree(tree: &::ad_astra_runtime::Tree<#step>) -> Result<Self, ::ad_astra_runtime::ConversionError> {
+                    #(#from_tree)*
+                    Err(::ad_astra_runtime::ConversionError {
+                        found: format!("{:?}", tree.steps),
+                        expected: #expected.to_string(),
+                    })
+                }
+            }
+        }
+    }
+}
+
+impl Variant {
+    fn field_types(&self, step: &Ident) -> Vec<TokenStream> {
+        let mut fields = vec![];
+        for label in &self.labels {
+            if let &Label::Pattern(..) = label {
+                fields.push(quote!(#step));
+            }
+        }
+        match self.children {
+            Children::None => {}
+            Children::Offshoots(ref types) => fields.extend(types.iter().map(|ty| quote!(Box<#ty>))),
+            Children::Exists(ref helper) => fields.push(quote!(#helper)),
+            Children::Chain(ref ty) => fields.push(quote!(Box<#ty>)),
+        }
+        fields
+    }
+
+    fn to_tree_arm(&self, ident: &Ident, step: &Ident) -> TokenStream {
+        let name = &self.name;
+        let binds: Vec<_> = (0 .. self.field_types(step).len()).map(|i| format_ident!("f{}", i)).collect();
+        let pattern = if binds.is_empty() {
+            quote!(#ident::#name)
+        } else {
+            quote!(#ident::#name(#(ref #binds),*))
+        };
+        let mut binds = binds.iter();
+        let steps: Vec<_> = self.labels.iter().map(|label| match label {
+            &Label::Unit(ref variant) => quote!(#step::#variant),
+            &Label::Pattern(..) => {
+                let bind = binds.next().unwrap();
+                quote!(#bind.clone())
+            }
+        }).collect();
+        let children = match self.children {
+            Children::None => quote!(vec![]),
+            Children::Offshoots(ref types) => {
+                let indices = 0 .. types.len();
+                let binds = binds.by_ref().take(types.len());
+                quote!(vec![#((#indices, ::ad_astra_runtime::IntoTree::to_tree(&**#binds))),*])
+            }
+            Children::Exists(_) => {
+                let bind = binds.next().unwrap();
+                quote!(#bind.children())
+            }
+            Children::Chain(_) => {
+                let bind = binds.next().unwrap();
+                return quote!(#pattern => ::ad_astra_runtime::IntoTree::to_tree(&**#bind));
+            }
+        };
+        quote! {
+            #pattern => ::ad_astra_runtime::Tree {
+                steps: vec![#(#steps),*],
+                children: #children,
+            }
+        }
+    }
+
+    fn from_tree_block(&self, ident: &Ident, generics: &TokenStream, step: &Ident) -> TokenStream {
+        let name = &self.name;
+        let num_labels = self.labels.len();
+        let mut checks = vec![];
+        let mut fields = vec![];
+        for (i, label) in self.labels.iter().enumerate() {
+            match label {
+                &Label::Unit(ref variant) => checks.push(quote! {
+                    match tree.steps[#i] {
+                        #step::#variant => {}
+                        _ => return None,
+                    }
+                }),
+                &Label::Pattern(ref variant, ref pattern) => {
+                    checks.push(quote! {
+                        match tree.steps[#i] {
+                            #step::#variant(#pattern) => {}
+                            _ => return None,
+                        }
+                    });
+                    fields.push(quote!(tree.steps[#i].clone()));
+                }
+            }
+        }
+        match self.children {
+            Children::None => checks.push(children_check(quote!(tree.children), 0)),
+            Children::Offshoots(ref types) => {
+                checks.push(children_check(quote!(tree.children), types.len()));
+                fields.extend(types.iter().enumerate().map(|(i, ty)| quote! {
+                    Box::new(<#ty as ::ad_astra_runtime::FromTree<#step>>::from_tree(&tree.children[#i].1).ok()?)
+                }));
+            }
+            Children::Exists(ref helper) => fields.push(quote!(#helper::from_children(&tree.children[..])?)),
+            Children::Chain(ref ty) => {
+                return quote! {
+                    if let Ok(value) = <#ty as ::ad_astra_runtime::FromTree<#step>>::from_tree(tree) {
+                        return Ok(#ident::#name(Box::new(value)));
+                    }
+                };
+            }
+        }
+        let value = if fields.is_empty() {
+            quote!(#ident::#name)
+        } else {
+            quote!(#ident::#name(#(#fields),*))
+        };
+        quote! {
+            if let Some(value) = (|| -> Option<#ident #generics> {
+                if tree.steps.len() != #num_labels {
+                    return None;
+                }
+                #(#checks)*
+                Some(#value)
+            })() {
+                return Ok(value);
+            }
+        }
+    }
+}
+
+/// Checks that the children are exactly the offshoots `0 .. len`, in order.
+fn children_check(children: TokenStream, len: usize) -> TokenStream {
+    let indices = 0 .. len;
+    quote! {
+        if #children.len() != #len #(|| #children[#indices].0 != #indices)* {
+            return None;
+        }
+    }
+}
+
+fn alternatives(rhs: &Rhs) -> Vec<&Rhs> {
+    match rhs {
+        &Rhs::Alternatives(ref alternatives) => alternatives.iter().collect(),
+        rhs => vec![rhs],
+    }
+}
+
+fn items(alternative: &Rhs) -> Vec<&Rhs> {
+    match alternative {
+        &Rhs::Sequence(ref items) => items.iter().collect(),
+        item => vec![item],
+    }
+}
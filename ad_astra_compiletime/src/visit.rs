@@ -0,0 +1,202 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Error, Ident, Result};
+
+use super::builder::snake_case;
+use super::grammar::{NeighborhoodInput, Rhs, StepVariants};
+use super::types::camel_case;
+
+/// One alternative of a rule, with the name of its methods.
+struct Production {
+    rule: usize,
+    alternative: Option<usize>,
+    name: Ident,
+}
+
+/// Emits the traits `Visitor` and `Fold`, with a method per production, e.g.
+/// `visit_lt_expr` and `fold_lt_expr`. Their defaults recurse into offshoots
+/// in the order of their traces.
+pub fn visitors(input: &NeighborhoodInput, variants: &StepVariants) -> Result<TokenStream> {
+    for stmt in &input.stmts {
+        let alternatives = match stmt.rhs {
+            Rhs::Alternatives(ref alternatives) => alternatives.iter().collect(),
+            ref rhs => vec![rhs],
+        };
+        if let Some(reason) = alternatives.into_iter().filter_map(|rhs| hidden_alternative(rhs, variants)).next() {
+            return Err(Error::new(stmt.span, format!("#[ast(visit)] cannot dispatch this rule: {}", reason)));
+        }
+    }
+    let step = &input.step_name;
+    let productions = productions(input, variants);
+    let arms = |prefix: &str, offshoots: &Ident| {
+        let arms: Vec<TokenStream> = productions.iter().map(|production| {
+            let &Production { rule, ref name, .. } = production;
+            let method = format_ident!("{}_{}", prefix, name);
+            let alternative = match production.alternative {
+                Some(alternative) => quote!(Some(#alternative)),
+                None => quote!(None),
+            };
+            quote!((#rule, #alternative) => self.#method(node))
+        }).collect();
+        quote! {
+            match (node.rule, node.alternative) {
+                #(#arms,)*
+                _ => self.#offshoots(node),
+            }
+        }
+    };
+    let visit_offshoots = format_ident!("visit_offshoots");
+    let fold_offshoots = format_ident!("fold_offshoots");
+    let visit = arms("visit", &visit_offshoots);
+    let fold = arms("fold", &fold_offshoots);
+    let visit_methods = productions.iter().map(|production| {
+        let method = format_ident!("visit_{}", production.name);
+        quote! {
+            fn #method(&mut self, node: &::ad_astra_runtime::Node<#step>) {
+                self.visit_offshoots(node)
+            }
+        }
+    });
+    let fold_methods = productions.iter().map(|production| {
+        let method = format_ident!("fold_{}", production.name);
+        quote! {
+            fn #method(&mut self, node: ::ad_astra_runtime::Node<#step>) -> ::ad_astra_runtime::Node<#step> {
+                self.fold_offshoots(node)
+            }
+        }
+    });
+    Ok(quote! {
+        /// Walks the nodes of a neighborhood, with a method per production.
+        pub trait Visitor {
+            fn visit(&mut self, node: &::ad_astra_runtime::Node<#step>) {
+                #visit
+            }
+
+            /// Visits the inner node, then the offshoots in the order of their
+            /// traces.
+            fn visit_offshoots(&mut self, node: &::ad_astra_runtime::Node<#step>) {
+                if let Some(ref inner) = node.inner {
+                    self.visit(inner);
+                }
+                for &(_, ref offshoot) in &node.offshoots {
+                    self.visit(offshoot);
+                }
+            }
+
+            #(#visit_methods)*
+        }
+
+        /// Rebuilds the nodes of a neighborhood, with a method per production.
+        pub trait Fold {
+            fn fold(&mut self, node: ::ad_astra_runtime::Node<#step>) -> ::ad_astra_runtime::Node<#step> {
+                #fold
+            }
+
+            /// Folds the inner node, then the offshoots in the order of their
+            /// traces.
+            fn fold_offshoots(&mut self, mut node: ::ad_astra_runtime::Node<#step>) -> ::ad_astra_runtime::Node<#step> {
+                node.inner = node.inner.take().map(|inner| Box::new(self.fold(*inner)));
+                let offshoots = ::std::mem::replace(&mut node.offshoots, vec![]);
+                node.offshoots = offshoots.into_iter().map(|(idx, offshoot)| (idx, self.fold(offshoot))).collect();
+                node
+            }
+
+            #(#fold_methods)*
+        }
+    })
+}
+
+/// Productions in the order of rules, each named after its first step, or the
+/// nonterminal it derives. Names used by more than one production are followed
+/// by their own nonterminal.
+fn productions(input: &NeighborhoodInput, variants: &StepVariants) -> Vec<Production> {
+    let mut productions = vec![];
+    let mut lhs_names = vec![];
+    for (rule, stmt) in input.stmts.iter().enumerate() {
+        let lhs = &stmt.lhs;
+        let lhs_name = match stmt.lhs_ty {
+            Some(ref ty) => snake_case(&camel_case(&quote!(#lhs<#ty>).to_string())),
+            None => snake_case(&lhs.to_string()),
+        };
+        let alternatives = match stmt.rhs {
+            Rhs::Alternatives(ref alternatives) => alternatives.iter().enumerate().collect(),
+            ref rhs => vec![(0, rhs)],
+        };
+        for (i, alternative) in alternatives {
+            let name = production_name(alternative, variants).unwrap_or_else(|| lhs_name.clone());
+            let alternative = match stmt.rhs {
+                Rhs::Alternatives(_) => Some(i),
+                _ => None,
+            };
+            productions.push(Production { rule, alternative, name });
+            lhs_names.push(lhs_name.clone());
+        }
+    }
+    let names: Vec<String> = productions.iter().map(|production| production.name.to_string()).collect();
+    for (production, lhs_name) in productions.iter_mut().zip(lhs_names) {
+        let name = production.name.to_string();
+        if names.iter().filter(|&other| *other == name).count() > 1 && name != lhs_name.to_string() {
+            production.name = format_ident!("{}_{}", name, lhs_name);
+        }
+    }
+    // Alternatives that remain alike are numbered.
+    for i in 0 .. productions.len() {
+        let same = (0 .. i).filter(|&j| productions[j].name == productions[i].name).count();
+        if same > 0 {
+            productions[i].name = format_ident!("{}_{}", productions[i].name, same);
+        }
+    }
+    productions
+}
+
+/// Finds a reason why an alternative would not label a node of its own. It
+/// needs a step of its own, since a nonterminal alone leaves every step to the
+/// production of the nonterminal.
+fn hidden_alternative(rhs: &Rhs, variants: &StepVariants) -> Option<&'static str> {
+    match rhs {
+        &Rhs::Apply(..) => Some("an alternative is a nonterminal alone"),
+        &Rhs::Symbol(ref name) if !variants.all.contains(&name.to_string()) => {
+            Some("an alternative is a nonterminal alone")
+        }
+        rhs => hidden(rhs, variants, true),
+    }
+}
+
+/// Finds a part of an alternative whose production would not label a node of
+/// its own. A nonterminal that ends the alternative or an offshoot gets a node,
+/// nested in the node of its steps, but one followed by other steps would
+/// share a node with them. An alternative cannot hold another choice.
+fn hidden(rhs: &Rhs, variants: &StepVariants, last: bool) -> Option<&'static str> {
+    match rhs {
+        &Rhs::Alternatives(_) => Some("it nests alternatives"),
+        &Rhs::Sequence(ref items) => items.iter().enumerate().filter_map(|(i, item)|
+            hidden(item, variants, last && i + 1 == items.len())
+        ).next(),
+        &Rhs::ForAll(_, ref inner) => hidden(inner, variants, last),
+        &Rhs::Repeat(ref inner) => hidden(inner, variants, false),
+        &Rhs::Offshoots(ref items) => items.iter().filter_map(|item| hidden(item, variants, true)).next(),
+        &Rhs::Apply(..) if !last => Some("a nonterminal is followed by other steps outside of an offshoot"),
+        &Rhs::Symbol(ref name) if !last && !variants.all.contains(&name.to_string()) => {
+            Some("a nonterminal is followed by other steps outside of an offshoot")
+        }
+        &Rhs::Apply(..) | &Rhs::Symbol(_) | &Rhs::Pattern(..) => None,
+    }
+}
+
+fn production_name(alternative: &Rhs, variants: &StepVariants) -> Option<Ident> {
+    let items = match alternative {
+        &Rhs::Sequence(ref items) => items.iter().collect(),
+        item => vec![item],
+    };
+    let step = items.iter().filter_map(|&item| match item {
+        &Rhs::Symbol(ref name) if variants.all.contains(&name.to_string()) => Some(snake_case(&name.to_string())),
+        &Rhs::Pattern(ref name, _) => Some(snake_case(&name.to_string())),
+        _ => None,
+    }).next();
+    match (step, &items[..]) {
+        (Some(step), _) => Some(step),
+        (None, &[&Rhs::Apply(ref name, ref ty)]) => Some(snake_case(&camel_case(&quote!(#name<#ty>).to_string()))),
+        (None, &[&Rhs::Symbol(ref name)]) => Some(snake_case(&name.to_string())),
+        _ => None,
+    }
+}
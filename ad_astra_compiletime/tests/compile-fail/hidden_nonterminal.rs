@@ -0,0 +1,20 @@
+use ad_astra::ast;
+
+#[ast(visit)]
+mod ast {
+    #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+    pub enum Step {
+        Bool(bool),
+        Not,
+        #[trace]
+        Trace(usize),
+    }
+
+    neighborhood! {
+        Neighborhood, Path, Step =>
+            (@stmt Expr<bool> ::= Bool(_) | Not Expr<bool> Not)
+            (@allow Expr<bool>)
+    }
+}
+
+fn main() {}
@@ -0,0 +1,30 @@
+#![allow(dead_code)]
+
+use ad_astra::ast;
+
+use self::ast::*;
+
+#[ast]
+mod ast {
+    #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+    pub enum Step {
+        Bool(bool),
+        Int(isize),
+        LtExpr,
+        #[trace]
+        Trace(usize),
+    }
+
+    neighborhood! {
+        Neighborhood, Path, Step =>
+            (@stmt Expr<bool> ::= Bool(_) | LtExpr (Expr<isize> ^ Expr<isize>))
+            (@stmt Expr<isize> ::= Int(_))
+            (@allow Expr<bool>)
+    }
+}
+
+fn main() {
+    let _ = neighborhood_lit![
+        [Step::LtExpr, Step::Trace(0), Step::Int(1)],
+    ];
+}
@@ -0,0 +1,21 @@
+use ad_astra::ast;
+
+#[ast]
+mod ast {
+    #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+    pub enum Step {
+        Bool(bool),
+        Int(isize),
+        #[trace]
+        Trace(usize),
+    }
+
+    neighborhood! {
+        Neighborhood, Path, Step =>
+            (@stmt Expr<bool> ::= Bool(_))
+            (@stmt Expr<isize> ::= Int(_))
+            (@allow Expr<bool>)
+    }
+}
+
+fn main() {}
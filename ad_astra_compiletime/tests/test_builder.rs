@@ -0,0 +1,36 @@
+use ad_astra::ast;
+
+use self::ast::*;
+
+#[ast(builder)]
+mod ast {
+    #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+    pub enum Step {
+        Bool(bool),
+        Int(isize),
+        LtExpr,
+        #[trace]
+        Trace(usize),
+    }
+
+    neighborhood! {
+        Neighborhood, Path, Step =>
+            (@stmt Expr<bool> ::= Bool(true) | LtExpr (Expr<isize> ^ Expr<isize>))
+            (@stmt Expr<isize> ::= Int(_))
+            (@allow Expr<bool>)
+    }
+}
+
+#[test]
+fn test_builder_patterns() {
+    let lt: Neighborhood = build::Expr::<bool>::lt_expr(build::Expr::<isize>::int(1), build::Expr::<isize>::int(2)).into();
+    assert_eq!(lt.validate(), Ok(()));
+    let expected = Neighborhood::with_paths(vec![
+        path![Step::LtExpr, Step::Trace(0), Step::Int(1)],
+        path![Step::LtExpr, Step::Trace(1), Step::Int(2)],
+    ]);
+    assert_eq!(lt.to_tree(), expected.to_tree());
+    let yes: Option<Neighborhood> = build::Expr::<bool>::bool(Step::Bool(true)).map(Into::into);
+    assert_eq!(yes.map(|tree| tree.validate()), Some(Ok(())));
+    assert_eq!(build::Expr::<bool>::bool(Step::Bool(false)), None);
+}
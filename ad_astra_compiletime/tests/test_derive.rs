@@ -0,0 +1,86 @@
+#[macro_use]
+extern crate ad_astra_runtime;
+
+use std::convert::TryFrom;
+
+use ad_astra::{FromNeighborhood, IntoNeighborhood};
+use ad_astra_runtime::{FromTree, IntoTree};
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Value {
+    Bool(bool),
+    Int(isize),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Step {
+    Value(Value),
+    IfExpr,
+    EqExpr,
+    LtExpr,
+    Block,
+    Trace(usize),
+}
+
+ast! {
+    Neighborhood, Path, Step, #[trace] Trace, (for<T> Expr<T>) =>
+        ((Expr<bool>) ::=
+            (@m Step::Value(Value::Bool(_))) |
+            (EqExpr (for<T> ((Expr<T>) ^ (Expr<T>)))) |
+            (LtExpr ((Expr<isize>) ^ (Expr<isize>)))
+        );
+        ((Expr<isize>) ::=
+            (@m Step::Value(Value::Int(_)))
+        );
+        (for<T> ((Expr<T>) ::=
+            (IfExpr ((Expr<bool>) ^ (Expr<T>) ^ (Expr<T>)))
+        ));
+}
+
+#[derive(Clone, Debug, PartialEq, IntoNeighborhood, FromNeighborhood)]
+#[neighborhood(Neighborhood, Step)]
+pub enum Expr {
+    Value(Value),
+    IfExpr {
+        condition: Box<Expr>,
+        ifthen: Box<Expr>,
+        elsethen: Box<Expr>,
+    },
+    EqExpr(Box<Expr>, Box<Expr>),
+    LtExpr(Box<Expr>, Box<Expr>),
+    Block(Vec<Expr>),
+}
+
+fn int(value: isize) -> Box<Expr> {
+    Box::new(Expr::Value(Value::Int(value)))
+}
+
+#[test]
+fn test_derive_round_trip() {
+    let expr = Expr::IfExpr {
+        condition: Box::new(Expr::LtExpr(int(420), int(130))),
+        ifthen: int(1),
+        elsethen: int(2),
+    };
+    let neighborhood: Neighborhood = expr.clone().into();
+    assert_eq!(neighborhood.validate(), Ok(()));
+    assert_eq!(neighborhood.to_tree().len(), 1);
+    assert_eq!(Expr::try_from(&neighborhood), Ok(expr));
+}
+
+#[test]
+fn test_derive_invalid() {
+    let expr = Expr::LtExpr(int(1), Box::new(Expr::Value(Value::Bool(true))));
+    let neighborhood: Neighborhood = expr.into();
+    assert!(neighborhood.validate().is_err());
+}
+
+#[test]
+fn test_derive_vec() {
+    let expr = Expr::Block(vec![*int(1), Expr::EqExpr(int(2), int(3)), *int(4)]);
+    let tree = expr.to_tree();
+    assert_eq!(tree.steps, vec![Step::Block]);
+    assert_eq!(tree.children.iter().map(|&(idx, _)| idx).collect::<Vec<_>>(), vec![0, 1, 2]);
+    assert_eq!(Expr::from_tree(&tree), Ok(expr));
+    assert_eq!(Expr::from_tree(&Expr::Block(vec![]).to_tree()), Ok(Expr::Block(vec![])));
+}
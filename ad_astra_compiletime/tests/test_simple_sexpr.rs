@@ -8,20 +8,22 @@
 //   | Eq : 'a expr * 'a expr -> bool expr
 //   | Lt : int expr * int expr -> bool expr
 
-extern crate enum_ast;
+use std::convert::TryFrom;
 
-use self::ast::*;
+use ad_astra::ast;
 
-type FragmentId = u32;
-type BindId = u32;
+use self::ast::*;
 
-#[ast]
+#[ast(types, builder, visit)]
 mod ast {
+    #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
     pub enum Value {
         Bool(bool),
         Int(isize),
+        Str(&'static str),
     }
 
+    #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
     pub enum Step {
         Value(Value),
         IfExpr,
@@ -41,6 +43,9 @@ mod ast {
             (@stmt Expr<isize> ::=
                 Value(Value::Int(_))
             )
+            (@stmt Expr<&'static str> ::=
+                Value(Value::Str(_))
+            )
             (@stmt for<T> Expr<T> ::=
                 IfExpr (Expr<bool> ^ Expr<T> ^ Expr<T>)
             )
@@ -48,15 +53,158 @@ mod ast {
     }
 }
 
+fn if_lt_neighborhood() -> Neighborhood {
+    Neighborhood::with_paths(vec![
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(130))],
+        path![Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("a"))],
+        path![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("b"))],
+    ])
+}
+
 #[test]
 fn test_simple_sexpr() {
-    let tree = InputNeighborhood {
-        paths: vec![
-            path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
-            path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(130))],
-            path![Step::IfExpr, Step::Trace(1), Step::Value(0, "a")],
-            path![Step::IfExpr, Step::Trace(2), Step::Value(1, "b")],
-        ]
-    };
-    tree.validate();
+    assert_eq!(if_lt_neighborhood().validate(), Ok(()));
+}
+
+#[test]
+fn test_simple_sexpr_typed() {
+    let expr = ExprStaticStr::try_from(&if_lt_neighborhood()).unwrap();
+    let condition = ExprBool::LtExpr(
+        Box::new(ExprIsize::Value(Step::Value(Value::Int(420)))),
+        Box::new(ExprIsize::Value(Step::Value(Value::Int(130)))),
+    );
+    assert_eq!(expr, ExprStaticStr::IfExpr(Box::new(Expr::IfExpr(
+        Box::new(condition),
+        Box::new(ExprStaticStr::Value(Step::Value(Value::Str("a")))),
+        Box::new(ExprStaticStr::Value(Step::Value(Value::Str("b")))),
+    ))));
+    let neighborhood: Neighborhood = expr.into();
+    assert_eq!(neighborhood.validate(), Ok(()));
+    assert_eq!(neighborhood.to_tree(), if_lt_neighborhood().to_tree());
+}
+
+#[test]
+fn test_simple_sexpr_typed_exists() {
+    let neighborhood = Neighborhood::with_paths(vec![
+        path![Step::EqExpr, Step::Trace(0), Step::Value(Value::Str("a"))],
+        path![Step::EqExpr, Step::Trace(1), Step::Value(Value::Str("b"))],
+    ]);
+    let expr = ExprBool::try_from(&neighborhood).unwrap();
+    assert_eq!(expr, ExprBool::EqExpr(ExprBoolEqExpr::StaticStr(
+        Box::new(ExprStaticStr::Value(Step::Value(Value::Str("a")))),
+        Box::new(ExprStaticStr::Value(Step::Value(Value::Str("b")))),
+    )));
+    assert!(ExprIsize::try_from(&neighborhood).is_err());
+}
+
+#[test]
+fn test_simple_sexpr_precompiled() {
+    let neighborhood = Neighborhood::with_paths(vec![
+        path![Step::LtExpr, Step::Trace(0), Step::Value(Value::Bool(true))],
+        path![Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(1))],
+    ]);
+    assert!(neighborhood.is_precompiled());
+    let errors = neighborhood.validate().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!((errors[0].path, errors[0].step), (0, 2));
+    assert!(errors[0].expected.contains("Expr<isize>"));
+    assert_eq!(neighborhood.derive().map(|_| ()), Err(errors));
+    assert_eq!(if_lt_neighborhood().check(), Ok(()));
+}
+
+#[test]
+fn test_simple_sexpr_precompiled_without_rules() {
+    // The runtime is built from the tables alone, and none of the rules are
+    // given to it or processed, yet patterns still match.
+    let neighborhood = if_lt_neighborhood();
+    assert_eq!(neighborhood.rule_count(), 0);
+    assert_eq!(neighborhood.check(), Ok(()));
+    let bool_value = Neighborhood::with_paths(vec![path![Step::Value(Value::Bool(false))]]);
+    assert_eq!(bool_value.rule_count(), 0);
+    assert_eq!(bool_value.validate(), Ok(()));
+}
+
+#[test]
+fn test_simple_sexpr_literal() {
+    let neighborhood = neighborhood_lit![
+        [Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+        [Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(130))],
+        [Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("a"))],
+        [Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("b"))],
+    ];
+    assert_eq!(neighborhood.to_tree(), if_lt_neighborhood().to_tree());
+    // A variable in the fields is taken to match the pattern.
+    let value = Value::Int(1);
+    let checked = neighborhood_lit![[Step::Value(value)]];
+    assert_eq!(checked.validate(), Ok(()));
+    // A step that is not written out leaves the literal to be checked at run
+    // time.
+    let step = Step::Value(Value::Bool(true));
+    let unchecked = neighborhood_lit![[Step::LtExpr, Step::Trace(0), step]];
+    assert!(unchecked.validate().is_err());
+}
+
+#[test]
+fn test_simple_sexpr_builder() {
+    use self::ast::build;
+
+    let int = build::Expr::<isize>::value;
+    let string = build::Expr::<&'static str>::value;
+    let expr = build::Expr::if_expr(build::Expr::<bool>::lt_expr(int(420), int(130)), string("a"), string("b"));
+    let neighborhood: Neighborhood = expr.into();
+    assert_eq!(neighborhood.validate(), Ok(()));
+    assert_eq!(neighborhood.to_tree(), if_lt_neighborhood().to_tree());
+    let eq: Neighborhood = build::Expr::<bool>::eq_expr(string("a"), string("b")).into();
+    assert_eq!(eq.validate(), Ok(()));
+}
+
+#[test]
+fn test_simple_sexpr_visit() {
+    use ad_astra_runtime::Node;
+
+    struct Ints(Vec<isize>, usize);
+
+    impl Visitor for Ints {
+        fn visit_value_expr_isize(&mut self, node: &Node<Step>) {
+            if let Step::Value(Value::Int(n)) = node.steps[0] {
+                self.0.push(n);
+            }
+        }
+
+        fn visit_lt_expr(&mut self, node: &Node<Step>) {
+            self.1 += 1;
+            self.visit_offshoots(node);
+        }
+    }
+
+    struct SwapBranches;
+
+    impl Fold for SwapBranches {
+        fn fold_if_expr(&mut self, node: Node<Step>) -> Node<Step> {
+            let mut node = self.fold_offshoots(node);
+            let (then, otherwise) = (node.offshoots[1].1.clone(), node.offshoots[2].1.clone());
+            node.offshoots[1].1 = otherwise;
+            node.offshoots[2].1 = then;
+            node
+        }
+    }
+
+    let nodes = if_lt_neighborhood().nodes().unwrap();
+    assert_eq!((nodes[0].rule, nodes[0].alternative), (3, None));
+    assert_eq!((nodes[0].offshoots[0].1.rule, nodes[0].offshoots[0].1.alternative), (0, Some(2)));
+    let mut ints = Ints(vec![], 0);
+    ints.visit(&nodes[0]);
+    assert_eq!((ints.0, ints.1), (vec![420, 130], 1));
+
+    let swapped = SwapBranches.fold(nodes[0].clone());
+    let neighborhood = Neighborhood::from_nodes(&[swapped]);
+    assert_eq!(neighborhood.validate(), Ok(()));
+    let expected = Neighborhood::with_paths(vec![
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(130))],
+        path![Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("b"))],
+        path![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("a"))],
+    ]);
+    assert_eq!(neighborhood.to_tree(), expected.to_tree());
 }
@@ -0,0 +1,88 @@
+use ad_astra::ast;
+use ad_astra_runtime::Node;
+
+use self::ast::*;
+
+#[ast(visit)]
+mod ast {
+    #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+    pub enum Step {
+        Bool(bool),
+        Not,
+        And,
+        #[trace]
+        Trace(usize),
+    }
+
+    neighborhood! {
+        Neighborhood, Path, Step =>
+            (@stmt Expr<bool> ::= Bool(_) | Not Expr<bool> | And (Expr<bool> ^ Expr<bool>))
+            (@allow Expr<bool>)
+    }
+}
+
+fn not_and_neighborhood() -> Neighborhood {
+    Neighborhood::with_paths(vec![
+        path![Step::Not, Step::And, Step::Trace(0), Step::Not, Step::Not, Step::Bool(true)],
+        path![Step::Not, Step::And, Step::Trace(1), Step::Bool(false)],
+    ])
+}
+
+#[test]
+fn test_visit_chain_nodes() {
+    let nodes = not_and_neighborhood().nodes().unwrap();
+    assert_eq!(nodes.len(), 1);
+    assert_eq!((nodes[0].steps.clone(), nodes[0].alternative), (vec![Step::Not], Some(1)));
+    let and = nodes[0].inner.as_ref().unwrap();
+    assert_eq!((and.steps.clone(), and.alternative), (vec![Step::And], Some(2)));
+    assert!(nodes[0].offshoots.is_empty());
+    let not = &and.offshoots[0].1;
+    let inner_not = not.inner.as_ref().unwrap();
+    assert_eq!((not.alternative, inner_not.alternative), (Some(1), Some(1)));
+    let value = inner_not.inner.as_ref().unwrap();
+    assert_eq!((value.steps.clone(), value.alternative), (vec![Step::Bool(true)], Some(0)));
+    assert_eq!(Neighborhood::from_nodes(&nodes).to_tree(), not_and_neighborhood().to_tree());
+}
+
+#[test]
+fn test_visit_chain_dispatch() {
+    struct Count(usize, Vec<bool>);
+
+    impl Visitor for Count {
+        fn visit_not(&mut self, node: &Node<Step>) {
+            self.0 += 1;
+            self.visit_offshoots(node);
+        }
+
+        fn visit_bool(&mut self, node: &Node<Step>) {
+            if let Step::Bool(value) = node.steps[0] {
+                self.1.push(value);
+            }
+        }
+    }
+
+    struct Negate;
+
+    impl Fold for Negate {
+        fn fold_bool(&mut self, mut node: Node<Step>) -> Node<Step> {
+            if let Step::Bool(value) = node.steps[0] {
+                node.steps[0] = Step::Bool(!value);
+            }
+            node
+        }
+    }
+
+    let nodes = not_and_neighborhood().nodes().unwrap();
+    let mut count = Count(0, vec![]);
+    count.visit(&nodes[0]);
+    assert_eq!((count.0, count.1), (3, vec![true, false]));
+
+    let negated = Negate.fold(nodes[0].clone());
+    let neighborhood = Neighborhood::from_nodes(&[negated]);
+    assert_eq!(neighborhood.validate(), Ok(()));
+    let expected = Neighborhood::with_paths(vec![
+        path![Step::Not, Step::And, Step::Trace(0), Step::Not, Step::Not, Step::Bool(false)],
+        path![Step::Not, Step::And, Step::Trace(1), Step::Bool(true)],
+    ]);
+    assert_eq!(neighborhood.to_tree(), expected.to_tree());
+}
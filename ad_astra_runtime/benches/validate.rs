@@ -0,0 +1,76 @@
+#[macro_use]
+extern crate ad_astra_runtime;
+#[macro_use]
+extern crate criterion;
+
+use ad_astra_runtime::PathTrie;
+use criterion::{BenchmarkId, Criterion};
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Value {
+    Bool(bool),
+    Int(isize),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Step {
+    Value(Value),
+    EqExpr,
+    Trace(usize),
+}
+
+ast! {
+    Neighborhood, Path, Step, #[trace] Trace, (for<T> Expr<T>) =>
+        ((Expr<bool>) ::=
+            (@m Step::Value(Value::Bool(_))) |
+            (EqExpr (for<T> ((Expr<T>) ^ (Expr<T>)))));
+        ((Expr<isize>) ::=
+            (@m Step::Value(Value::Int(_))));
+}
+
+/// A complete binary tree of `EqExpr` nodes with integer leaves.
+fn eq_tree(depth: usize) -> Neighborhood {
+    fn build(prefix: &mut Vec<Step>, depth: usize, paths: &mut Vec<Path>) {
+        if depth == 0 {
+            let mut steps = prefix.clone();
+            steps.push(Step::Value(Value::Int(paths.len() as isize)));
+            paths.push(Path::with_steps(steps));
+            return;
+        }
+        for trace in 0 .. 2 {
+            prefix.push(Step::EqExpr);
+            prefix.push(Step::Trace(trace));
+            build(prefix, depth - 1, paths);
+            prefix.pop();
+            prefix.pop();
+        }
+    }
+    let mut paths = vec![];
+    build(&mut vec![], depth, &mut paths);
+    Neighborhood::with_paths(paths)
+}
+
+/// Validates trees of growing depth with the same recognizer, path by path
+/// and through a trie. The times of the trie grow with the number of nodes,
+/// and those of separate paths with the number of nodes times the depth.
+fn bench_validate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("validate");
+    for &depth in &[4, 6, 8, 10] {
+        let tree = eq_tree(depth);
+        group.bench_with_input(BenchmarkId::new("path_by_path", depth), &tree, |b, tree| {
+            b.iter(|| {
+                for (i, path) in tree.paths.iter().enumerate() {
+                    tree.runtime.validate_steps(i, &path.steps[..]).unwrap();
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("trie", depth), &tree, |b, tree| {
+            let trie = PathTrie::new(&tree.paths[..]);
+            b.iter(|| tree.runtime.validate_trie(&trie).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_validate);
+criterion_main!(benches);
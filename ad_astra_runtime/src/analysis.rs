@@ -0,0 +1,131 @@
+use std::collections::BTreeSet;
+
+use cfg::Symbol;
+
+use super::{GrammarError, Matcher, NeighborhoodRuntime, Step};
+
+impl<T> NeighborhoodRuntime<T> {
+    /// Finds grammar bugs in the lowered rules before any path is recognized.
+    pub(crate) fn analyze(&self, start: Symbol) -> Vec<GrammarError> {
+        let mut errors = vec![];
+
+        let mut productive: BTreeSet<Symbol> = self.terminals.iter().map(|&(sym, _)| sym).collect();
+        loop {
+            let before = productive.len();
+            for &(lhs, ref rhs) in &self.rules {
+                if rhs.iter().all(|sym| productive.contains(sym)) {
+                    productive.insert(lhs);
+                }
+            }
+            if productive.len() == before {
+                break;
+            }
+        }
+
+        let mut reachable = BTreeSet::new();
+        let mut stack = vec![start];
+        while let Some(sym) = stack.pop() {
+            if reachable.insert(sym) {
+                for &(lhs, ref rhs) in &self.rules {
+                    if lhs == sym {
+                        stack.extend(rhs.iter().cloned());
+                    }
+                }
+            }
+        }
+
+        // Instances of `for<T>` rules that cannot bottom out are not defined
+        // for their type at all.
+        let concrete = self.concrete_definitions();
+        let mut undefined = BTreeSet::new();
+        for (name, &sym) in &self.nonterminals {
+            let has_rules = self.rules.iter().any(|&(lhs, _)| lhs == sym);
+            if !has_rules || (!productive.contains(&sym) && !concrete.contains(name)) {
+                errors.push(GrammarError::Undefined { name: name.clone() });
+                undefined.insert(name.clone());
+            }
+        }
+
+        for &(rule, ref lhs, rhs) in &self.productions {
+            if !productive.contains(&rhs) && !undefined.contains(lhs) {
+                errors.push(GrammarError::Unproductive { rule, lhs: lhs.clone() });
+            }
+        }
+
+        for rule in 0 .. self.stmts.len() {
+            let is_reachable = self.productions.iter().any(|&(production_rule, ref lhs, _)|
+                production_rule == rule && reachable.contains(&self.nonterminals[lhs])
+            );
+            if is_reachable {
+                continue;
+            }
+            // A family of nonterminals is never reached only if no reachable
+            // rule applies it to a type.
+            if self.is_family(rule) {
+                errors.push(GrammarError::NeverInstantiated { rule, lhs: self.rule_lhs(rule) });
+            } else {
+                errors.push(GrammarError::Unreachable { rule, lhs: self.rule_lhs(rule) });
+            }
+        }
+
+        errors.extend(self.unused_params());
+        errors
+    }
+
+    /// Names of nonterminals defined by rules without `for<T>`.
+    fn concrete_definitions(&self) -> BTreeSet<String> {
+        self.stmts.iter().filter_map(|stmt|
+            stmt.paths.iter().find(|path| path.steps == [Step::Lhs])
+        ).map(|path| self.matcher_name(&path.matcher, &[])).collect()
+    }
+
+    fn rule_lhs(&self, rule: usize) -> String {
+        let path = self.stmts[rule].paths.iter()
+            .find(|path| path.steps.last() == Some(&Step::Lhs))
+            .expect("rule without a left-hand side");
+        self.matcher_name(&path.matcher, &[])
+    }
+
+    /// Whether the left-hand side applies a parameter of the rule's `for<T>`.
+    fn is_family(&self, rule: usize) -> bool {
+        let path = self.stmts[rule].paths.iter()
+            .find(|path| path.steps.last() == Some(&Step::Lhs))
+            .expect("rule without a left-hand side");
+        match path.matcher {
+            Matcher::ParamApply { ref ty_param, .. } => path.steps.iter().any(|step| match step {
+                &Step::IntroduceParam(ref param) => param == ty_param,
+                _ => false,
+            }),
+            _ => false,
+        }
+    }
+
+    fn unused_params(&self) -> Vec<GrammarError> {
+        let mut errors = vec![];
+        for (rule, stmt) in self.stmts.iter().enumerate() {
+            let mut seen = BTreeSet::new();
+            for path in &stmt.paths {
+                for (i, step) in path.steps.iter().enumerate() {
+                    let param = match step {
+                        &Step::IntroduceParam(ref param) => param,
+                        _ => continue,
+                    };
+                    let prefix = &path.steps[.. i + 1];
+                    if !seen.insert(prefix.to_vec()) {
+                        continue;
+                    }
+                    let used = stmt.paths.iter().any(|other|
+                        other.steps.starts_with(prefix) && match other.matcher {
+                            Matcher::ParamApply { ref ty_param, .. } => ty_param == param,
+                            _ => false,
+                        }
+                    );
+                    if !used {
+                        errors.push(GrammarError::UnusedParam { rule, param: param.clone() });
+                    }
+                }
+            }
+        }
+        errors
+    }
+}
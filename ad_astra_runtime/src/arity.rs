@@ -0,0 +1,54 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::{ArityError, Derivation, NeighborhoodError, NeighborhoodRuntime, PathTrie, Traced};
+
+impl<T: Traced + PartialEq + fmt::Debug> NeighborhoodRuntime<T> {
+    /// Checks the neighborhood as a whole: validates and unifies every path,
+    /// then checks that every node has exactly the offshoots of its production.
+    pub fn check<P: AsRef<[T]>>(&self, paths: &[P]) -> Result<(), Vec<NeighborhoodError>> {
+        let derivations = self.unify(paths)?;
+        self.check_arity(paths, &derivations[..])
+    }
+
+    /// Checks that the traces following each node are contiguous from 0 and
+    /// match the number of offshoots of the production the node was derived from.
+    pub fn check_arity<P: AsRef<[T]>>(&self, paths: &[P], derivations: &[Vec<Derivation>]) -> Result<(), Vec<NeighborhoodError>> {
+        let trie = PathTrie::new(paths);
+        let mut errors = vec![];
+        for node in 0 .. trie.len() {
+            let traces: Vec<(usize, usize)> = trie.children(node).iter().filter_map(|&child|
+                trie.step(child).trace().map(|idx| (idx, child))
+            ).collect();
+            if traces.is_empty() {
+                continue;
+            }
+            let child = traces[0].1;
+            let arity = derivations[trie.path(child)].iter()
+                .filter_map(|derivation| derivation.steps[trie.depth(child)].arity)
+                .next();
+            let arity = match arity {
+                Some(arity) => arity,
+                None => continue,
+            };
+            let found: BTreeSet<usize> = traces.iter().map(|&(idx, _)| idx).collect();
+            let missing: Vec<usize> = (0 .. arity).filter(|idx| !found.contains(idx)).collect();
+            let extra: Vec<usize> = found.iter().cloned().filter(|&idx| idx >= arity).collect();
+            if !missing.is_empty() || !extra.is_empty() {
+                errors.push(NeighborhoodError::from(ArityError {
+                    path: trie.path(node),
+                    step: trie.depth(node),
+                    prefix: format!("{:?}", trie.prefix(child)),
+                    arity,
+                    missing,
+                    extra,
+                }));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ptr;
+use std::rc::Rc;
+
+use cfg::Symbol;
+
+/// An Earley recognizer whose sets are shared between paths. Items point at
+/// the set where they began, so a set after a fork keeps every earlier set
+/// alive without copying it, and each set is built once.
+pub(crate) struct Chart<'r> {
+    rules: &'r [(Symbol, Vec<Symbol>)],
+    /// Rules by the index of their left-hand side.
+    by_lhs: Vec<Vec<usize>>,
+    nullable: Vec<bool>,
+    start: Symbol,
+}
+
+pub(crate) struct EarleySet {
+    items: Vec<Item>,
+    /// The number of steps scanned before the set.
+    position: usize,
+}
+
+#[derive(Clone)]
+struct Item {
+    rule: usize,
+    dot: usize,
+    /// The set where the item began, or `None` for the set that holds it.
+    origin: Option<Rc<EarleySet>>,
+}
+
+impl<'r> Chart<'r> {
+    pub(crate) fn new(rules: &'r [(Symbol, Vec<Symbol>)], start: Symbol) -> Self {
+        let syms = rules.iter()
+            .flat_map(|&(lhs, ref rhs)| Some(lhs).into_iter().chain(rhs.iter().cloned()))
+            .fold(start.usize() + 1, |syms, sym| syms.max(sym.usize() + 1));
+        let mut by_lhs = vec![vec![]; syms];
+        for (rule, &(lhs, _)) in rules.iter().enumerate() {
+            by_lhs[lhs.usize()].push(rule);
+        }
+        let mut nullable = vec![false; syms];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &(lhs, ref rhs) in rules {
+                if !nullable[lhs.usize()] && rhs.iter().all(|sym| nullable[sym.usize()]) {
+                    nullable[lhs.usize()] = true;
+                    changed = true;
+                }
+            }
+        }
+        Chart { rules, by_lhs, nullable, start }
+    }
+
+    /// The set before the first step.
+    pub(crate) fn root(&self) -> Rc<EarleySet> {
+        let kernel = self.by_lhs[self.start.usize()].iter()
+            .map(|&rule| Item { rule, dot: 0, origin: None })
+            .collect();
+        Rc::new(self.close(kernel, 0))
+    }
+
+    /// The set after a step that matches the given terminals, if any item
+    /// accepts it.
+    pub(crate) fn scan(&self, set: &Rc<EarleySet>, terminals: &BTreeSet<usize>) -> Option<Rc<EarleySet>> {
+        let kernel: Vec<Item> = set.items.iter().filter_map(|item| {
+            match self.next_sym(item) {
+                Some(sym) if terminals.contains(&sym.usize()) => Some(Item {
+                    rule: item.rule,
+                    dot: item.dot + 1,
+                    origin: Some(item.origin.clone().unwrap_or_else(|| set.clone())),
+                }),
+                _ => None,
+            }
+        }).collect();
+        if kernel.is_empty() {
+            None
+        } else {
+            Some(Rc::new(self.close(kernel, set.position + 1)))
+        }
+    }
+
+    /// Whether the steps up to the set form a whole neighborhood path.
+    pub(crate) fn is_finished(&self, set: &Rc<EarleySet>, root: &Rc<EarleySet>) -> bool {
+        set.items.iter().any(|item| {
+            let (lhs, ref rhs) = self.rules[item.rule];
+            let from_root = match item.origin {
+                Some(ref origin) => Rc::ptr_eq(origin, root),
+                None => Rc::ptr_eq(set, root),
+            };
+            lhs == self.start && item.dot == rhs.len() && from_root
+        })
+    }
+
+    /// The symbols after the dot in the items of the set, each with the
+    /// left-hand side of its rule.
+    pub(crate) fn predictions<'s>(&'s self, set: &'s EarleySet) -> impl Iterator<Item = (Symbol, Symbol)> + 's {
+        set.items.iter().filter_map(move |item| self.next_sym(item).map(|sym| (self.rules[item.rule].0, sym)))
+    }
+
+    fn next_sym(&self, item: &Item) -> Option<Symbol> {
+        self.rules[item.rule].1.get(item.dot).cloned()
+    }
+
+    /// Adds predictions and completions to the items. Rules that derive the
+    /// empty string are skipped over as they are predicted.
+    fn close(&self, kernel: Vec<Item>, position: usize) -> EarleySet {
+        let mut items: Vec<Item> = vec![];
+        let mut seen = BTreeSet::new();
+        let mut pending = kernel;
+        while let Some(item) = pending.pop() {
+            let origin = item.origin.as_ref().map_or(ptr::null(), |origin| &**origin as *const EarleySet);
+            if !seen.insert((item.rule, item.dot, origin)) {
+                continue;
+            }
+            match self.next_sym(&item) {
+                Some(sym) => {
+                    for &rule in &self.by_lhs[sym.usize()] {
+                        pending.push(Item { rule, dot: 0, origin: None });
+                    }
+                    if self.nullable[sym.usize()] {
+                        pending.push(Item { rule: item.rule, dot: item.dot + 1, origin: item.origin.clone() });
+                    }
+                }
+                None => {
+                    // Items that began in this set are nullable, and were
+                    // skipped over already.
+                    if let Some(ref origin) = item.origin {
+                        let lhs = self.rules[item.rule].0;
+                        for parent in &origin.items {
+                            if self.next_sym(parent) == Some(lhs) {
+                                pending.push(Item {
+                                    rule: parent.rule,
+                                    dot: parent.dot + 1,
+                                    origin: Some(parent.origin.clone().unwrap_or_else(|| origin.clone())),
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+            items.push(item);
+        }
+        EarleySet { items, position }
+    }
+
+    /// Every derivation of the start symbol over the sets of one path, from
+    /// the root on, where each step matched the given terminals. A derivation
+    /// is valued bottom-up: `leaf` values a terminal, `rule` values a rule from
+    /// the values of its right-hand side, and a symbol that derives no steps
+    /// has the default value.
+    pub(crate) fn derivations<V, L, R>(&self, sets: &[Rc<EarleySet>], terminals: &[BTreeSet<usize>], leaf: L, rule: R) -> Vec<V>
+        where V: Clone + Default,
+              L: Fn(Symbol) -> V,
+              R: Fn(usize, &[&V]) -> V,
+    {
+        let mut evaluator = Evaluator { chart: self, sets, terminals, leaf, rule, memo: BTreeMap::new() };
+        evaluator.values(self.start, 0, sets.len() - 1)
+    }
+}
+
+/// Walks back from the completed items of a path to the items they began
+/// with, and values every way of splitting each rule over the steps.
+struct Evaluator<'c, 'r: 'c, V, L, R> {
+    chart: &'c Chart<'r>,
+    sets: &'c [Rc<EarleySet>],
+    terminals: &'c [BTreeSet<usize>],
+    leaf: L,
+    rule: R,
+    /// Values by symbol and span, or `None` while the span is being valued,
+    /// which cuts cycles of rules that derive one another.
+    memo: BTreeMap<(usize, usize, usize), Option<Vec<V>>>,
+}
+
+impl<'c, 'r: 'c, V, L, R> Evaluator<'c, 'r, V, L, R>
+    where V: Clone + Default,
+          L: Fn(Symbol) -> V,
+          R: Fn(usize, &[&V]) -> V,
+{
+    fn values(&mut self, sym: Symbol, start: usize, end: usize) -> Vec<V> {
+        if start == end {
+            return if self.chart.nullable[sym.usize()] { vec![V::default()] } else { vec![] };
+        }
+        if self.chart.by_lhs[sym.usize()].is_empty() {
+            return if end == start + 1 && self.terminals[start].contains(&sym.usize()) {
+                vec![(self.leaf)(sym)]
+            } else {
+                vec![]
+            };
+        }
+        let key = (sym.usize(), start, end);
+        match self.memo.get(&key) {
+            Some(&Some(ref values)) => return values.clone(),
+            Some(&None) => return vec![],
+            None => {}
+        }
+        self.memo.insert(key, None);
+        let mut completed: Vec<usize> = self.sets[end].items.iter().filter(|item| {
+            let (lhs, ref rhs) = self.chart.rules[item.rule];
+            lhs == sym && item.dot == rhs.len() && self.origin(item, end) == start
+        }).map(|item| item.rule).collect();
+        completed.sort();
+        completed.dedup();
+        let mut values = vec![];
+        for rule in completed {
+            for args in self.splits(rule, self.chart.rules[rule].1.len(), start, end) {
+                let args: Vec<&V> = args.iter().collect();
+                values.push((self.rule)(rule, &args[..]));
+            }
+        }
+        self.memo.insert(key, Some(values.clone()));
+        values
+    }
+
+    /// Values of the first `dot` symbols of the rule over the steps from
+    /// `start` to `end`, split in every way the items allow.
+    fn splits(&mut self, rule: usize, dot: usize, start: usize, end: usize) -> Vec<Vec<V>> {
+        if dot == 0 {
+            return if start == end { vec![vec![]] } else { vec![] };
+        }
+        let sym = self.chart.rules[rule].1[dot - 1];
+        let mut splits = vec![];
+        for mid in start .. end + 1 {
+            let begun = self.sets[mid].items.iter().any(|item|
+                item.rule == rule && item.dot == dot - 1 && self.origin(item, mid) == start
+            );
+            if !begun {
+                continue;
+            }
+            let values = self.values(sym, mid, end);
+            if values.is_empty() {
+                continue;
+            }
+            for left in self.splits(rule, dot - 1, start, mid) {
+                for value in &values {
+                    let mut args = left.clone();
+                    args.push(value.clone());
+                    splits.push(args);
+                }
+            }
+        }
+        splits
+    }
+
+    fn origin(&self, item: &Item, set: usize) -> usize {
+        item.origin.as_ref().map_or(set, |origin| origin.position)
+    }
+}
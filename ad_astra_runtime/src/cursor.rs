@@ -0,0 +1,184 @@
+use std::fmt;
+use std::rc::Rc;
+
+use super::{PathTrie, Traced};
+
+/// A position at a node of the trees that the paths go through. A node holds
+/// the steps up to the next trace, as in `Tree`, and its children are the
+/// offshoots after that trace. Cursors from one call to `roots` or `at` share
+/// an index of the paths.
+pub struct Cursor<'a, T: 'a> {
+    trie: Rc<PathTrie<'a, T>>,
+    /// The trie node of the first step.
+    first: usize,
+    /// The number of nodes above this one.
+    depth: usize,
+}
+
+impl<'a, T: Traced + PartialEq> Cursor<'a, T> {
+    /// Cursors at the roots of the trees, in the order of paths.
+    pub fn roots<P: AsRef<[T]>>(paths: &'a [P]) -> Vec<Self> {
+        let trie = Rc::new(PathTrie::new(paths));
+        Cursor::starts(&trie, trie.root_nodes(), 0)
+    }
+
+    /// A cursor at the node that begins right after the prefix, if a path
+    /// goes through it.
+    pub fn at<P: AsRef<[T]>>(paths: &'a [P], prefix: &[T]) -> Option<Self> {
+        let trie = Rc::new(PathTrie::new(paths));
+        let mut nodes = trie.root_nodes();
+        for step in prefix {
+            let node = nodes.iter().cloned().find(|&node| trie.step(node) == step)?;
+            nodes = trie.children(node);
+        }
+        let first = nodes.iter().cloned().find(|&node| trie.is_first(node))?;
+        let mut depth = 0;
+        let mut node = first;
+        while let Some(parent) = trie.parent(node) {
+            if trie.is_first(parent) {
+                depth += 1;
+            }
+            node = parent;
+        }
+        Some(Cursor { trie, first, depth })
+    }
+}
+
+impl<'a, T: Traced> Cursor<'a, T> {
+    /// Nodes that begin after the given trie nodes, skipping traces. Offshoots
+    /// come in the order of their traces.
+    fn starts(trie: &Rc<PathTrie<'a, T>>, nodes: &[usize], depth: usize) -> Vec<Self> {
+        let mut starts = vec![];
+        for &node in nodes {
+            match trie.step(node).trace() {
+                Some(idx) => starts.extend(trie.children(node).iter().map(|&child| (Some(idx), child))),
+                None => starts.push((None, node)),
+            }
+        }
+        starts.sort_by_key(|&(idx, _)| idx);
+        starts.into_iter().map(|(_, first)| Cursor { trie: trie.clone(), first, depth }).collect()
+    }
+}
+
+impl<'a, T: Traced> PathTrie<'a, T> {
+    /// Whether a node begins at the given trie node. Nodes end before a trace,
+    /// where paths diverge, and where a path ends.
+    fn is_first(&self, node: usize) -> bool {
+        if self.step(node).trace().is_some() {
+            return false;
+        }
+        match self.parent(node) {
+            None => true,
+            Some(parent) => {
+                self.step(parent).trace().is_some()
+                    || self.children(parent).len() > 1
+                    || !self.ends(parent).is_empty()
+            }
+        }
+    }
+}
+
+impl<'a, T: Traced> Cursor<'a, T> {
+    /// The first step of the node.
+    pub fn step(&self) -> &'a T {
+        self.trie.step(self.first)
+    }
+
+    /// The steps of the node, up to the next trace.
+    pub fn steps(&self) -> &'a [T] {
+        self.trie.steps(self.first, self.last())
+    }
+
+    /// The steps before the node.
+    pub fn prefix(&self) -> &'a [T] {
+        self.trie.prefix(self.first)
+    }
+
+    /// The index of the offshoot, or `None` for a root, or for paths that
+    /// diverge before a trace.
+    pub fn trace(&self) -> Option<usize> {
+        self.trie.parent(self.first).and_then(|parent| self.trie.step(parent).trace())
+    }
+
+    /// The number of nodes above this one.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn parent(&self) -> Option<Self> {
+        let mut node = self.trie.parent(self.first)?;
+        if self.trie.step(node).trace().is_some() {
+            node = self.trie.parent(node)?;
+        }
+        while !self.trie.is_first(node) {
+            node = self.trie.parent(node)?;
+        }
+        Some(Cursor { trie: self.trie.clone(), first: node, depth: self.depth - 1 })
+    }
+
+    /// The offshoots in the order of their traces, after nodes of paths that
+    /// diverge.
+    pub fn children(&self) -> Vec<Self> {
+        Cursor::starts(&self.trie, self.trie.children(self.last()), self.depth + 1)
+    }
+
+    /// The first offshoot with the given trace index.
+    pub fn child(&self, idx: usize) -> Option<Self> {
+        self.children().into_iter().find(|child| child.trace() == Some(idx))
+    }
+
+    pub fn next_sibling(&self) -> Option<Self> {
+        let siblings = match self.parent() {
+            Some(parent) => parent.children(),
+            None => Cursor::starts(&self.trie, self.trie.root_nodes(), 0),
+        };
+        let position = siblings.iter().position(|sibling| sibling == self)?;
+        siblings.get(position + 1).cloned()
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.trie.children(self.last()).is_empty()
+    }
+
+    /// Indices of the paths that go through the node, in order.
+    pub fn paths(&self) -> Vec<usize> {
+        let mut paths = self.trie.paths_under(self.first);
+        paths.sort();
+        paths
+    }
+
+    /// The trie node of the last step.
+    fn last(&self) -> usize {
+        let mut node = self.first;
+        loop {
+            let children = self.trie.children(node);
+            if children.len() != 1 || !self.trie.ends(node).is_empty() || self.trie.step(children[0]).trace().is_some() {
+                return node;
+            }
+            node = children[0];
+        }
+    }
+}
+
+impl<'a, T> Clone for Cursor<'a, T> {
+    fn clone(&self) -> Self {
+        Cursor { trie: self.trie.clone(), first: self.first, depth: self.depth }
+    }
+}
+
+impl<'a, T> PartialEq for Cursor<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.trie, &other.trie) && self.first == other.first
+    }
+}
+
+impl<'a, T> Eq for Cursor<'a, T> {}
+
+impl<'a, T: Traced + fmt::Debug> fmt::Debug for Cursor<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cursor")
+            .field("prefix", &self.prefix())
+            .field("steps", &self.steps())
+            .finish()
+    }
+}
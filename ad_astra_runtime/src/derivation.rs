@@ -0,0 +1,94 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use cfg::Symbol;
+
+use super::{ExtStep, NeighborhoodRuntime, TerminalKind, Traced, ValidationError};
+
+/// One way of deriving a path from the grammar.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Derivation {
+    pub steps: Vec<StepDerivation>,
+}
+
+/// The production a single step of a path was derived under.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StepDerivation {
+    /// Nonterminals whose derivation begins at this step, outermost first.
+    pub starts: Vec<String>,
+    /// The nonterminal of the rule that matched this step, e.g. `Expr<bool>`.
+    pub lhs: String,
+    /// Index of the rule, in the order given to `NeighborhoodRuntime::rule`.
+    pub rule: usize,
+    /// Alternatives of `or` taken within the rule, outermost first.
+    pub alternatives: Vec<usize>,
+    /// Type parameters bound by `for<T>`, e.g. `("T", "bool")`.
+    pub bindings: Vec<(String, String)>,
+    /// For a trace, the number of offshoots of its production.
+    pub arity: Option<usize>,
+}
+
+impl<T: Traced + fmt::Debug> NeighborhoodRuntime<T> {
+    pub fn derive<P: AsRef<[T]>>(&self, paths: &[P]) -> Result<Vec<Vec<Derivation>>, Vec<ValidationError>> {
+        let mut derivations = vec![];
+        let mut errors = vec![];
+        for (i, path) in paths.iter().enumerate() {
+            match self.derive_steps(i, path.as_ref()) {
+                Ok(derivation) => derivations.push(derivation),
+                Err(error) => errors.push(error),
+            }
+        }
+        if errors.is_empty() {
+            Ok(derivations)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Returns every derivation of the given path. There is more than one when
+    /// the path alone does not determine the type parameters of `for<T>`.
+    pub fn derive_steps(&self, path: usize, steps: &[T]) -> Result<Vec<Derivation>, ValidationError> {
+        let chart = self.chart();
+        let sets = self.recognize(&chart, path, steps)?;
+        let terminals: Vec<BTreeSet<usize>> = steps.iter().map(|step| self.terminal_syms(step)).collect();
+        let results = chart.derivations(
+            &sets[..],
+            &terminals[..],
+            |sym| vec![self.step_derivation(sym)],
+            |rule, args| self.rule_derivation(rule, args),
+        );
+        Ok(results.into_iter().map(|steps| Derivation { steps }).collect())
+    }
+
+    fn step_derivation(&self, sym: Symbol) -> StepDerivation {
+        let terminal = self.terminals.iter().find(|&&(terminal, _)| terminal == sym)
+            .map(|&(_, ref terminal)| terminal)
+            .expect("unknown terminal");
+        StepDerivation {
+            starts: vec![],
+            lhs: terminal.lhs.clone(),
+            rule: terminal.ext.stmt,
+            alternatives: terminal.ext.steps.iter().filter_map(|step| match step {
+                &ExtStep::Alternative(n) => Some(n),
+                _ => None,
+            }).collect(),
+            bindings: terminal.env.iter().map(|&(ref param, ty)|
+                (param.clone(), self.param_map.get(ty).to_string())
+            ).collect(),
+            arity: match terminal.kind {
+                TerminalKind::Trace { arity, .. } => Some(arity),
+                TerminalKind::Leaf(_) => None,
+            },
+        }
+    }
+
+    fn rule_derivation(&self, rule: usize, args: &[&Vec<StepDerivation>]) -> Vec<StepDerivation> {
+        let mut steps: Vec<StepDerivation> = args.iter().flat_map(|arg| arg.iter().cloned()).collect();
+        let lhs = self.rules[rule].0;
+        let name = self.nonterminals.iter().find(|&(_, &sym)| sym == lhs).map(|(name, _)| name);
+        if let (Some(name), Some(first)) = (name, steps.first_mut()) {
+            first.starts.insert(0, name.clone());
+        }
+        steps
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt;
+use std::slice;
+
+use super::{PatchError, Traced, Tree};
+
+/// A change to a subtree. Prefixes are the steps before the subtree in the old
+/// neighborhood, and paths are given below the prefix.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Edit<T> {
+    /// A subtree only in the new neighborhood.
+    Insert {
+        prefix: Vec<T>,
+        paths: Vec<Vec<T>>,
+    },
+    /// A subtree only in the old neighborhood.
+    Remove {
+        prefix: Vec<T>,
+        paths: Vec<Vec<T>>,
+    },
+    /// A subtree whose first steps changed.
+    Replace {
+        prefix: Vec<T>,
+        old: Vec<Vec<T>>,
+        new: Vec<Vec<T>>,
+    },
+    /// An offshoot that moved to another trace, unchanged. The prefix holds
+    /// the steps before the trace.
+    Shift {
+        prefix: Vec<T>,
+        from: usize,
+        to: usize,
+        paths: Vec<Vec<T>>,
+    },
+}
+
+/// Edits that turn one neighborhood into another, in the order of its trees.
+/// `Display` draws the edits within the trees they change.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diff<T> {
+    pub edits: Vec<Edit<T>>,
+}
+
+impl<T> Diff<T> {
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+}
+
+impl<T: Traced + Clone> Edit<T> {
+    /// The steps before the old subtree, and its paths.
+    fn old(&self) -> Option<(Vec<T>, &[Vec<T>])> {
+        match self {
+            &Edit::Insert { .. } => None,
+            &Edit::Remove { ref prefix, ref paths } => Some((prefix.clone(), &paths[..])),
+            &Edit::Replace { ref prefix, ref old, .. } => Some((prefix.clone(), &old[..])),
+            &Edit::Shift { ref prefix, from, ref paths, .. } => Some((traced(prefix, from), &paths[..])),
+        }
+    }
+}
+
+/// Compares the trees of two neighborhoods. Offshoots are matched first when
+/// they are equal, which finds those that moved to another trace, and then by
+/// their trace. Roots are matched the same way, by their position.
+pub fn diff<T, P, Q>(old: &[P], new: &[Q]) -> Diff<T>
+    where T: Traced + PartialEq + Clone, P: AsRef<[T]>, Q: AsRef<[T]>
+{
+    let roots = |trees: Vec<Tree<T>>| trees.into_iter().enumerate().collect::<Vec<_>>();
+    let mut edits = vec![];
+    offshoots(&[], &roots(Tree::from_paths(old)), &roots(Tree::from_paths(new)), false, &mut edits);
+    Diff { edits }
+}
+
+fn subtree<T: Traced + PartialEq + Clone>(prefix: Vec<T>, old: &Tree<T>, new: &Tree<T>, edits: &mut Vec<Edit<T>>) {
+    if old == new {
+        return;
+    }
+    if old.steps != new.steps {
+        edits.push(Edit::Replace { prefix, old: paths(old), new: paths(new) });
+        return;
+    }
+    let mut node = prefix;
+    node.extend(old.steps.iter().cloned());
+    offshoots(&node, &old.children, &new.children, true, edits);
+}
+
+/// Matches the offshoots of a node by their traces, or the roots by their
+/// positions if they are not traced.
+fn offshoots<T>(node: &[T], old: &[(usize, Tree<T>)], new: &[(usize, Tree<T>)], is_traced: bool, edits: &mut Vec<Edit<T>>)
+    where T: Traced + PartialEq + Clone
+{
+    let mut matches: Vec<Option<usize>> = vec![None; old.len()];
+    let mut used = vec![false; new.len()];
+    let mut pair = |condition: &dyn Fn(usize, usize) -> bool| {
+        for i in 0 .. old.len() {
+            if matches[i].is_none() {
+                if let Some(j) = (0 .. new.len()).find(|&j| !used[j] && condition(i, j)) {
+                    matches[i] = Some(j);
+                    used[j] = true;
+                }
+            }
+        }
+    };
+    pair(&|i, j| old[i] == new[j]);
+    pair(&|i, j| old[i].1 == new[j].1);
+    pair(&|i, j| old[i].0 == new[j].0);
+    let slot = |idx| if is_traced { traced(node, idx) } else { node.to_vec() };
+    for (i, &(idx, ref tree)) in old.iter().enumerate() {
+        match matches[i] {
+            Some(j) if new[j].1 == *tree => {
+                if is_traced && new[j].0 != idx {
+                    edits.push(Edit::Shift { prefix: node.to_vec(), from: idx, to: new[j].0, paths: paths(tree) });
+                }
+            }
+            Some(j) => subtree(slot(idx), tree, &new[j].1, edits),
+            None => edits.push(Edit::Remove { prefix: slot(idx), paths: paths(tree) }),
+        }
+    }
+    for (j, &(idx, ref tree)) in new.iter().enumerate() {
+        if !used[j] {
+            edits.push(Edit::Insert { prefix: slot(idx), paths: paths(tree) });
+        }
+    }
+}
+
+fn paths<T: Traced + PartialEq + Clone>(tree: &Tree<T>) -> Vec<Vec<T>> {
+    Tree::to_paths(slice::from_ref(tree))
+}
+
+fn traced<T: Traced + Clone>(prefix: &[T], idx: usize) -> Vec<T> {
+    let mut steps = prefix.to_vec();
+    steps.push(T::from_trace(idx));
+    steps
+}
+
+fn prefixed<T: Clone>(prefix: &[T], paths: &[Vec<T>]) -> Vec<Vec<T>> {
+    paths.iter().map(|path| prefix.iter().chain(path).cloned().collect()).collect()
+}
+
+/// Applies the edits of a diff to the old neighborhood. Replaced subtrees take
+/// the place of their first path. Inserted offshoots go after the offshoots of
+/// their node with lower traces, and inserted roots come last.
+pub fn patch<T, P>(old: &[P], diff: &Diff<T>) -> Result<Vec<Vec<T>>, PatchError>
+    where T: Traced + PartialEq + Clone + fmt::Debug, P: AsRef<[T]>
+{
+    let old: Vec<&[T]> = old.iter().map(|path| path.as_ref()).collect();
+    // The edit that changes each path, if any.
+    let mut edited: Vec<Option<usize>> = vec![None; old.len()];
+    for (e, edit) in diff.edits.iter().enumerate() {
+        if let Some((prefix, paths)) = edit.old() {
+            for suffix in paths {
+                let found = (0 .. old.len()).find(|&p| {
+                    edited[p].is_none()
+                        && old[p].len() == prefix.len() + suffix.len()
+                        && old[p].starts_with(&prefix)
+                        && old[p].ends_with(suffix)
+                });
+                match found {
+                    Some(p) => edited[p] = Some(e),
+                    None => {
+                        let path: Vec<&T> = prefix.iter().chain(suffix).collect();
+                        return Err(PatchError { edit: e, message: format!("no path {:?}", path) });
+                    }
+                }
+            }
+        }
+    }
+    let mut patched = vec![];
+    let mut replaced = vec![false; diff.edits.len()];
+    for (path, edit) in old.iter().zip(edited) {
+        let e = match edit {
+            Some(e) => e,
+            None => {
+                patched.push(path.to_vec());
+                continue;
+            }
+        };
+        match &diff.edits[e] {
+            &Edit::Replace { ref prefix, ref new, .. } if !replaced[e] => {
+                patched.extend(prefixed(prefix, new));
+                replaced[e] = true;
+            }
+            &Edit::Shift { ref prefix, to, .. } => {
+                let mut path = path.to_vec();
+                path[prefix.len()] = T::from_trace(to);
+                patched.push(path);
+            }
+            _ => {}
+        }
+    }
+    for edit in &diff.edits {
+        if let &Edit::Insert { ref prefix, ref paths } = edit {
+            let at = insertion(&patched, prefix);
+            patched.splice(at .. at, prefixed(prefix, paths));
+        }
+    }
+    Ok(patched)
+}
+
+/// Where paths inserted under the prefix go: after the paths of the node's
+/// offshoots with lower traces, else before its first path, else last.
+fn insertion<T: Traced + PartialEq>(paths: &[Vec<T>], prefix: &[T]) -> usize {
+    let (node, idx) = match prefix.split_last() {
+        Some((step, node)) if step.trace().is_some() => (node, step.trace()),
+        _ => return paths.len(),
+    };
+    let under: Vec<usize> = (0 .. paths.len())
+        .filter(|&p| paths[p].len() > node.len() && paths[p].starts_with(node))
+        .collect();
+    match under.iter().rev().find(|&&p| paths[p][node.len()].trace() < idx) {
+        Some(&p) => p + 1,
+        None => under.first().cloned().unwrap_or(paths.len()),
+    }
+}
+
+impl<T: Traced + PartialEq + Clone + fmt::Debug> fmt::Display for Diff<T> {
+    /// Draws one node per line, indented by the number of traces before it.
+    /// Lines of removed subtrees start with `-`, of inserted ones with `+`, and
+    /// of moved ones with `~`. Nodes above the edits are drawn once.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut drawn: Vec<String> = vec![];
+        for edit in &self.edits {
+            let (prefix, lead) = match edit {
+                &Edit::Shift { ref prefix, .. } => (&prefix[..], String::new()),
+                &Edit::Insert { ref prefix, .. } | &Edit::Remove { ref prefix, .. } | &Edit::Replace { ref prefix, .. } => {
+                    match prefix.last() {
+                        Some(step) if step.trace().is_some() => (&prefix[.. prefix.len() - 1], format!("{:?} ", step)),
+                        _ => (&prefix[..], String::new()),
+                    }
+                }
+            };
+            let context = nodes(prefix);
+            let common = drawn.iter().zip(&context).take_while(|&(drawn, node)| drawn == node).count();
+            for (depth, node) in context.iter().enumerate().skip(common) {
+                writeln!(f, "  {}{}", indent(depth), node)?;
+            }
+            let depth = context.len();
+            drawn = context;
+            match edit {
+                &Edit::Insert { ref paths, .. } => draw(f, '+', depth, &lead, paths)?,
+                &Edit::Remove { ref paths, .. } => draw(f, '-', depth, &lead, paths)?,
+                &Edit::Replace { ref old, ref new, .. } => {
+                    draw(f, '-', depth, &lead, old)?;
+                    draw(f, '+', depth, &lead, new)?;
+                }
+                &Edit::Shift { from, to, ref paths, .. } => {
+                    let lead = format!("{:?} -> {:?} ", T::from_trace(from), T::from_trace(to));
+                    draw(f, '~', depth, &lead, paths)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The nodes that the steps go through, each starting with its trace.
+fn nodes<T: Traced + fmt::Debug>(steps: &[T]) -> Vec<String> {
+    let mut nodes: Vec<Vec<String>> = vec![];
+    for step in steps {
+        if nodes.is_empty() || step.trace().is_some() {
+            nodes.push(vec![]);
+        }
+        nodes.last_mut().unwrap().push(format!("{:?}", step));
+    }
+    nodes.into_iter().map(|node| node.join(" ")).collect()
+}
+
+fn draw<T>(f: &mut fmt::Formatter, marker: char, depth: usize, lead: &str, paths: &[Vec<T>]) -> fmt::Result
+    where T: Traced + PartialEq + Clone + fmt::Debug
+{
+    fn node<T: Traced + fmt::Debug>(f: &mut fmt::Formatter, marker: char, depth: usize, lead: &str, tree: &Tree<T>) -> fmt::Result {
+        let steps: Vec<String> = tree.steps.iter().map(|step| format!("{:?}", step)).collect();
+        writeln!(f, "{} {}{}{}", marker, indent(depth), lead, steps.join(" "))?;
+        for &(idx, ref child) in &tree.children {
+            node(f, marker, depth + 1, &format!("{:?} ", T::from_trace(idx)), child)?;
+        }
+        Ok(())
+    }
+    for tree in &Tree::from_paths(paths) {
+        node(f, marker, depth, lead, tree)?;
+    }
+    Ok(())
+}
+
+fn indent(depth: usize) -> String {
+    "  ".repeat(depth)
+}
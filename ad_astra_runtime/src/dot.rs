@@ -0,0 +1,160 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+
+use cfg::Symbol;
+
+use super::{NeighborhoodRuntime, TerminalKind, Traced, Tree, ValidationError};
+
+impl<T: Traced + PartialEq + Clone + fmt::Debug> NeighborhoodRuntime<T> {
+    /// Draws the trees that the paths go through, in the DOT language. Nodes
+    /// are labeled with their steps and edges with trace indices. Nodes where
+    /// validation fails, and everything below them, are drawn in red.
+    pub fn to_dot<P: AsRef<[T]>>(&self, paths: &[P]) -> String {
+        let trees = Tree::from_paths(paths);
+        let errors = self.validate(paths).err().unwrap_or_else(Vec::new);
+        let invalid: Vec<*const Tree<T>> = errors.iter().filter_map(|error: &ValidationError|
+            locate(trees.iter().collect(), paths[error.path].as_ref(), error.step)
+        ).map(|tree| tree as *const _).collect();
+        let mut dot = String::new();
+        writeln!(dot, "digraph neighborhood {{").unwrap();
+        writeln!(dot, "    node [shape=box];").unwrap();
+        let mut next = 0;
+        for tree in &trees {
+            write_tree(&mut dot, tree, &invalid, false, &mut next);
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+/// The node that holds the given step of the path, or the node whose trace
+/// steps lead to it.
+fn locate<'a, T: Traced + PartialEq>(nodes: Vec<&'a Tree<T>>, path: &[T], step: usize) -> Option<&'a Tree<T>> {
+    for node in nodes {
+        let len = node.steps.len();
+        if path.len() < len || path[.. len] != node.steps[..] {
+            continue;
+        }
+        if step <= len || path.len() == len {
+            return Some(node);
+        }
+        let idx = match path[len].trace() {
+            Some(idx) => idx,
+            None => continue,
+        };
+        let children = node.children.iter().filter(|&&(child, _)| child == idx).map(|&(_, ref child)| child).collect();
+        if let Some(found) = locate(children, &path[len + 1 ..], step - len - 1) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+fn write_tree<T: fmt::Debug>(
+    dot: &mut String,
+    tree: &Tree<T>,
+    invalid: &[*const Tree<T>],
+    mut highlight: bool,
+    next: &mut usize,
+) -> usize {
+    let id = *next;
+    *next += 1;
+    highlight = highlight || invalid.contains(&(tree as *const _));
+    let label: Vec<String> = tree.steps.iter().map(|step| format!("{:?}", step)).collect();
+    write!(dot, "    n{} [label=\"{}\"", id, escape(&label.join("\n"))).unwrap();
+    if highlight {
+        write!(dot, ", color=red, fontcolor=red").unwrap();
+    }
+    writeln!(dot, "];").unwrap();
+    for &(idx, ref child) in &tree.children {
+        let child_id = write_tree(dot, child, invalid, highlight, next);
+        writeln!(dot, "    n{} -> n{} [label=\"{}\"];", id, child_id, idx).unwrap();
+    }
+    id
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl<T> NeighborhoodRuntime<T> {
+    /// Draws the processed grammar in the DOT language. Each nonterminal has
+    /// an edge to each of its productions, which are labeled with the steps they
+    /// match, and each production has an edge to the nonterminal of each offshoot.
+    pub fn grammar_to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph grammar {{").unwrap();
+        for name in self.nonterminals.keys() {
+            writeln!(dot, "    \"{}\" [shape=ellipse];", escape(name)).unwrap();
+        }
+        for (i, &(stmt, ref lhs, rhs)) in self.productions.iter().enumerate() {
+            let mut labels = vec![];
+            let mut edges = vec![];
+            self.walk(rhs, None, &mut BTreeSet::new(), &mut labels, &mut edges);
+            let label = format!("rule {}\n{}", stmt, labels.join(" "));
+            writeln!(dot, "    p{} [shape=box, label=\"{}\"];", i, escape(&label)).unwrap();
+            writeln!(dot, "    \"{}\" -> p{};", escape(lhs), i).unwrap();
+            for (name, offshoot) in edges {
+                match offshoot {
+                    Some(idx) => writeln!(dot, "    p{} -> \"{}\" [label=\"^{}\"];", i, escape(&name), idx).unwrap(),
+                    None => writeln!(dot, "    p{} -> \"{}\" [style=dashed];", i, escape(&name)).unwrap(),
+                }
+            }
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    /// Collects the steps matched by a production and the nonterminals it
+    /// refers to, with the trace index of the offshoot they are under.
+    fn walk(
+        &self,
+        sym: Symbol,
+        offshoot: Option<usize>,
+        visited: &mut BTreeSet<(Symbol, Option<usize>)>,
+        labels: &mut Vec<String>,
+        edges: &mut Vec<(String, Option<usize>)>,
+    ) {
+        if !visited.insert((sym, offshoot)) {
+            return;
+        }
+        if let Some((name, _)) = self.nonterminals.iter().find(|&(_, &nonterminal)| nonterminal == sym) {
+            if !edges.contains(&(name.clone(), offshoot)) {
+                edges.push((name.clone(), offshoot));
+            }
+            return;
+        }
+        if let Some(&(_, ref terminal)) = self.terminals.iter().find(|&&(terminal, _)| terminal == sym) {
+            if let TerminalKind::Leaf(ref matcher) = terminal.kind {
+                let name = self.matcher_name(matcher, &terminal.env);
+                let name = match offshoot {
+                    Some(idx) => format!("^{}: {}", idx, name),
+                    None => name,
+                };
+                if !labels.contains(&name) {
+                    labels.push(name);
+                }
+            }
+            return;
+        }
+        for &(lhs, ref rhs) in &self.rules {
+            if lhs != sym {
+                continue;
+            }
+            let trace = rhs.first().and_then(|&first|
+                self.terminals.iter().find(|&&(terminal, _)| terminal == first)
+            ).and_then(|&(_, ref terminal)| match terminal.kind {
+                TerminalKind::Trace { idx, .. } => Some(idx),
+                TerminalKind::Leaf(_) => None,
+            });
+            match trace {
+                Some(idx) => self.walk(rhs[1], Some(idx), visited, labels, edges),
+                None => {
+                    for &child in rhs {
+                        self.walk(child, offshoot, visited, labels, edges);
+                    }
+                }
+            }
+        }
+    }
+}
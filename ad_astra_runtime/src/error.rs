@@ -0,0 +1,379 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt;
+
+/// A path of a neighborhood rejected by the grammar.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ValidationError {
+    /// Index of the rejected path.
+    pub path: usize,
+    /// Index of the step where the recognizer stopped accepting. Equal to the
+    /// path's length when the path ends too early.
+    pub step: usize,
+    /// The offending step, formatted with `Debug`. `None` when the path ends
+    /// too early.
+    pub found: Option<String>,
+    /// Names of matchers that would have been accepted at this step.
+    pub expected: BTreeSet<String>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.found {
+            Some(ref found) => write!(f, "path {}, step {}: unexpected {}", self.path, self.step, found)?,
+            None => write!(f, "path {}, step {}: unexpected end of path", self.path, self.step)?,
+        }
+        if !self.expected.is_empty() {
+            let expected: Vec<_> = self.expected.iter().map(|name| &name[..]).collect();
+            write!(f, "; expected one of: {}", expected.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ValidationError {}
+
+/// Offshoots of a single node that bind a `for<T>` parameter to different types.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnificationError {
+    /// Index of a path that goes through the node.
+    pub path: usize,
+    /// Index of the node's step within that path.
+    pub step: usize,
+    /// Name of the type parameter, e.g. `T`.
+    pub param: String,
+    /// Nonterminals that disagree on the parameter, e.g. `Expr<bool>` and `Expr<isize>`.
+    pub types: Vec<String>,
+}
+
+impl fmt::Display for UnificationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let types: Vec<_> = self.types.iter().map(|name| &name[..]).collect();
+        write!(f, "path {}, step {}: cannot unify {}: {}", self.path, self.step, self.param, types.join(" vs "))
+    }
+}
+
+impl Error for UnificationError {}
+
+/// A node whose type is not determined by its offshoots.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AmbiguityError {
+    /// Index of a path that goes through the node.
+    pub path: usize,
+    /// Index of the node's step within that path.
+    pub step: usize,
+    /// Every nonterminal the node could be derived from.
+    pub types: Vec<String>,
+}
+
+impl fmt::Display for AmbiguityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let types: Vec<_> = self.types.iter().map(|name| &name[..]).collect();
+        write!(f, "path {}, step {}: ambiguous type: {}", self.path, self.step, types.join(" or "))
+    }
+}
+
+impl Error for AmbiguityError {}
+
+/// A node whose offshoots do not match the arity of its production.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ArityError {
+    /// Index of a path that goes through the node.
+    pub path: usize,
+    /// Index of the node's step within that path.
+    pub step: usize,
+    /// Steps leading to the node's offshoots, formatted with `Debug`.
+    pub prefix: String,
+    /// Number of offshoots of the production.
+    pub arity: usize,
+    /// Trace indices with no paths.
+    pub missing: Vec<usize>,
+    /// Trace indices beyond the arity.
+    pub extra: Vec<usize>,
+}
+
+impl fmt::Display for ArityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "path {}, step {}: {} expects {} offshoots", self.path, self.step, self.prefix, self.arity)?;
+        if !self.missing.is_empty() {
+            write!(f, "; missing {:?}", self.missing)?;
+        }
+        if !self.extra.is_empty() {
+            write!(f, "; extra {:?}", self.extra)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ArityError {}
+
+/// A tree that does not have the shape of any variant of a typed enum.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConversionError {
+    /// The steps of the node that failed to convert, formatted with `Debug`.
+    pub found: String,
+    /// The typed enum, e.g. `ExprBool`.
+    pub expected: String,
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot convert {} into {}", self.found, self.expected)
+    }
+}
+
+impl Error for ConversionError {}
+
+/// Text that cannot be read as s-expressions of steps.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SexprError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SexprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for SexprError {}
+
+/// Text that cannot be read as a query.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct QueryError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl Error for QueryError {}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NeighborhoodError {
+    Validation(ValidationError),
+    Unification(UnificationError),
+    Ambiguity(AmbiguityError),
+    Arity(ArityError),
+}
+
+impl fmt::Display for NeighborhoodError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &NeighborhoodError::Validation(ref error) => error.fmt(f),
+            &NeighborhoodError::Unification(ref error) => error.fmt(f),
+            &NeighborhoodError::Ambiguity(ref error) => error.fmt(f),
+            &NeighborhoodError::Arity(ref error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for NeighborhoodError {}
+
+impl NeighborhoodError {
+    /// Index of the path the error is about.
+    pub(crate) fn path_mut(&mut self) -> &mut usize {
+        match self {
+            &mut NeighborhoodError::Validation(ref mut error) => &mut error.path,
+            &mut NeighborhoodError::Unification(ref mut error) => &mut error.path,
+            &mut NeighborhoodError::Ambiguity(ref mut error) => &mut error.path,
+            &mut NeighborhoodError::Arity(ref mut error) => &mut error.path,
+        }
+    }
+}
+
+impl From<ValidationError> for NeighborhoodError {
+    fn from(error: ValidationError) -> Self {
+        NeighborhoodError::Validation(error)
+    }
+}
+
+impl From<UnificationError> for NeighborhoodError {
+    fn from(error: UnificationError) -> Self {
+        NeighborhoodError::Unification(error)
+    }
+}
+
+impl From<AmbiguityError> for NeighborhoodError {
+    fn from(error: AmbiguityError) -> Self {
+        NeighborhoodError::Ambiguity(error)
+    }
+}
+
+impl From<ArityError> for NeighborhoodError {
+    fn from(error: ArityError) -> Self {
+        NeighborhoodError::Arity(error)
+    }
+}
+
+/// A rewrite rule that cannot be used, or a rewrite that failed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RewriteError {
+    /// A rule with a misplaced or unbound variable.
+    Rule {
+        rule: usize,
+        message: String,
+    },
+    /// The neighborhood is invalid before rewriting, or a rewrite made it
+    /// invalid.
+    Invalid(Vec<NeighborhoodError>),
+    /// A rewrite that changed the nonterminal of the rewritten subtree.
+    Type {
+        rule: usize,
+        /// The steps before the subtree, formatted with `Debug`.
+        prefix: String,
+        expected: String,
+        found: String,
+    },
+    /// Rewriting did not reach a fixpoint within the limit.
+    Limit {
+        rewrites: usize,
+    },
+}
+
+impl fmt::Display for RewriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &RewriteError::Rule { rule, ref message } => write!(f, "rewrite rule {}: {}", rule, message),
+            &RewriteError::Invalid(ref errors) => {
+                let messages: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+                write!(f, "invalid neighborhood while rewriting: {}", messages.join("; "))
+            }
+            &RewriteError::Type { rule, ref prefix, ref expected, ref found } => {
+                write!(f, "rewrite rule {} at {} turned {} into {}", rule, prefix, expected, found)
+            }
+            &RewriteError::Limit { rewrites } => write!(f, "no fixpoint after {} rewrites", rewrites),
+        }
+    }
+}
+
+impl Error for RewriteError {}
+
+/// An edit of a diff that does not apply to the neighborhood.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PatchError {
+    /// Index of the edit.
+    pub edit: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "edit {}: {}", self.edit, self.message)
+    }
+}
+
+impl Error for PatchError {}
+
+/// A subtree that cannot be extracted, grafted or replaced.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SpliceError {
+    /// No path goes through the prefix.
+    Missing {
+        prefix: String,
+    },
+    /// The neighborhood is invalid after splicing.
+    Invalid(Vec<NeighborhoodError>),
+    /// A subtree whose nonterminal does not fit the slot under the prefix.
+    Type {
+        /// The steps before the subtree, formatted with `Debug`.
+        prefix: String,
+        expected: String,
+        found: String,
+    },
+}
+
+impl fmt::Display for SpliceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &SpliceError::Missing { ref prefix } => write!(f, "no subtree at {}", prefix),
+            &SpliceError::Invalid(ref errors) => {
+                let messages: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+                write!(f, "invalid neighborhood after splicing: {}", messages.join("; "))
+            }
+            &SpliceError::Type { ref prefix, ref expected, ref found } => {
+                write!(f, "a subtree of {} does not fit at {}, which expects {}", found, prefix, expected)
+            }
+        }
+    }
+}
+
+impl Error for SpliceError {}
+
+/// A problem with the rules given to `NeighborhoodRuntime`, found by `process_rules`,
+/// or with the text of a grammar.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GrammarError {
+    /// A nonterminal that is used, but has no rules for its type.
+    Undefined {
+        name: String,
+    },
+    /// A rule that never derives a path ending in a pattern.
+    Unproductive {
+        rule: usize,
+        lhs: String,
+    },
+    /// A rule that cannot be reached from the allowed nonterminals.
+    Unreachable {
+        rule: usize,
+        lhs: String,
+    },
+    /// A `for<T>` rule whose nonterminal is never applied to a type that can
+    /// be reached from the allowed nonterminals.
+    NeverInstantiated {
+        rule: usize,
+        lhs: String,
+    },
+    /// A `for<T>` whose parameter is never applied.
+    UnusedParam {
+        rule: usize,
+        param: String,
+    },
+    /// Grammar text that cannot be parsed.
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A grammar file that cannot be read.
+    Io {
+        path: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &GrammarError::Undefined { ref name } => {
+                write!(f, "`{}` is used but never defined", name)
+            }
+            &GrammarError::Unproductive { rule, ref lhs } => {
+                write!(f, "rule {}: `{}` never bottoms out in a pattern", rule, lhs)
+            }
+            &GrammarError::Unreachable { rule, ref lhs } => {
+                write!(f, "rule {}: `{}` is unreachable from the allowed nonterminals", rule, lhs)
+            }
+            &GrammarError::NeverInstantiated { rule, ref lhs } => {
+                write!(f, "rule {}: `{}` is never instantiated with a reachable type", rule, lhs)
+            }
+            &GrammarError::UnusedParam { rule, ref param } => {
+                write!(f, "rule {}: type parameter `{}` is never used", rule, param)
+            }
+            &GrammarError::Syntax { line, column, ref message } => {
+                write!(f, "line {}, column {}: {}", line, column, message)
+            }
+            &GrammarError::Io { ref path, ref message } => {
+                write!(f, "{}: {}", path, message)
+            }
+        }
+    }
+}
+
+impl Error for GrammarError {}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use cfg::Symbol;
+
+use super::{NeighborhoodRuntime, Terminal, TerminalKind, Traced};
+
+impl<T: Traced + Clone> NeighborhoodRuntime<T> {
+    /// Generates the paths of a random neighborhood that the grammar accepts.
+    /// `choose(n)` picks one of `n` choices, and `step` makes a step that the
+    /// named variant or pattern matches. Once `depth` nonterminals are nested,
+    /// only the choices that end soonest are taken. A grammar whose start never
+    /// finishes generates no paths.
+    pub fn generate<C, S>(&self, depth: usize, mut choose: C, mut step: S) -> Vec<Vec<T>>
+        where C: FnMut(usize) -> usize,
+              S: FnMut(&str) -> T,
+    {
+        let start = self.start.expect("rules must be processed first");
+        let mut generator = Generator {
+            runtime: self,
+            heights: self.heights(),
+            choose: &mut choose,
+            step: &mut step,
+        };
+        generator.paths(start, depth)
+    }
+
+    fn terminal(&self, sym: Symbol) -> Option<&Terminal<T>> {
+        self.terminals.iter().find(|&&(terminal, _)| terminal == sym).map(|&(_, ref terminal)| terminal)
+    }
+
+    /// Whether the rules of the symbol are the offshoots of a node, each
+    /// beginning with a trace.
+    fn is_branch(&self, rhs: &[Symbol]) -> bool {
+        rhs.len() == 2 && self.terminal(rhs[0]).map_or(false, |terminal| match terminal.kind {
+            TerminalKind::Trace { .. } => true,
+            TerminalKind::Leaf(_) => false,
+        })
+    }
+
+    /// How many nested rules it takes at least to finish each symbol. Offshoots
+    /// are taken together, so a branching symbol takes as long as its deepest
+    /// offshoot.
+    fn heights(&self) -> BTreeMap<Symbol, usize> {
+        let mut heights: BTreeMap<Symbol, usize> = self.terminals.iter().map(|&(sym, _)| (sym, 0)).collect();
+        loop {
+            let mut changed = false;
+            for &(lhs, ref rhs) in &self.rules {
+                let rule_height = |rhs: &[Symbol]| rhs.iter().map(|sym| heights.get(sym).cloned())
+                    .collect::<Option<Vec<_>>>()
+                    .map(|rhs_heights| 1 + rhs_heights.into_iter().max().unwrap_or(0));
+                let height = if self.is_branch(rhs) {
+                    self.rules.iter().filter(|&&(other, _)| other == lhs)
+                        .map(|&(_, ref rhs)| rule_height(rhs))
+                        .collect::<Option<Vec<_>>>()
+                        .and_then(|branches| branches.into_iter().max())
+                } else {
+                    rule_height(rhs)
+                };
+                let height = match height {
+                    Some(height) => height,
+                    None => continue,
+                };
+                if heights.get(&lhs).map_or(true, |&old| height < old) {
+                    heights.insert(lhs, height);
+                    changed = true;
+                }
+            }
+            if !changed {
+                return heights;
+            }
+        }
+    }
+}
+
+struct Generator<'a, T: 'a> {
+    runtime: &'a NeighborhoodRuntime<T>,
+    heights: BTreeMap<Symbol, usize>,
+    choose: &'a mut dyn FnMut(usize) -> usize,
+    step: &'a mut dyn FnMut(&str) -> T,
+}
+
+impl<'a, T: Traced + Clone> Generator<'a, T> {
+    fn paths(&mut self, sym: Symbol, depth: usize) -> Vec<Vec<T>> {
+        let runtime = self.runtime;
+        if let Some(terminal) = runtime.terminal(sym) {
+            let step = match terminal.kind {
+                TerminalKind::Trace { idx, .. } => T::from_trace(idx),
+                TerminalKind::Leaf(ref matcher) => {
+                    (self.step)(&runtime.matcher_name(matcher, &terminal.env))
+                }
+            };
+            return vec![vec![step]];
+        }
+        let depth = if runtime.nonterminals.values().any(|&nonterminal| nonterminal == sym) {
+            depth.saturating_sub(1)
+        } else {
+            depth
+        };
+        let rules: Vec<&[Symbol]> = runtime.rules.iter().filter(|&&(lhs, ref rhs)|
+            lhs == sym && rhs.iter().all(|sym| self.heights.contains_key(sym))
+        ).map(|&(_, ref rhs)| &rhs[..]).collect();
+        // Rules of symbols that never finish are left out above, so only a
+        // symbol that never finishes itself has none to choose from.
+        if rules.is_empty() {
+            return vec![];
+        }
+        if rules.iter().any(|rhs| runtime.is_branch(rhs)) {
+            let mut paths = vec![];
+            for rhs in rules {
+                paths.extend(self.sequence(rhs, depth));
+            }
+            return paths;
+        }
+        let rules: Vec<&[Symbol]> = if depth == 0 {
+            let height = |rhs: &&[Symbol]| rhs.iter().map(|sym| self.heights[sym]).max().unwrap_or(0);
+            let min = rules.iter().map(&height).min().unwrap_or(0);
+            rules.iter().cloned().filter(|rhs| height(rhs) == min).collect()
+        } else {
+            rules
+        };
+        let rhs = rules[(self.choose)(rules.len())];
+        self.sequence(rhs, depth)
+    }
+
+    /// Joins every path of each symbol with every path of the next.
+    fn sequence(&mut self, rhs: &[Symbol], depth: usize) -> Vec<Vec<T>> {
+        let mut paths = vec![vec![]];
+        for &sym in rhs {
+            let suffixes = self.paths(sym, depth);
+            let mut joined = vec![];
+            for prefix in &paths {
+                for suffix in &suffixes {
+                    let mut path = prefix.clone();
+                    path.extend(suffix.iter().cloned());
+                    joined.push(path);
+                }
+            }
+            paths = joined;
+        }
+        paths
+    }
+}
@@ -0,0 +1,33 @@
+use std::collections::HashMap;
+
+pub struct Interner {
+    map: HashMap<String, usize>,
+    strings: Vec<String>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Interner {
+            map: HashMap::new(),
+            strings: vec![],
+        }
+    }
+
+    pub fn intern(&mut self, string: &str) -> usize {
+        if let Some(&id) = self.map.get(string) {
+            return id;
+        }
+        let id = self.strings.len();
+        self.strings.push(string.to_string());
+        self.map.insert(string.to_string(), id);
+        id
+    }
+
+    pub fn get(&self, id: usize) -> &str {
+        &self.strings[id][..]
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = usize> {
+        0 .. self.strings.len()
+    }
+}
@@ -1,38 +1,136 @@
 extern crate proc_macro;
 extern crate cfg;
-extern crate gearley;
 extern crate internship;
-
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub extern crate serde;
+
+mod analysis;
+mod arity;
+mod chart;
+mod cursor;
+mod derivation;
+mod diff;
+mod dot;
+mod error;
+mod generate;
 mod interner;
 #[macro_use]
 mod macros;
-
-use std::collections::BTreeMap;
+mod print;
+mod query;
+mod rewrite;
+#[cfg(feature = "serde")]
+mod serialize;
+mod sexpr;
+mod splice;
+mod tables;
+mod text;
+mod tree;
+mod trie;
+mod typing;
+mod unify;
+mod visit;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::rc::Rc;
 
 use cfg::earley::Grammar;
 use cfg::Symbol;
-use gearley::grammar::InternalGrammar;
-use gearley::recognizer::Recognizer;
-use gearley::forest::NullForest;
 
+use self::chart::{Chart, EarleySet};
 use self::interner::Interner;
 
+pub use self::cursor::Cursor;
+pub use self::derivation::{Derivation, StepDerivation};
+pub use self::diff::{diff, patch, Diff, Edit};
+pub use self::error::{
+    AmbiguityError, ArityError, ConversionError, GrammarError, NeighborhoodError, PatchError,
+    QueryError, RewriteError, SexprError, SpliceError, UnificationError, ValidationError,
+};
+pub use self::query::Query;
+pub use self::rewrite::{PatternStep, Rewriter};
+#[cfg(feature = "serde")]
+pub use self::serialize::{Validate, Validated};
+pub use self::sexpr::{from_sexpr, to_sexpr};
+pub use self::splice::{graft, replace, subtree};
+#[doc(hidden)]
+pub use self::tables::{Tables, TerminalKindTable, TerminalTable};
+pub use self::text::Predicates;
+pub use self::tree::{FromTree, IntoTree, Tree};
+pub use self::trie::PathTrie;
+pub use self::visit::Node;
+
+/// Steps of a neighborhood that carry the index of an offshoot.
+pub trait Traced {
+    fn trace(&self) -> Option<usize>;
+    fn from_trace(idx: usize) -> Self;
+
+    /// The name of the step's variant, which plain names in a grammar match.
+    /// Without it, the name is read from the derived `Debug` output, so steps
+    /// with a custom `Debug` must return it. `ast!` generates it for the
+    /// variants listed in `#[variants(...)]`.
+    fn variant_name(&self) -> Option<&str> {
+        None
+    }
+
+    /// The names of every variant, if known, which queries check their steps
+    /// against. `ast!` generates it along with `variant_name`.
+    fn variant_names() -> Option<&'static [&'static str]> {
+        None
+    }
+}
+
 pub struct NeighborhoodRuntime<T> {
     stmts: Vec<Neighborhood<T>>,
+    allowed: Vec<Neighborhood<T>>,
     param_map: Interner,
     external_syms: BTreeMap<ExtPath, Symbol>,
+    nonterminals: BTreeMap<String, Symbol>,
+    terminals: Vec<(Symbol, Terminal<T>)>,
+    rules: Vec<(Symbol, Vec<Symbol>)>,
+    productions: Vec<(usize, String, Symbol)>,
+    /// Whether the right-hand side of each rule is a choice of alternatives.
+    alternatives: Vec<bool>,
+    start: Option<Symbol>,
     external_grammar: Grammar,
-    internal_grammar: Option<InternalGrammar>,
+    /// The text of the grammar, for a runtime built from precompiled tables
+    /// rather than from rules.
+    precompiled: Option<&'static str>,
+}
+
+struct Terminal<T> {
+    kind: TerminalKind<T>,
+    ext: ExtPath,
+    lhs: String,
+    env: Vec<(String, usize)>,
+    first: bool,
+}
+
+enum TerminalKind<T> {
+    Trace {
+        idx: usize,
+        arity: usize,
+    },
+    Leaf(Matcher<T>),
 }
 
 impl<T> NeighborhoodRuntime<T> {
     pub fn new() -> Self {
         NeighborhoodRuntime {
             stmts: Vec::new(),
+            allowed: Vec::new(),
             param_map: Interner::new(),
             external_syms: BTreeMap::new(),
+            nonterminals: BTreeMap::new(),
+            terminals: Vec::new(),
+            rules: Vec::new(),
+            productions: Vec::new(),
+            alternatives: Vec::new(),
+            start: None,
             external_grammar: Grammar::new(),
-            internal_grammar: None,
+            precompiled: None,
         }
     }
 
@@ -40,30 +138,353 @@ impl<T> NeighborhoodRuntime<T> {
         self.stmts.push(neighborhood);
     }
 
-    pub fn process_rules(&mut self) {
-        let mut neighborhoods = neighborhood.separate_alternatives();
-        for neighborhood in &mut neighborhoods {
-            neighborhood.externalize();
+    pub fn allow(&mut self, neighborhood: Neighborhood<T>) {
+        self.allowed.push(neighborhood);
+    }
+
+    pub fn process_rules(&mut self) -> Result<(), Vec<GrammarError>> {
+        let type_args: Vec<usize> = self.type_args().iter().map(|ty| self.param_map.intern(ty)).collect();
+        let type_args: Vec<usize> = type_args.into_iter().collect::<BTreeSet<_>>().into_iter().collect();
+
+        for stmt in 0 .. self.stmts.len() {
+            let indices = (0 .. self.stmts[stmt].paths.len()).collect();
+            let ext = ExtPath { stmt, steps: vec![] };
+            self.lower_stmt(ext, indices, 0, &mut vec![], &type_args[..]);
+        }
+
+        let start = self.external_grammar.sym();
+        for allowed in 0 .. self.allowed.len() {
+            for name in self.allowed_names(allowed, &type_args[..]) {
+                let nonterminal = self.nonterminal(&name);
+                self.add_rule(start, &[nonterminal]);
+            }
+        }
+        let errors = self.analyze(start);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        self.alternatives = (0 .. self.stmts.len()).map(|rule| self.has_alternatives(rule)).collect();
+        self.external_grammar.set_start(start);
+        self.start = Some(start);
+        Ok(())
+    }
+
+    /// The types that the rules apply nonterminals to.
+    fn type_args(&self) -> Vec<String> {
+        let params = self.param_names();
+        let mut type_args = vec![];
+        for neighborhood in self.stmts.iter().chain(self.allowed.iter()) {
+            for path in &neighborhood.paths {
+                if let Matcher::ParamApply { ref ty_param, .. } = path.matcher {
+                    if !params.contains(ty_param) {
+                        type_args.push(ty_param.clone());
+                    }
+                }
+            }
+        }
+        type_args
+    }
+
+    fn param_names(&self) -> BTreeSet<String> {
+        let mut params = BTreeSet::new();
+        for neighborhood in self.stmts.iter().chain(self.allowed.iter()) {
+            for path in &neighborhood.paths {
+                for step in &path.steps {
+                    if let &Step::IntroduceParam(ref name) = step {
+                        params.insert(name.clone());
+                    }
+                }
+            }
+        }
+        params
+    }
+
+    fn allowed_names(&self, allowed: usize, type_args: &[usize]) -> Vec<String> {
+        let path = &self.allowed[allowed].paths[0];
+        let mut env = vec![];
+        let mut names = vec![];
+        let params: Vec<_> = path.steps.iter().filter_map(|step| match step {
+            &Step::IntroduceParam(ref name) => Some(name.clone()),
+            _ => None,
+        }).collect();
+        if params.is_empty() {
+            names.push(self.matcher_name(&path.matcher, &env));
+        } else {
+            for &ty in type_args {
+                env.push((params[0].clone(), ty));
+                names.push(self.matcher_name(&path.matcher, &env));
+                env.pop();
+            }
+        }
+        names
+    }
+
+    fn lower_stmt(&mut self, ext: ExtPath, indices: Vec<usize>, depth: usize, env: &mut Vec<(String, usize)>, type_args: &[usize]) {
+        let stmt = ext.stmt;
+        let first = self.stmts[stmt].paths[indices[0]].steps[depth].clone();
+        match first {
+            Step::IntroduceParam(name) => {
+                for &ty in type_args {
+                    env.push((name.clone(), ty));
+                    self.lower_stmt(ext.child(ExtStep::IntroduceParam(ty)), indices.clone(), depth + 1, env, type_args);
+                    env.pop();
+                }
+            }
+            Step::Lhs | Step::Rhs => {
+                let (lhs, rhs): (Vec<usize>, Vec<usize>) = indices.into_iter().partition(|&i|
+                    self.stmts[stmt].paths[i].steps[depth] == Step::Lhs
+                );
+                assert_eq!(lhs.len(), 1);
+                let lhs_name = self.matcher_name(&self.stmts[stmt].paths[lhs[0]].matcher, env);
+                let nonterminal = self.nonterminal(&lhs_name);
+                let rhs = self.lower(ext.child(ExtStep::Rhs), rhs, depth + 1, env, type_args, &lhs_name, true);
+                self.add_rule(nonterminal, &[rhs]);
+                self.productions.push((stmt, lhs_name, rhs));
+            }
+            _ => panic!("expected a rule"),
+        }
+    }
+
+    fn lower(
+        &mut self,
+        ext: ExtPath,
+        indices: Vec<usize>,
+        depth: usize,
+        env: &mut Vec<(String, usize)>,
+        type_args: &[usize],
+        lhs: &str,
+        first: bool,
+    ) -> Symbol {
+        let stmt = ext.stmt;
+        let sym = self.external_grammar.sym();
+        self.external_syms.insert(ext.clone(), sym);
+
+        if indices.len() == 1 && self.stmts[stmt].paths[indices[0]].steps.len() == depth {
+            let path = indices[0];
+            let nonterminal = match self.stmts[stmt].paths[path].matcher {
+                Matcher::ParamApply { .. } => true,
+                Matcher::Symbol(ref name) => self.defines(name),
+                Matcher::Pattern { .. } => false,
+            };
+            if nonterminal {
+                let name = self.matcher_name(&self.stmts[stmt].paths[path].matcher, env);
+                let nonterminal = self.nonterminal(&name);
+                self.add_rule(sym, &[nonterminal]);
+            } else {
+                self.terminals.push((sym, Terminal {
+                    kind: TerminalKind::Leaf(self.stmts[stmt].paths[path].matcher.clone()),
+                    ext,
+                    lhs: lhs.to_string(),
+                    env: env.clone(),
+                    first,
+                }));
+            }
+            return sym;
+        }
+
+        let mut children: BTreeMap<Step, Vec<usize>> = BTreeMap::new();
+        for i in indices {
+            let step = self.stmts[stmt].paths[i].steps[depth].clone();
+            children.entry(step).or_insert_with(Vec::new).push(i);
+        }
+        let arity = children.keys().filter(|step| step.is_offshoot()).count();
+        let mut seq = vec![];
+        for (step, indices) in children {
+            match step {
+                Step::Alternative(n) => {
+                    let child = self.lower(ext.child(ExtStep::Alternative(n)), indices, depth + 1, env, type_args, lhs, first);
+                    self.add_rule(sym, &[child]);
+                }
+                Step::Idx(n) => {
+                    let child = self.lower(ext.child(ExtStep::Idx(n)), indices, depth + 1, env, type_args, lhs, first && n == 0);
+                    seq.push(child);
+                }
+                Step::Offshoot(n) => {
+                    let child_ext = ext.child(ExtStep::Offshoot(n));
+                    let trace = self.external_grammar.sym();
+                    self.terminals.push((trace, Terminal {
+                        kind: TerminalKind::Trace { idx: n, arity },
+                        ext: child_ext.clone(),
+                        lhs: lhs.to_string(),
+                        env: env.clone(),
+                        first: false,
+                    }));
+                    let child = self.lower(child_ext, indices, depth + 1, env, type_args, lhs, false);
+                    self.add_rule(sym, &[trace, child]);
+                }
+                Step::Repeat => {
+                    let child = self.lower(ext.child(ExtStep::Repeat), indices, depth + 1, env, type_args, lhs, first);
+                    self.add_rule(sym, &[child, sym]);
+                    self.add_rule(sym, &[]);
+                }
+                Step::IntroduceParam(name) => {
+                    for &ty in type_args {
+                        env.push((name.clone(), ty));
+                        let child = self.lower(ext.child(ExtStep::IntroduceParam(ty)), indices.clone(), depth + 1, env, type_args, lhs, first);
+                        self.add_rule(sym, &[child]);
+                        env.pop();
+                    }
+                }
+                Step::Lhs | Step::Rhs => panic!("nested rules are not supported"),
+            }
+        }
+        if !seq.is_empty() {
+            self.add_rule(sym, &seq[..]);
+        }
+        sym
+    }
+
+    fn defines(&self, name: &str) -> bool {
+        self.stmts.iter().any(|stmt| stmt.paths.iter().any(|path|
+            path.steps.last() == Some(&Step::Lhs) && match path.matcher {
+                Matcher::Symbol(ref lhs) => lhs == name,
+                _ => false,
+            }
+        ))
+    }
+
+    fn matcher_name(&self, matcher: &Matcher<T>, env: &[(String, usize)]) -> String {
+        match matcher {
+            &Matcher::Symbol(ref name) => name.clone(),
+            &Matcher::ParamApply { ref rhs, ref ty_param } => {
+                let ty = env.iter().rev().find(|&&(ref param, _)| param == ty_param)
+                    .map_or(&ty_param[..], |&(_, ty)| self.param_map.get(ty));
+                format!("{}<{}>", rhs, ty)
+            }
+            &Matcher::Pattern { ref name, .. } => name.clone(),
+        }
+    }
+
+    fn nonterminal(&mut self, name: &str) -> Symbol {
+        if let Some(&sym) = self.nonterminals.get(name) {
+            return sym;
+        }
+        let sym = self.external_grammar.sym();
+        self.nonterminals.insert(name.to_string(), sym);
+        sym
+    }
+
+    fn add_rule(&mut self, lhs: Symbol, rhs: &[Symbol]) {
+        self.external_grammar.rule(lhs).rhs(rhs);
+        self.rules.push((lhs, rhs.to_vec()));
+    }
+}
+
+impl<T: Traced + fmt::Debug> NeighborhoodRuntime<T> {
+    /// Validates one path on its own, scanning every step of it.
+    pub fn validate_steps(&self, path: usize, steps: &[T]) -> Result<(), ValidationError> {
+        self.recognize(&self.chart(), path, steps).map(|_| ())
+    }
+
+    /// Scans the steps, and returns the Earley sets from the root on.
+    fn recognize(&self, chart: &Chart, path: usize, steps: &[T]) -> Result<Vec<Rc<EarleySet>>, ValidationError> {
+        let mut sets = vec![chart.root()];
+        for (i, step) in steps.iter().enumerate() {
+            match chart.scan(&sets[i], &self.terminal_syms(step)) {
+                Some(set) => sets.push(set),
+                None => return Err(ValidationError {
+                    path,
+                    step: i,
+                    found: Some(format!("{:?}", step)),
+                    expected: self.expected(chart, &sets[i]),
+                }),
+            }
+        }
+        if chart.is_finished(&sets[steps.len()], &sets[0]) {
+            Ok(sets)
+        } else {
+            Err(ValidationError {
+                path,
+                step: steps.len(),
+                found: None,
+                expected: self.expected(chart, &sets[steps.len()]),
+            })
         }
-        let (start, nested, terminal) = self.external_grammar.sym();
-        external.rule(start).rhs([nested, terminal])
-                .rule(nested).rhs([terminal, terminal]);
-        external.set_start(start);
-        let cfg = InternalGrammar::from_grammar(&external);
     }
 
-    pub fn validate_steps(&self, steps: &[T]) {
-        let mut rec = Recognizer::new(&cfg, NullForest);
-        for step in steps {
-            recognizer.scan();
+    pub(crate) fn chart(&self) -> Chart {
+        Chart::new(&self.rules[..], self.start.expect("rules must be processed first"))
+    }
+
+    /// The set after the steps, if the grammar accepts them as a prefix.
+    pub(crate) fn scan_prefix(&self, chart: &Chart, prefix: &[T]) -> Option<Rc<EarleySet>> {
+        prefix.iter().fold(Some(chart.root()), |set, step| {
+            set.and_then(|set| chart.scan(&set, &self.terminal_syms(step)))
+        })
+    }
+
+    /// Names of the steps that the set accepts next, and of the nonterminals
+    /// that such a step may begin.
+    fn expected(&self, chart: &Chart, set: &EarleySet) -> BTreeSet<String> {
+        let next: BTreeSet<Symbol> = chart.predictions(set).map(|(_, sym)| sym).collect();
+        let mut expected = BTreeSet::new();
+        for &(sym, ref terminal) in &self.terminals {
+            if !next.contains(&sym) {
+                continue;
+            }
+            match terminal.kind {
+                TerminalKind::Trace { idx, .. } => {
+                    expected.insert(format!("{:?}", T::from_trace(idx)));
+                }
+                TerminalKind::Leaf(ref matcher) => {
+                    expected.insert(self.matcher_name(matcher, &[]));
+                }
+            }
+            if terminal.first {
+                expected.insert(terminal.lhs.clone());
+            }
+        }
+        expected
+    }
+
+    /// Indices of the terminals that match the step.
+    pub(crate) fn terminal_syms(&self, step: &T) -> BTreeSet<usize> {
+        self.terminals.iter()
+            .filter(|&&(_, ref terminal)| self.matches(terminal, step))
+            .map(|&(sym, _)| sym.usize())
+            .collect()
+    }
+
+    fn matches(&self, terminal: &Terminal<T>, step: &T) -> bool {
+        match terminal.kind {
+            TerminalKind::Trace { idx, .. } => step.trace() == Some(idx),
+            TerminalKind::Leaf(ref matcher) => match *matcher {
+                Matcher::Symbol(ref name) => variant_name(step) == &name[..],
+                Matcher::Pattern { ref func, .. } => func(step),
+                Matcher::ParamApply { .. } => false,
+            },
         }
     }
 }
 
-impl<T> Path<T> {
-    fn segments(&self) -> Vec<Vec<Step>> {
-        self.steps
+/// Spells a type the same way whether it comes from `stringify!` or from a
+/// token stream, which space tokens differently: with a space only between two
+/// words, as in `&'static str`.
+fn type_name(ty: &str) -> String {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut name = String::new();
+    let mut space = false;
+    for c in ty.chars() {
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        if space && is_word(c) && name.chars().last().map_or(false, is_word) {
+            name.push(' ');
+        }
+        space = false;
+        name.push(c);
     }
+    name
+}
+
+fn variant_name<T: Traced + fmt::Debug>(step: &T) -> String {
+    if let Some(name) = step.variant_name() {
+        return name.to_string();
+    }
+    let debug = format!("{:?}", step);
+    let end = debug.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(debug.len());
+    debug[.. end].to_string()
 }
 
 // #[derive(Clone, Eq, PartialEq, Ord, PartialOrd)]
@@ -81,42 +502,47 @@ pub enum Matcher<T> {
         rhs: String,
         ty_param: String,
     },
-    Pattern(Box<dyn Fn(&T) -> bool>),
-
-    // Processed.
-    ExtSymbol(Symbol),
-    ExtParamApply {
-        rhs: Symbol,
-        ty_param: usize,
+    Pattern {
+        name: String,
+        func: Rc<dyn Fn(&T) -> bool>,
     },
 }
 
+impl<T> Clone for Matcher<T> {
+    fn clone(&self) -> Self {
+        match self {
+            &Matcher::Symbol(ref name) => Matcher::Symbol(name.clone()),
+            &Matcher::ParamApply { ref rhs, ref ty_param } => {
+                Matcher::ParamApply { rhs: rhs.clone(), ty_param: ty_param.clone() }
+            }
+            &Matcher::Pattern { ref name, ref func } => Matcher::Pattern { name: name.clone(), func: func.clone() },
+        }
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Step {
     Alternative(usize),
     Idx(usize),
+    Offshoot(usize),
     Repeat,
 
     // Lhs can come after param introduction.
     IntroduceParam(String),
     Lhs,
     Rhs,
-
-    // Processed.
-    ExtIntroduceParam(usize),
-    ExtAlternativeAndIdx(Vec<usize>, usize),
 }
 
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub enum ExtStep {
+    Alternative(usize),
+    Idx(usize),
+    Offshoot(usize),
     Repeat,
 
-    // Lhs can come after param introduction.
-    Lhs,
-    Rhs,
-
     // Processed.
-    AlternativeAndIdx(Vec<usize>, usize),
+    IntroduceParam(usize),
+    Rhs,
 }
 
 pub struct Path<T> {
@@ -126,8 +552,16 @@ pub struct Path<T> {
 
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub struct ExtPath {
+    stmt: usize,
     steps: Vec<ExtStep>,
-    symbol: Symbol,
+}
+
+impl ExtPath {
+    fn child(&self, step: ExtStep) -> ExtPath {
+        let mut steps = self.steps.clone();
+        steps.push(step);
+        ExtPath { stmt: self.stmt, steps }
+    }
 }
 
 pub struct Neighborhood<T> {
@@ -180,6 +614,13 @@ impl Step {
             _ => false,
         }
     }
+
+    fn is_offshoot(&self) -> bool {
+        match self {
+            &Step::Offshoot(_) => true,
+            _ => false,
+        }
+    }
 }
 
 impl<U> Matcher<U> {
@@ -188,11 +629,13 @@ impl<U> Matcher<U> {
     }
 
     pub fn apply(ty: &str, parameter: &str) -> Matcher<U> {
-        Matcher::Param { rhs: ty.to_string(), ty_param: parameter.to_string() }
+        Matcher::ParamApply { rhs: ty.to_string(), ty_param: type_name(parameter) }
     }
 
-    pub fn match_pattern(func: Box<dyn Fn(&U) -> bool>) -> Matcher<U> {
-        Matcher::Pattern(func)
+    pub fn match_pattern<F>(name: &str, func: F) -> Matcher<U>
+        where F: Fn(&U) -> bool + 'static
+    {
+        Matcher::Pattern { name: name.to_string(), func: Rc::new(func) }
     }
 
     pub fn into_neighborhood(self) -> Neighborhood<U> {
@@ -242,7 +685,7 @@ impl<T> Neighborhood<T> {
     }
 
     pub fn or(mut self, mut next: Neighborhood<T>) -> Neighborhood<T> {
-        if !self.paths.iter().all(|path| path.steps.get(0).map_or(false, |step| step.is_idx())) {
+        if !self.paths.iter().all(|path| path.steps.get(0).map_or(false, |step| step.is_alternative())) {
             for path in &mut self.paths {
                 path.steps.insert(0, Step::Alternative(0));
             }
@@ -261,25 +704,25 @@ impl<T> Neighborhood<T> {
     }
 
     pub fn offshoot(mut self, mut next: Neighborhood<T>) -> Neighborhood<T> {
-        if !self.paths.iter().all(|path| path.steps.get(0).map_or(false, |step| step.is_idx())) {
+        if !self.paths.iter().all(|path| path.steps.get(0).map_or(false, |step| step.is_offshoot())) {
             for path in &mut self.paths {
-                path.steps.insert(0, Step::Alternative(0));
+                path.steps.insert(0, Step::Offshoot(0));
             }
         }
         let last_num = self.paths.iter().map(|path|
             match path.steps[0] {
-                Step::Alternative(n) => n,
+                Step::Offshoot(n) => n,
                 _ => unreachable!()
             }
         ).max().map_or(-1, |n| n as isize);
         for path in &mut next.paths {
-            path.steps.insert(0, Step::Alternative((last_num + 1) as usize));
+            path.steps.insert(0, Step::Offshoot((last_num + 1) as usize));
         }
         self.paths.extend(next.paths.into_iter());
         self
     }
 
-    pub fn lhs_then(mut self, mut rhs: Neighborhood<T>) -> Self {
+    pub fn lhs_then(mut self, rhs: Neighborhood<T>) -> Self {
         assert_eq!(self.paths.len(), 1);
         for lhs_path in &mut self.paths {
             lhs_path.steps.insert(0, Step::Lhs);
@@ -288,105 +731,6 @@ impl<T> Neighborhood<T> {
             rhs_path.steps.insert(0, Step::Rhs);
             self.paths.push(rhs_path);
         }
-        rhs
-    }
-
-    fn separate_alternatives(self) -> Vec<Neighborhood<T>> {
-
-    }
-
-    fn externalize(&mut self) {
-        let mut new_paths = vec![];
-        for path in &mut self.paths {
-            for i in 0 .. path.steps.len() {
-                match path.steps[i] {
-                    Step::Rhs => {
-                        let mut used_indices = vec![];
-                        let mut alts = vec![];
-                        let mut idx = None;
-                        for j in i + 1 .. path.steps.len() {
-                            match path.steps[j] {
-                                Step::Alternative(i) => {
-                                    alts.push(i);
-                                    used_indices.push(j);
-                                }
-                                Step::Idx(i) => {
-                                    idx = Some(i);
-                                    used_indices.push(j);
-                                    break;
-                                }
-                                Step::Repeat | Step::IntroduceParam(..) => break,
-                                Step::Rhs | Step::AlternativeAndIdx(..) => unreachable!()
-                            }
-                        }
-                        used_indices.sort();
-                        for i in used_indices.into_iter().rev() {
-                            path.steps.remove(i);
-                        }
-                        path.steps[i] = Step::AlternativeAndIdx(alts, idx.unwrap_or(0));
-                    }
-                    Step::Alternative(i) => {
-                        let mut used_indices = vec![];
-                        let mut alts = vec![i];
-                        let mut idx = None;
-                        for j in i + 1 .. path.steps.len() {
-                            match path.steps[j] {
-                                Step::Alternative(i) => {
-                                    alts.push(i);
-                                    used_indices.push(j);
-                                }
-                                Step::Idx(i) => {
-                                    idx = Some(i);
-                                    used_indices.push(j);
-                                    break;
-                                }
-                                Step::Repeat | Step::IntroduceParam(..) => break,
-                                Step::Rhs | Step::AlternativeAndIdx(..) => unreachable!()
-                            }
-                        }
-                        used_indices.sort();
-                        for i in used_indices.into_iter().rev() {
-                            path.steps.remove(i);
-                        }
-                        path.steps[i] = Step::AlternativeAndIdx(alts, idx.unwrap_or(0));
-                    }
-                    Step::Idx(i) => {
-                        path.steps[i] = Step::AlternativeAndIdx(vec![], i);
-                    }
-                    _ => {}
-                }
-            }
-        }
-
-        for i in 0 .. self.paths.len() {
-            let mut used_indices = vec![];
-            for j in 0 .. self.paths[i].steps.len() {
-                match self.paths[i].steps[j] {
-                    Step::IntroduceParam(ref param_name) => {
-                        let mut k = i + 1;
-                        while k < self.paths.len() &&
-                                self.paths[k].steps[.. j + 1].iter().enumerate().all(|(l, step)| step == self.paths[i].steps[l]) {
-                                self.paths[k].steps[j] = Step::ExtIntroduceParam()
-                                continue;
-                            }
-                            break;
-                        }
-                        used_indices.push(j);
-                    }
-                    _ => {}
-                }
-            }
-            used_indices.sort();
-            for j in used_indices.into_iter().rev() {
-                path.steps.remove(j);
-            }
-        }
-
-        for path in &mut self.paths {
-            for i in 1 .. path.steps.len() {
-                let path = Path { steps: path.steps[.. i].to_vec() };
-                self.external_syms.entry(path).or_insert_with(|| self.external_grammar.sym());
-            }
-        }
+        self
     }
 }
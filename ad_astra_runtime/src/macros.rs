@@ -11,8 +11,60 @@ macro_rules! ast {
             );
         }
     };
+    // Processes the rules, or builds the grammar from precompiled tables
+    // without looking at them.
+    (@runtime $Step:ident [] ($($start_lhs:tt)*) $(($($rule:tt)*))*) => {{
+        let mut runtime = $crate::NeighborhoodRuntime::<$Step>::new();
+        $(
+            runtime.rule(
+                // rule!($lhs).lhs_then(rule!($($rhs)|+))
+                $crate::rule!(( $($rule)* ))
+            );
+        )*
+        runtime.allow($crate::rule!(( $($start_lhs)* )));
+        runtime.process_rules()?;
+        runtime
+    }};
+    (@runtime $Step:ident [$tables:expr] $($rules:tt)*) => {
+        $crate::NeighborhoodRuntime::<$Step>::from_tables(&$tables)
+    };
+    (@variant_name $Step:ident []) => {};
+    (@variant_name $Step:ident [$($variant:ident),+]) => {
+        #[allow(unreachable_patterns)]
+        fn variant_name(&self) -> Option<&str> {
+            match self {
+                $(&$Step::$variant { .. } => Some(stringify!($variant)),)+
+                _ => None,
+            }
+        }
+
+        fn variant_names() -> Option<&'static [&'static str]> {
+            Some(&[$(stringify!($variant)),+])
+        }
+    };
+    // Reads the optional attributes in any order, then the names. The trace
+    // variant is called `Trace` unless `#[trace]` names it.
+    (@header [$($tables:tt)*] [$($variants:tt)*] #[tables($table:expr)] $($rest:tt)*) => (
+        $crate::ast!(@header [$table] [$($variants)*] $($rest)*);
+    );
+    (@header [$($tables:tt)*] [$($variants:tt)*] #[variants($($variant:ident),* $(,)*)] $($rest:tt)*) => (
+        $crate::ast!(@header [$($tables)*] [$($variant),*] $($rest)*);
+    );
+    (
+        @header [$($tables:tt)*] [$($variants:tt)*]
+        $Neighborhood:ident, $Path:ident, $Step:ident, #[trace] $Trace:ident, $($rest:tt)*
+    ) => (
+        $crate::ast!(@define [$($tables)*] [$($variants)*] $Neighborhood, $Path, $Step, $Trace, $($rest)*);
+    );
     (
-        $Neighborhood:ident, $Path:ident, $Step:ident, ($($start_lhs:tt)*) =>
+        @header [$($tables:tt)*] [$($variants:tt)*]
+        $Neighborhood:ident, $Path:ident, $Step:ident, $($rest:tt)*
+    ) => (
+        $crate::ast!(@define [$($tables)*] [$($variants)*] $Neighborhood, $Path, $Step, Trace, $($rest)*);
+    );
+    (
+        @define [$($tables:tt)*] [$($variants:tt)*]
+        $Neighborhood:ident, $Path:ident, $Step:ident, $Trace:ident, ($($start_lhs:tt)*) =>
             $(
                 (
                     $($rule:tt)*
@@ -24,34 +76,63 @@ macro_rules! ast {
                 );
             )*
     ) => (
-        ast!(($), $Path);
+        $crate::ast!(($), $Path);
 
-        struct $Neighborhood {
+        pub struct $Neighborhood {
             paths: Vec<$Path>,
-            runtime: $crate::NeighborhoodRuntime<$Step>,
+            runtime: ::std::rc::Rc<$crate::NeighborhoodRuntime<$Step>>,
         }
 
-        struct $Path {
+        pub struct $Path {
             steps: Vec<$Step>,
         }
 
+        impl $crate::Traced for $Step {
+            fn trace(&self) -> Option<usize> {
+                match self {
+                    &$Step::$Trace(idx) => Some(idx),
+                    _ => None,
+                }
+            }
+
+            fn from_trace(idx: usize) -> Self {
+                $Step::$Trace(idx)
+            }
+
+            $crate::ast!(@variant_name $Step [$($variants)*]);
+        }
+
         impl $Neighborhood {
             pub fn new() -> Self {
-                let mut runtime = $crate::NeighborhoodRuntime::<$Step>::new();
-
-                $(
-                    runtime.rule(
-                        // rule!($lhs).lhs_then(rule!($($rhs)|+))
-                        rule!(( $($rule)* ));
-                    );
-                )*
+                match $Neighborhood::try_new() {
+                    Ok(this) => this,
+                    Err(errors) => {
+                        let messages: Vec<String> = errors.iter().map(|error| error.to_string()).collect();
+                        panic!("invalid grammar: {}", messages.join("; "))
+                    }
+                }
+            }
 
-                runtime.process_rules();
+            /// Builds the grammar once per thread. Later neighborhoods share it.
+            pub fn try_new() -> Result<Self, Vec<$crate::GrammarError>> {
+                thread_local! {
+                    static RUNTIME: ::std::cell::RefCell<Option<::std::rc::Rc<$crate::NeighborhoodRuntime<$Step>>>> =
+                        ::std::cell::RefCell::new(None);
+                }
+                if let Some(runtime) = RUNTIME.with(|cached| cached.borrow().clone()) {
+                    return Ok($Neighborhood {
+                        paths: Vec::new(),
+                        runtime,
+                    });
+                }
 
-                $Neighborhood {
+                let runtime = $crate::ast!(@runtime $Step [$($tables)*] ($($start_lhs)*) $(($($rule)*))*);
+                let runtime = ::std::rc::Rc::new(runtime);
+                RUNTIME.with(|cached| *cached.borrow_mut() = Some(runtime.clone()));
+                Ok($Neighborhood {
                     paths: Vec::new(),
                     runtime,
-                }
+                })
             }
 
             pub fn with_paths(paths: Vec<$Path>) -> Self {
@@ -60,12 +141,148 @@ macro_rules! ast {
                 this
             }
 
-            pub fn validate(&self) -> bool {
-                let mut valid = true;
-                for path in &self.paths {
-                    valid = valid && self.runtime.validate_steps(&path.steps[..]);
+            /// Reconstructs the family of trees that the paths go through.
+            pub fn to_tree(&self) -> Vec<$crate::Tree<$Step>> {
+                $crate::Tree::from_paths(&self.paths[..])
+            }
+
+            pub fn from_tree(trees: &[$crate::Tree<$Step>]) -> Self {
+                $Neighborhood::with_paths(
+                    $crate::Tree::to_paths(trees).into_iter().map($Path::with_steps).collect()
+                )
+            }
+
+            /// Draws the trees in the DOT language, with invalid subtrees in red.
+            pub fn to_dot(&self) -> String {
+                self.runtime.to_dot(&self.paths[..])
+            }
+
+            pub fn grammar_to_dot(&self) -> String {
+                self.runtime.grammar_to_dot()
+            }
+
+            pub fn validate(&self) -> Result<(), Vec<$crate::ValidationError>> {
+                self.runtime.validate(&self.paths[..])
+            }
+
+            pub fn derive(&self) -> Result<Vec<Vec<$crate::Derivation>>, Vec<$crate::ValidationError>> {
+                self.runtime.derive(&self.paths[..])
+            }
+
+            pub fn unify(&self) -> Result<Vec<Vec<$crate::Derivation>>, Vec<$crate::NeighborhoodError>> {
+                self.runtime.unify(&self.paths[..])
+            }
+
+            pub fn check(&self) -> Result<(), Vec<$crate::NeighborhoodError>> {
+                self.runtime.check(&self.paths[..])
+            }
+
+            pub fn types(&self) -> Result<::std::collections::BTreeMap<&[$Step], String>, Vec<$crate::NeighborhoodError>> {
+                self.runtime.types(&self.paths[..])
+            }
+
+            /// Checks the neighborhood and rebuilds its trees, labeled with
+            /// productions.
+            pub fn nodes(&self) -> Result<Vec<$crate::Node<$Step>>, Vec<$crate::NeighborhoodError>> {
+                self.runtime.nodes(&self.paths[..])
+            }
+
+            /// Indexes the paths by their common prefixes.
+            pub fn trie(&self) -> $crate::PathTrie<$Step> {
+                $crate::PathTrie::new(&self.paths[..])
+            }
+
+            /// Cursors at the roots of the trees, sharing one index of the
+            /// paths.
+            pub fn roots(&self) -> Vec<$crate::Cursor<$Step>> {
+                $crate::Cursor::roots(&self.paths[..])
+            }
+
+            /// A cursor at the node that begins right after the prefix.
+            pub fn cursor(&self, prefix: &[$Step]) -> Option<$crate::Cursor<$Step>> {
+                $crate::Cursor::at(&self.paths[..], prefix)
+            }
+
+            /// Returns the prefixes of the paths that the query matches.
+            pub fn select(&self, query: &$crate::Query<$Step>) -> Vec<&[$Step]> {
+                query.select(&self.paths[..])
+            }
+
+            pub fn rewriter(&self) -> $crate::Rewriter<$Step> {
+                $crate::Rewriter::new(&self.runtime)
+            }
+
+            /// Applies the rules of the rewriter until none matches.
+            pub fn rewrite(&self, rewriter: &$crate::Rewriter<$Step>) -> Result<Self, $crate::RewriteError> {
+                let paths = rewriter.rewrite(&self.paths[..])?;
+                Ok(self.with_same_runtime(paths))
+            }
+
+            /// The paths under the prefix, with the prefix stripped.
+            pub fn subtree(&self, prefix: &[$Step]) -> Self {
+                self.with_same_runtime($crate::subtree(&self.paths[..], prefix))
+            }
+
+            /// Adds the paths of another neighborhood under the prefix.
+            pub fn graft(&self, prefix: &[$Step], other: &Self) -> Self {
+                self.with_same_runtime($crate::graft(&self.paths[..], prefix, &other.paths[..]))
+            }
+
+            /// Swaps the subtree under the prefix for another neighborhood.
+            pub fn replace(&self, prefix: &[$Step], other: &Self) -> Self {
+                self.with_same_runtime($crate::replace(&self.paths[..], prefix, &other.paths[..]))
+            }
+
+            /// Extracts a subtree that is valid on its own, with the same
+            /// nonterminal.
+            pub fn try_subtree(&self, prefix: &[$Step]) -> Result<Self, $crate::SpliceError> {
+                let paths = self.runtime.check_subtree(&self.paths[..], prefix)?;
+                Ok(self.with_same_runtime(paths))
+            }
+
+            /// Grafts another neighborhood and checks that it fits the slot.
+            pub fn try_graft(&self, prefix: &[$Step], other: &Self) -> Result<Self, $crate::SpliceError> {
+                let paths = self.runtime.check_graft(&self.paths[..], prefix, &other.paths[..])?;
+                Ok(self.with_same_runtime(paths))
+            }
+
+            /// Replaces a subtree and checks that the new one fits the slot.
+            pub fn try_replace(&self, prefix: &[$Step], other: &Self) -> Result<Self, $crate::SpliceError> {
+                let paths = self.runtime.check_replace(&self.paths[..], prefix, &other.paths[..])?;
+                Ok(self.with_same_runtime(paths))
+            }
+
+            /// Compares the trees with those of another neighborhood.
+            pub fn diff(&self, other: &Self) -> $crate::Diff<$Step> {
+                $crate::diff(&self.paths[..], &other.paths[..])
+            }
+
+            pub fn patch(&self, diff: &$crate::Diff<$Step>) -> Result<Self, $crate::PatchError> {
+                let paths = $crate::patch(&self.paths[..], diff)?;
+                Ok(self.with_same_runtime(paths))
+            }
+
+            #[doc(hidden)]
+            pub fn is_precompiled(&self) -> bool {
+                self.runtime.is_precompiled()
+            }
+
+            #[doc(hidden)]
+            pub fn rule_count(&self) -> usize {
+                self.runtime.rule_count()
+            }
+
+            fn with_same_runtime(&self, paths: Vec<Vec<$Step>>) -> Self {
+                $Neighborhood {
+                    paths: paths.into_iter().map($Path::with_steps).collect(),
+                    runtime: self.runtime.clone(),
                 }
-                valid
+            }
+
+            pub fn from_nodes(nodes: &[$crate::Node<$Step>]) -> Self {
+                $Neighborhood::with_paths(
+                    $crate::Node::to_paths(nodes).into_iter().map($Path::with_steps).collect()
+                )
             }
         }
 
@@ -78,7 +295,19 @@ macro_rules! ast {
                 $Path { steps }
             }
         }
-    )
+
+        impl AsRef<[$Step]> for $Path {
+            fn as_ref(&self) -> &[$Step] {
+                &self.steps[..]
+            }
+        }
+    );
+    (@ $($input:tt)*) => (
+        compile_error!("expected `Neighborhood, Path, Step, (start) =>` after the attributes of `ast!`");
+    );
+    ($($input:tt)*) => (
+        $crate::ast!(@header [] [] $($input)*);
+    );
 }
 
 // #[macro_export]
@@ -108,33 +337,43 @@ macro_rules! rule {
         $crate::Matcher::apply(stringify!($rhs), stringify!($T)).into_neighborhood()
     };
     ((@m $pattern:pat )) => {
-        $crate::Matcher::match_pattern(Box::new(|val| match val { &$pattern => true, _ => false }))
+        $crate::Matcher::match_pattern(stringify!($pattern), |val| match val { &$pattern => true, _ => false })
           .into_neighborhood()
     };
     (( for<$T:ident> $rhs:tt )) => {
-        rule!($rhs).introduce_param(stringify!($T))
+        $crate::rule!($rhs).introduce_param(stringify!($T))
+    };
+    (( for<$T:ident> $($rhs:tt)+ )) => {
+        $crate::rule!(( $($rhs)+ )).introduce_param(stringify!($T))
     };
     (( $rhs:tt * )) => {
-        rule!($rhs).repeat()
+        $crate::rule!($rhs).repeat()
     };
-    (( $lhs:tt ::= $rhs:tt )) => {
-        rule!($lhs).lhs_then(rule!($rhs))
+    (( $lhs:tt ::= $($rhs:tt)+ )) => {
+        $crate::rule!($lhs).lhs_then($crate::rule!($($rhs)+))
     };
     ((
         /**/ $rhs0:tt
         $(^  $rhsN:tt)+
     )) => {
-        /***/ rule!($rhs0)
-        $(.offshoot(rule!($rhsN)))+
+        /***/ $crate::rule!($rhs0)
+        $(.offshoot($crate::rule!($rhsN)))+
+    };
+    ((
+        /**/ $rhs0:tt
+        $(|  $rhsN:tt)+
+    )) => {
+        /***/ $crate::rule!($rhs0)
+        $(.or($crate::rule!($rhsN)))+
     };
     (( $rhs0:tt $($rhsN:tt)* )) => {
-        rule!($rhs0) $(.then(rule!($rhsN)))*
+        $crate::rule!($rhs0) $(.then($crate::rule!($rhsN)))*
     };
     (
         /**/ $rhs0:tt
         $(|  $rhsN:tt)+
     ) => {
-        /***/ rule!($rhs0)
-        $(.or(rule!($rhsN)))+
+        /***/ $crate::rule!($rhs0)
+        $(.or($crate::rule!($rhsN)))+
     };
 }
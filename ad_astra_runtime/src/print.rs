@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+use cfg::Symbol;
+
+use super::{type_name, ExtPath, ExtStep, Matcher, Neighborhood, NeighborhoodRuntime, Step, TerminalKind};
+
+/// How tightly an expression binds, from a list of alternatives to a single
+/// step. A part is put in parentheses where it binds less tightly than its
+/// position requires.
+const ALTERNATIVES: u8 = 0;
+const SEQUENCE: u8 = 1;
+const OFFSHOOTS: u8 = 2;
+const REPEAT: u8 = 3;
+const ATOM: u8 = 4;
+
+/// Writes the rules in the syntax that `from_grammar_str` reads, one per line.
+impl<T> fmt::Display for NeighborhoodRuntime<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(text) = self.precompiled {
+            return f.write_str(text);
+        }
+        for stmt in &self.stmts {
+            writeln!(f, "{};", stmt)?;
+        }
+        for allowed in &self.allowed {
+            writeln!(f, "@allow {};", allowed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a rule as `for<T> Expr<T> ::= IfExpr (Expr<bool> ^ Expr<T> ^ Expr<T>)`,
+/// or the right-hand side of one.
+impl<T> fmt::Display for Neighborhood<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.paths.is_empty() {
+            return Ok(());
+        }
+        let indices: Vec<usize> = (0 .. self.paths.len()).collect();
+        self.write(f, &indices, 0, ALTERNATIVES)
+    }
+}
+
+impl<T> Neighborhood<T> {
+    fn write(&self, f: &mut fmt::Formatter, indices: &[usize], depth: usize, prec: u8) -> fmt::Result {
+        if indices.len() == 1 && self.paths[indices[0]].steps.len() == depth {
+            return write!(f, "{}", self.paths[indices[0]].matcher);
+        }
+        let mut children: BTreeMap<&Step, Vec<usize>> = BTreeMap::new();
+        for &i in indices {
+            children.entry(&self.paths[i].steps[depth]).or_insert_with(Vec::new).push(i);
+        }
+        // Offshoots are put in parentheses in a sequence, as in `neighborhood!`.
+        let (own_prec, separator, part_prec) = match *children.keys().next().unwrap() {
+            &Step::Alternative(_) => (ALTERNATIVES, " | ", SEQUENCE),
+            &Step::Idx(_) => (SEQUENCE, " ", REPEAT),
+            &Step::Offshoot(_) => (OFFSHOOTS, " ^ ", REPEAT),
+            &Step::Repeat => (REPEAT, "", ATOM),
+            &Step::IntroduceParam(_) if self.is_stmt(indices, depth) => (ALTERNATIVES, "", ALTERNATIVES),
+            &Step::IntroduceParam(_) => (ATOM, "", ATOM),
+            &Step::Lhs | &Step::Rhs => (ALTERNATIVES, "", ALTERNATIVES),
+        };
+        if own_prec < prec {
+            write!(f, "(")?;
+        }
+        let mut children = children.into_iter();
+        match children.next().unwrap() {
+            (&Step::Repeat, indices) => {
+                self.write(f, &indices, depth + 1, part_prec)?;
+                write!(f, "*")?;
+            }
+            (&Step::IntroduceParam(ref param), indices) => {
+                write!(f, "for<{}> ", param)?;
+                self.write(f, &indices, depth + 1, part_prec)?;
+            }
+            (&Step::Lhs, lhs) => {
+                let (_, rhs) = children.next().expect("a rule without a right-hand side");
+                write!(f, "{} ::= ", self.paths[lhs[0]].matcher)?;
+                self.write(f, &rhs, depth + 1, part_prec)?;
+            }
+            (_, indices) => {
+                self.write(f, &indices, depth + 1, part_prec)?;
+                for (_, indices) in children {
+                    write!(f, "{}", separator)?;
+                    self.write(f, &indices, depth + 1, part_prec)?;
+                }
+            }
+        }
+        if own_prec < prec {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+
+    /// Whether the paths below `for<T>` parameters begin a rule.
+    fn is_stmt(&self, indices: &[usize], depth: usize) -> bool {
+        self.paths[indices[0]].steps[depth ..].iter().find(|step| match step {
+            &&Step::IntroduceParam(_) => false,
+            _ => true,
+        }).map_or(false, |step| *step == Step::Lhs || *step == Step::Rhs)
+    }
+}
+
+impl<T> fmt::Display for Matcher<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Matcher::Symbol(ref name) => write!(f, "{}", name),
+            &Matcher::ParamApply { ref rhs, ref ty_param } => write!(f, "{}<{}>", rhs, ty_param),
+            &Matcher::Pattern { ref name, .. } => write!(f, "{}", pattern_name(name)),
+        }
+    }
+}
+
+/// Writes a pattern from `stringify!`, such as `Step :: Value (Value :: Bool (_))`,
+/// the way `from_grammar_str` reads it: `Value(Value::Bool(_))`.
+fn pattern_name(name: &str) -> String {
+    let name = type_name(name);
+    let head = name.find('(').unwrap_or(name.len());
+    match name[.. head].rfind("::") {
+        Some(end) => name[end + 2 ..].to_string(),
+        None => name,
+    }
+}
+
+/// Writes a position in the lowered rules as `rule 2 / rhs / |1 / #0 / ^2`,
+/// with `|` for an alternative, `#` for an element of a sequence, `^` for an
+/// offshoot, `*` for a repetition, and `<...>` for the type given to a parameter.
+impl fmt::Display for ExtPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rule {}", self.stmt)?;
+        for step in &self.steps {
+            match step {
+                &ExtStep::Alternative(n) => write!(f, " / |{}", n)?,
+                &ExtStep::Idx(n) => write!(f, " / #{}", n)?,
+                &ExtStep::Offshoot(n) => write!(f, " / ^{}", n)?,
+                &ExtStep::Repeat => write!(f, " / *")?,
+                &ExtStep::IntroduceParam(ty) => write!(f, " / <{}>", ty)?,
+                &ExtStep::Rhs => write!(f, " / rhs")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T> NeighborhoodRuntime<T> {
+    /// Lists the symbols that `process_rules` made for each position in the
+    /// rules, followed by the context-free rules between them. Nonterminals
+    /// are written by name, terminals as the steps they match, and other
+    /// symbols as `s` and their number.
+    pub fn dump_lowering(&self) -> String {
+        let mut dump = String::new();
+        writeln!(dump, "// types").unwrap();
+        for ty in self.param_map.ids() {
+            writeln!(dump, "<{}> = {}", ty, self.param_map.get(ty)).unwrap();
+        }
+        writeln!(dump, "// positions").unwrap();
+        for (ext, &sym) in &self.external_syms {
+            writeln!(dump, "{} = {}", self.sym_name(sym), ext).unwrap();
+        }
+        writeln!(dump, "// rules").unwrap();
+        for &(lhs, ref rhs) in &self.rules {
+            let rhs: Vec<String> = rhs.iter().map(|&sym| self.sym_name(sym)).collect();
+            writeln!(dump, "{} ::= {}", self.sym_name(lhs), rhs.join(" ")).unwrap();
+        }
+        dump
+    }
+
+    fn sym_name(&self, sym: Symbol) -> String {
+        if let Some((name, _)) = self.nonterminals.iter().find(|&(_, &nonterminal)| nonterminal == sym) {
+            return name.clone();
+        }
+        if let Some(&(_, ref terminal)) = self.terminals.iter().find(|&&(terminal, _)| terminal == sym) {
+            return match terminal.kind {
+                TerminalKind::Trace { idx, .. } => format!("s{}:trace({})", sym.usize(), idx),
+                TerminalKind::Leaf(ref matcher) => {
+                    format!("s{}:{}", sym.usize(), self.matcher_name(matcher, &terminal.env))
+                }
+            };
+        }
+        format!("s{}", sym.usize())
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::rc::Rc;
+
+use super::{variant_name, Predicates, QueryError, Traced};
+
+/// A pattern over the steps of paths, such as `IfExpr/Trace(0)/**/LtExpr`.
+/// Segments are separated by `/`, and the first segment matches the first step
+/// of a path:
+///
+/// * `*` matches any step, and `**` any number of steps;
+/// * `Trace(n)` matches the trace of offshoot `n`, and `Trace` or `Trace(*)`
+///   any trace;
+/// * a name registered in the predicates matches the steps it accepts;
+/// * a step with fields, e.g. `Value(Int(1))`, matches steps written the same
+///   way with `Debug`, and a bare name matches steps of that variant.
+///
+/// Where `Traced::variant_names` lists the variants, other names are errors.
+/// Fields are compared with the `Debug` output alone, so a step with a custom
+/// `Debug` matches fields only as it writes them, e.g. `Int(1)` never matches
+/// a step that writes itself as `1`.
+pub struct Query<T> {
+    segments: Vec<Segment<T>>,
+}
+
+enum Segment<T> {
+    Any,
+    Descendants,
+    Trace(Option<usize>),
+    Step(String),
+    Predicate(Rc<dyn Fn(&T) -> bool>),
+}
+
+impl<T: Traced + PartialEq + fmt::Debug> Query<T> {
+    pub fn new(text: &str) -> Result<Self, QueryError> {
+        Query::with_predicates(text, &Predicates::new())
+    }
+
+    pub fn with_predicates(text: &str, predicates: &Predicates<T>) -> Result<Self, QueryError> {
+        let mut segments = vec![];
+        for (column, segment) in split(text)? {
+            if segment.is_empty() {
+                return Err(QueryError { column, message: "expected a step".to_string() });
+            }
+            segments.push(match &segment[..] {
+                "*" => Segment::Any,
+                "**" => Segment::Descendants,
+                "Trace" | "Trace(*)" => Segment::Trace(None),
+                _ if segment.starts_with("Trace(") && segment.ends_with(')') => {
+                    match segment["Trace(".len() .. segment.len() - 1].trim().parse() {
+                        Ok(idx) => Segment::Trace(Some(idx)),
+                        Err(_) => return Err(QueryError { column, message: "expected a trace index".to_string() }),
+                    }
+                }
+                _ => match predicates.get(&segment) {
+                    Some(predicate) => Segment::Predicate(predicate.clone()),
+                    None => {
+                        let name = segment.split('(').next().unwrap_or("").trim();
+                        if T::variant_names().map_or(false, |names| !names.contains(&name)) {
+                            let message = format!("unknown variant `{}`", name);
+                            return Err(QueryError { column, message });
+                        }
+                        Segment::Step(normalize(&segment))
+                    }
+                },
+            });
+        }
+        Ok(Query { segments })
+    }
+
+    /// Whether the query matches the steps as a whole.
+    pub fn matches(&self, steps: &[T]) -> bool {
+        self.prefixes(steps).last() == Some(&steps.len())
+    }
+
+    /// Returns every prefix of the paths that the query matches, each once, in
+    /// the order of paths.
+    pub fn select<'a, P: AsRef<[T]>>(&self, paths: &'a [P]) -> Vec<&'a [T]>
+        where T: Ord
+    {
+        let mut seen = BTreeSet::new();
+        let mut selected: Vec<&'a [T]> = vec![];
+        for path in paths {
+            let steps = path.as_ref();
+            for len in self.prefixes(steps) {
+                if seen.insert(&steps[.. len]) {
+                    selected.push(&steps[.. len]);
+                }
+            }
+        }
+        selected
+    }
+
+    /// Lengths of the nonempty prefixes that the query matches.
+    fn prefixes(&self, steps: &[T]) -> Vec<usize> {
+        let mut states = vec![false; self.segments.len() + 1];
+        states[0] = true;
+        self.close(&mut states);
+        let mut lengths = vec![];
+        for (i, step) in steps.iter().enumerate() {
+            let mut next = vec![false; states.len()];
+            for (state, segment) in self.segments.iter().enumerate() {
+                if !states[state] {
+                    continue;
+                }
+                match segment {
+                    &Segment::Descendants => next[state] = true,
+                    segment if segment.matches(step) => next[state + 1] = true,
+                    _ => {}
+                }
+            }
+            self.close(&mut next);
+            states = next;
+            if !states.iter().any(|&state| state) {
+                break;
+            }
+            if states[self.segments.len()] {
+                lengths.push(i + 1);
+            }
+        }
+        lengths
+    }
+
+    /// Adds the states after `**`, which may match no steps.
+    fn close(&self, states: &mut [bool]) {
+        for (state, segment) in self.segments.iter().enumerate() {
+            if let &Segment::Descendants = segment {
+                if states[state] {
+                    states[state + 1] = true;
+                }
+            }
+        }
+    }
+}
+
+impl<T: Traced + fmt::Debug> Segment<T> {
+    fn matches(&self, step: &T) -> bool {
+        match self {
+            &Segment::Any | &Segment::Descendants => true,
+            &Segment::Trace(idx) => step.trace().map_or(false, |trace| idx.map_or(true, |idx| idx == trace)),
+            &Segment::Step(ref text) => {
+                if step.trace().is_some() {
+                    false
+                } else if text.contains('(') {
+                    normalize(&format!("{:?}", step)) == *text
+                } else {
+                    variant_name(step) == *text
+                }
+            }
+            &Segment::Predicate(ref predicate) => step.trace().is_none() && predicate(step),
+        }
+    }
+}
+
+/// Splits the query at each `/` outside of parentheses, with the column where
+/// each segment begins. Columns count characters from 1.
+fn split(text: &str) -> Result<Vec<(usize, String)>, QueryError> {
+    let mut segments = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    let mut start_column = 1;
+    for (column, (i, c)) in text.char_indices().enumerate() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Err(QueryError { column: column + 1, message: "unexpected `)`".to_string() }),
+            ')' => depth -= 1,
+            '/' if depth == 0 => {
+                segments.push((start_column, text[start .. i].trim().to_string()));
+                start = i + 1;
+                start_column = column + 2;
+            }
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return Err(QueryError { column: text.chars().count() + 1, message: "expected `)`".to_string() });
+    }
+    segments.push((start_column, text[start ..].trim().to_string()));
+    Ok(segments)
+}
+
+fn normalize(text: &str) -> String {
+    text.chars().filter(|c| !c.is_whitespace()).collect()
+}
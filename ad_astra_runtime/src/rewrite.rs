@@ -0,0 +1,277 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::rc::Rc;
+
+use super::{subtree, NeighborhoodError, NeighborhoodRuntime, RewriteError, Traced, Tree};
+
+/// A step of a pattern or of a replacement. A variable stands for the whole
+/// subtree under the prefix before it, so it ends its path.
+pub enum PatternStep<T> {
+    Step(T),
+    /// Matches any step the predicate accepts. Only in patterns.
+    Predicate(Rc<dyn Fn(&T) -> bool>),
+    Var(String),
+}
+
+impl<T> PatternStep<T> {
+    pub fn predicate<F>(predicate: F) -> Self
+        where F: Fn(&T) -> bool + 'static
+    {
+        PatternStep::Predicate(Rc::new(predicate))
+    }
+
+    pub fn var(name: &str) -> Self {
+        PatternStep::Var(name.to_string())
+    }
+}
+
+struct Rule<T> {
+    pattern: Vec<Vec<PatternStep<T>>>,
+    replacement: Vec<Vec<PatternStep<T>>>,
+}
+
+/// Rewrites subtrees of a neighborhood that match patterns, such as
+/// `IfExpr(true, a, b)` to `a`, until no pattern matches. Each rewrite must
+/// leave the rewritten subtree valid and derived from the same nonterminal as
+/// before, which is checked on the subtree and the node above it alone.
+pub struct Rewriter<'a, T: 'a> {
+    runtime: &'a NeighborhoodRuntime<T>,
+    rules: Vec<Rule<T>>,
+    limit: usize,
+}
+
+/// Subtrees bound to variables, as the suffixes of their paths.
+type Bindings<'p, T> = BTreeMap<String, Vec<&'p [T]>>;
+
+/// The nonterminal of each subtree, by its prefix.
+type Types<T> = BTreeMap<Vec<T>, String>;
+
+impl<'a, T: Traced + Ord + Clone + fmt::Debug> Rewriter<'a, T> {
+    pub fn new(runtime: &'a NeighborhoodRuntime<T>) -> Self {
+        Rewriter { runtime, rules: vec![], limit: 1000 }
+    }
+
+    /// Adds a rule, given as paths of a subtree. Rules are tried in the order
+    /// they are added.
+    pub fn rule(&mut self, pattern: Vec<Vec<PatternStep<T>>>, replacement: Vec<Vec<PatternStep<T>>>) -> Result<&mut Self, RewriteError> {
+        let rule = self.rules.len();
+        let invalid = |message: String| Err(RewriteError::Rule { rule, message });
+        let mut vars = vec![];
+        for path in &pattern {
+            for (i, step) in path.iter().enumerate() {
+                if let &PatternStep::Var(ref name) = step {
+                    if i + 1 != path.len() {
+                        return invalid(format!("variable `{}` does not end its path", name));
+                    }
+                    if vars.contains(name) {
+                        return invalid(format!("variable `{}` is bound twice", name));
+                    }
+                    vars.push(name.clone());
+                }
+            }
+        }
+        for path in &replacement {
+            for (i, step) in path.iter().enumerate() {
+                match step {
+                    &PatternStep::Var(ref name) if i + 1 != path.len() => {
+                        return invalid(format!("variable `{}` does not end its path", name));
+                    }
+                    &PatternStep::Var(ref name) if !vars.contains(name) => {
+                        return invalid(format!("variable `{}` is not bound by the pattern", name));
+                    }
+                    &PatternStep::Predicate(_) => return invalid("a predicate in the replacement".to_string()),
+                    _ => {}
+                }
+            }
+        }
+        self.rules.push(Rule { pattern, replacement });
+        Ok(self)
+    }
+
+    /// Sets the number of rewrites after which rewriting stops with an error.
+    pub fn limit(&mut self, limit: usize) -> &mut Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Applies the rules until none matches, and returns the rewritten paths.
+    pub fn rewrite<P: AsRef<[T]>>(&self, paths: &[P]) -> Result<Vec<Vec<T>>, RewriteError> {
+        let mut types = self.types(paths).map_err(RewriteError::Invalid)?;
+        let mut paths: Vec<Vec<T>> = paths.iter().map(|path| path.as_ref().to_vec()).collect();
+        let mut rewrites = 0;
+        while let Some((next, next_types)) = self.rewrite_once(&paths, &types)? {
+            rewrites += 1;
+            if rewrites > self.limit {
+                return Err(RewriteError::Limit { rewrites: self.limit });
+            }
+            paths = next;
+            types = next_types;
+        }
+        Ok(paths)
+    }
+
+    /// Rewrites the first subtree that a rule matches, if any.
+    fn rewrite_once(&self, paths: &[Vec<T>], types: &Types<T>) -> Result<Option<(Vec<Vec<T>>, Types<T>)>, RewriteError> {
+        for (r, rule) in self.rules.iter().enumerate() {
+            for prefix in subtrees(paths) {
+                let suffixes: Vec<&[T]> = paths.iter()
+                    .filter(|path| path.starts_with(prefix))
+                    .map(|path| &path[prefix.len() ..])
+                    .collect();
+                if let Some(bindings) = rule.matches(&suffixes) {
+                    let rewritten = rule.replace(paths, prefix, &bindings);
+                    let types = self.verify(r, &rewritten, prefix, types)?;
+                    return Ok(Some((rewritten, types)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Checks that the rewritten subtree is derived from the nonterminal it
+    /// replaced, and that it stays valid along with the node whose offshoot it
+    /// is. Returns the nonterminals, with those within the subtree updated.
+    fn verify(&self, rule: usize, rewritten: &[Vec<T>], prefix: &[T], types: &Types<T>) -> Result<Types<T>, RewriteError> {
+        if let Some(expected) = types.get(prefix) {
+            let subtree = subtree(rewritten, prefix);
+            if !self.runtime.derives(&subtree[..], expected) {
+                let found = self.runtime.derived_from(&subtree[..]);
+                return Err(RewriteError::Type {
+                    rule,
+                    prefix: format!("{:?}", prefix),
+                    expected: expected.clone(),
+                    found: if found.is_empty() { "no nonterminal".to_string() } else { found.join(" or ") },
+                });
+            }
+        }
+        let local = self.runtime.check_local(rewritten, prefix).map_err(RewriteError::Invalid)?;
+        let within = |key: &[T]| key.len() > prefix.len() && key.starts_with(prefix);
+        let mut types: Types<T> = types.iter()
+            .filter(|&(key, _)| !within(&key[..]))
+            .map(|(key, ty)| (key.clone(), ty.clone()))
+            .collect();
+        types.extend(local.into_iter().filter(|&(ref key, _)| within(&key[..])));
+        Ok(types)
+    }
+
+    /// Checks the paths, and infers their nonterminals from one unification.
+    fn types<P: AsRef<[T]>>(&self, paths: &[P]) -> Result<Types<T>, Vec<NeighborhoodError>> {
+        let derivations = self.runtime.unify(paths)?;
+        self.runtime.check_arity(paths, &derivations[..])?;
+        let types = self.runtime.types_of(paths, &derivations[..])?;
+        Ok(types.into_iter().map(|(prefix, ty)| (prefix.to_vec(), ty)).collect())
+    }
+}
+
+impl<T: Traced + PartialEq + Clone> Rule<T> {
+    /// Binds the variables if the pattern covers each path of the subtree.
+    fn matches<'p>(&self, suffixes: &[&'p [T]]) -> Option<Bindings<'p, T>> {
+        let mut bindings: Bindings<'p, T> = BTreeMap::new();
+        // The steps that each variable follows in the subtree.
+        let mut bound_after: Vec<Option<&'p [T]>> = vec![None; self.pattern.len()];
+        let mut covered = vec![false; self.pattern.len()];
+        for &suffix in suffixes {
+            let found = self.pattern.iter().enumerate().filter_map(|(p, pattern)| {
+                covers(pattern, suffix).map(|len| (p, len))
+            }).next();
+            let (p, len) = match found {
+                Some(found) => found,
+                None => return None,
+            };
+            covered[p] = true;
+            if let Some(&PatternStep::Var(ref name)) = self.pattern[p].last() {
+                match bound_after[p] {
+                    Some(before) if before != &suffix[.. len] => return None,
+                    _ => bound_after[p] = Some(&suffix[.. len]),
+                }
+                bindings.entry(name.clone()).or_insert_with(Vec::new).push(&suffix[len ..]);
+            }
+        }
+        if covered.iter().all(|&covered| covered) {
+            Some(bindings)
+        } else {
+            None
+        }
+    }
+
+    fn replace(&self, paths: &[Vec<T>], prefix: &[T], bindings: &Bindings<T>) -> Vec<Vec<T>> {
+        let mut replacement = vec![];
+        for path in &self.replacement {
+            let mut steps = prefix.to_vec();
+            let mut var = None;
+            for step in path {
+                match step {
+                    &PatternStep::Step(ref step) => steps.push(step.clone()),
+                    &PatternStep::Var(ref name) => var = Some(name),
+                    &PatternStep::Predicate(_) => unreachable!("a predicate in the replacement"),
+                }
+            }
+            match var {
+                Some(name) => {
+                    for suffix in &bindings[name] {
+                        replacement.push(steps.iter().chain(suffix.iter()).cloned().collect());
+                    }
+                }
+                None => replacement.push(steps),
+            }
+        }
+        let mut rewritten = vec![];
+        let mut replaced = false;
+        for path in paths {
+            if !path.starts_with(prefix) {
+                rewritten.push(path.clone());
+            } else if !replaced {
+                rewritten.extend(replacement.drain(..));
+                replaced = true;
+            }
+        }
+        renumber(&rewritten)
+    }
+}
+
+/// The length of the steps before the variable, if the pattern covers the
+/// path. A variable covers one or more steps.
+fn covers<T: PartialEq>(pattern: &[PatternStep<T>], path: &[T]) -> Option<usize> {
+    let (fixed, var) = match pattern.last() {
+        Some(&PatternStep::Var(_)) => (&pattern[.. pattern.len() - 1], true),
+        _ => (pattern, false),
+    };
+    let fits = if var { path.len() > fixed.len() } else { path.len() == fixed.len() };
+    let matches = fits && fixed.iter().zip(path).all(|(pattern, step)| match pattern {
+        &PatternStep::Step(ref expected) => expected == step,
+        &PatternStep::Predicate(ref predicate) => predicate(step),
+        &PatternStep::Var(_) => false,
+    });
+    if matches { Some(fixed.len()) } else { None }
+}
+
+/// Prefixes of the subtrees: the empty prefix, and each prefix that ends with
+/// a trace, in the order of paths.
+fn subtrees<T: Traced + Ord>(paths: &[Vec<T>]) -> Vec<&[T]> {
+    let mut seen = BTreeSet::new();
+    let mut prefixes: Vec<&[T]> = vec![];
+    for path in paths {
+        for end in 0 .. path.len() {
+            if (end == 0 || path[end - 1].trace().is_some()) && seen.insert(&path[.. end]) {
+                prefixes.push(&path[.. end]);
+            }
+        }
+    }
+    prefixes
+}
+
+/// Numbers the offshoots of each node from 0, keeping their order.
+fn renumber<T: Traced + PartialEq + Clone>(paths: &[Vec<T>]) -> Vec<Vec<T>> {
+    fn node<T: Clone>(tree: &Tree<T>) -> Tree<T> {
+        let mut indices: Vec<usize> = tree.children.iter().map(|&(idx, _)| idx).collect();
+        indices.sort();
+        indices.dedup();
+        let mut children: Vec<(usize, Tree<T>)> = tree.children.iter().map(|&(idx, ref child)| {
+            (indices.binary_search(&idx).unwrap(), node(child))
+        }).collect();
+        children.sort_by_key(|&(idx, _)| idx);
+        Tree { steps: tree.steps.clone(), children }
+    }
+    let trees: Vec<Tree<T>> = Tree::from_paths(paths).iter().map(node).collect();
+    Tree::to_paths(&trees)
+}
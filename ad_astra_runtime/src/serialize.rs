@@ -0,0 +1,85 @@
+use std::fmt;
+
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, Serializer};
+
+use super::ValidationError;
+
+/// Neighborhoods that can be validated against their grammar.
+pub trait Validate {
+    fn validate(&self) -> Result<(), Vec<ValidationError>>;
+}
+
+/// A neighborhood that is validated as part of deserialization. Invalid data
+/// is rejected with the path and step of every error.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Validated<N>(pub N);
+
+impl<N> Validated<N> {
+    pub fn into_inner(self) -> N {
+        self.0
+    }
+}
+
+impl<'de, N: Deserialize<'de> + Validate> Deserialize<'de> for Validated<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let neighborhood = N::deserialize(deserializer)?;
+        match neighborhood.validate() {
+            Ok(()) => Ok(Validated(neighborhood)),
+            Err(errors) => Err(D::Error::custom(InvalidNeighborhood(errors))),
+        }
+    }
+}
+
+impl<N: Serialize> Serialize for Validated<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+struct InvalidNeighborhood(Vec<ValidationError>);
+
+impl fmt::Display for InvalidNeighborhood {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let messages: Vec<String> = self.0.iter().map(|error| error.to_string()).collect();
+        write!(f, "invalid neighborhood: {}", messages.join("; "))
+    }
+}
+
+/// Implements `Serialize` and `Deserialize` for the structs generated by
+/// `ast!`, e.g. `ast_serde!(Neighborhood, Path, Step);`. The steps must
+/// implement both.
+#[macro_export]
+macro_rules! ast_serde {
+    ($Neighborhood:ident, $Path:ident, $Step:ident) => {
+        impl $crate::serde::Serialize for $Path {
+            fn serialize<S: $crate::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                $crate::serde::Serialize::serialize(&self.steps, serializer)
+            }
+        }
+
+        impl<'de> $crate::serde::Deserialize<'de> for $Path {
+            fn deserialize<D: $crate::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                <Vec<$Step> as $crate::serde::Deserialize>::deserialize(deserializer).map($Path::with_steps)
+            }
+        }
+
+        impl $crate::serde::Serialize for $Neighborhood {
+            fn serialize<S: $crate::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                $crate::serde::Serialize::serialize(&self.paths, serializer)
+            }
+        }
+
+        impl<'de> $crate::serde::Deserialize<'de> for $Neighborhood {
+            fn deserialize<D: $crate::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                <Vec<$Path> as $crate::serde::Deserialize>::deserialize(deserializer).map($Neighborhood::with_paths)
+            }
+        }
+
+        impl $crate::Validate for $Neighborhood {
+            fn validate(&self) -> Result<(), Vec<$crate::ValidationError>> {
+                $Neighborhood::validate(self)
+            }
+        }
+    };
+}
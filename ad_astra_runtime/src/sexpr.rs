@@ -0,0 +1,203 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
+
+use super::{SexprError, Tree};
+
+/// Renders a node as `(IfExpr (LtExpr 420 130) "a" "b")`. A node with more
+/// than one step has its steps in brackets, as in `[StmtFragment StmtIdx]`.
+/// An offshoot whose trace index is not one more than its predecessor's is
+/// preceded by `@` and the index.
+impl<T: fmt::Display> fmt::Display for Tree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.children.is_empty() {
+            write!(f, "(")?;
+        }
+        if self.steps.len() == 1 {
+            write!(f, "{}", self.steps[0])?;
+        } else {
+            write!(f, "[")?;
+            for (i, step) in self.steps.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " ")?;
+                }
+                write!(f, "{}", step)?;
+            }
+            write!(f, "]")?;
+        }
+        let mut next = 0;
+        for &(idx, ref child) in &self.children {
+            if idx == next {
+                write!(f, " {}", child)?;
+            } else {
+                write!(f, " @{} {}", idx, child)?;
+            }
+            next = idx + 1;
+        }
+        if !self.children.is_empty() {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders each tree of a neighborhood on its own line.
+pub fn to_sexpr<T: fmt::Display>(trees: &[Tree<T>]) -> String {
+    let lines: Vec<String> = trees.iter().map(|tree| tree.to_string()).collect();
+    lines.join("\n")
+}
+
+/// Reads the trees written by `to_sexpr`. Steps are read with `FromStr`, from
+/// atoms that are either quoted strings or runs of characters other than
+/// whitespace, parentheses and brackets.
+pub fn from_sexpr<T: FromStr>(text: &str) -> Result<Vec<Tree<T>>, SexprError> {
+    let mut parser = Parser { text, chars: text.char_indices().peekable(), line: 1, column: 1 };
+    let mut trees = vec![];
+    while parser.skip_whitespace().is_some() {
+        trees.push(parser.tree()?);
+    }
+    Ok(trees)
+}
+
+#[derive(Debug)]
+enum Token {
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+    Index(usize),
+    Atom(String),
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn bump(&mut self) -> Option<(usize, char)> {
+        let next = self.chars.next();
+        if let Some((_, c)) = next {
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        next
+    }
+
+    fn skip_whitespace(&mut self) -> Option<char> {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if !c.is_whitespace() {
+                return Some(c);
+            }
+            self.bump();
+        }
+        None
+    }
+
+    fn error(&self, line: usize, column: usize, message: String) -> SexprError {
+        SexprError { line, column, message }
+    }
+
+    /// The next token, with its line and column.
+    fn token(&mut self) -> Result<(Token, usize, usize), SexprError> {
+        self.skip_whitespace();
+        let (line, column) = (self.line, self.column);
+        let (start, c) = match self.bump() {
+            Some(next) => next,
+            None => return Err(self.error(line, column, "unexpected end of input".to_string())),
+        };
+        let token = match c {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '[' => Token::OpenBracket,
+            ']' => Token::CloseBracket,
+            '"' => {
+                let mut escaped = false;
+                loop {
+                    match self.bump() {
+                        Some((_, '\\')) if !escaped => escaped = true,
+                        Some((end, '"')) if !escaped => break Token::Atom(self.text[start .. end + 1].to_string()),
+                        Some(_) => escaped = false,
+                        None => return Err(self.error(line, column, "unterminated string".to_string())),
+                    }
+                }
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = self.chars.peek() {
+                    if c.is_whitespace() || "()[]".contains(c) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    self.bump();
+                }
+                let atom = &self.text[start .. end];
+                if atom.starts_with('@') {
+                    match atom[1 ..].parse() {
+                        Ok(idx) => Token::Index(idx),
+                        Err(_) => return Err(self.error(line, column, format!("invalid trace index `{}`", atom))),
+                    }
+                } else {
+                    Token::Atom(atom.to_string())
+                }
+            }
+        };
+        Ok((token, line, column))
+    }
+
+    fn step<T: FromStr>(&self, atom: String, line: usize, column: usize) -> Result<T, SexprError> {
+        atom.parse().map_err(|_| self.error(line, column, format!("invalid step `{}`", atom)))
+    }
+
+    /// Steps of a node, from an atom or from the contents of brackets.
+    fn steps<T: FromStr>(&mut self, token: Token, line: usize, column: usize) -> Result<Vec<T>, SexprError> {
+        match token {
+            Token::Atom(atom) => Ok(vec![self.step(atom, line, column)?]),
+            Token::OpenBracket => {
+                let mut steps = vec![];
+                loop {
+                    match self.token()? {
+                        (Token::CloseBracket, _, _) => return Ok(steps),
+                        (Token::Atom(atom), line, column) => steps.push(self.step(atom, line, column)?),
+                        (token, line, column) => {
+                            return Err(self.error(line, column, format!("expected a step, found {:?}", token)));
+                        }
+                    }
+                }
+            }
+            token => Err(self.error(line, column, format!("expected a step, found {:?}", token))),
+        }
+    }
+
+    fn tree<T: FromStr>(&mut self) -> Result<Tree<T>, SexprError> {
+        let (token, line, column) = self.token()?;
+        self.tree_from(token, line, column)
+    }
+
+    fn tree_from<T: FromStr>(&mut self, token: Token, line: usize, column: usize) -> Result<Tree<T>, SexprError> {
+        match token {
+            Token::Open => {}
+            token => return Ok(Tree { steps: self.steps(token, line, column)?, children: vec![] }),
+        }
+        let (token, line, column) = self.token()?;
+        let steps = self.steps(token, line, column)?;
+        let mut children = vec![];
+        let mut next = 0;
+        loop {
+            let (token, line, column) = self.token()?;
+            let (idx, (token, line, column)) = match token {
+                Token::Close => return Ok(Tree { steps, children }),
+                Token::Index(idx) => (idx, self.token()?),
+                token => (next, (token, line, column)),
+            };
+            children.push((idx, self.tree_from(token, line, column)?));
+            next = idx + 1;
+        }
+    }
+}
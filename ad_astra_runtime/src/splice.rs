@@ -0,0 +1,189 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use cfg::Symbol;
+
+use super::{NeighborhoodError, NeighborhoodRuntime, PathTrie, SpliceError, Traced};
+
+/// The paths under the prefix, with the prefix stripped.
+pub fn subtree<T: PartialEq + Clone, P: AsRef<[T]>>(paths: &[P], prefix: &[T]) -> Vec<Vec<T>> {
+    paths.iter()
+        .map(|path| path.as_ref())
+        .filter(|path| path.len() > prefix.len() && path.starts_with(prefix))
+        .map(|path| path[prefix.len() ..].to_vec())
+        .collect()
+}
+
+/// Adds the paths of another neighborhood under the prefix, after the paths.
+pub fn graft<T: Clone, P: AsRef<[T]>, Q: AsRef<[T]>>(paths: &[P], prefix: &[T], other: &[Q]) -> Vec<Vec<T>> {
+    let mut grafted: Vec<Vec<T>> = paths.iter().map(|path| path.as_ref().to_vec()).collect();
+    grafted.extend(prefixed(prefix, other));
+    grafted
+}
+
+/// Swaps the subtree under the prefix for the paths of another neighborhood,
+/// which take the place of its first path. The paths stay the same if none
+/// goes through the prefix.
+pub fn replace<T: PartialEq + Clone, P: AsRef<[T]>, Q: AsRef<[T]>>(paths: &[P], prefix: &[T], other: &[Q]) -> Vec<Vec<T>> {
+    let mut replacement = prefixed(prefix, other);
+    let mut replaced = vec![];
+    for path in paths {
+        let path = path.as_ref();
+        if !path.starts_with(prefix) {
+            replaced.push(path.to_vec());
+        } else {
+            replaced.extend(replacement.drain(..));
+        }
+    }
+    replaced
+}
+
+fn prefixed<T: Clone, Q: AsRef<[T]>>(prefix: &[T], other: &[Q]) -> Vec<Vec<T>> {
+    other.iter().map(|path| prefix.iter().chain(path.as_ref()).cloned().collect()).collect()
+}
+
+impl<T: Traced + Ord + Clone + fmt::Debug> NeighborhoodRuntime<T> {
+    /// Extracts the subtree under the prefix, which must derive from a
+    /// nonterminal that the grammar expects under the prefix.
+    pub fn check_subtree<P: AsRef<[T]>>(&self, paths: &[P], prefix: &[T]) -> Result<Vec<Vec<T>>, SpliceError> {
+        let extracted = subtree(paths, prefix);
+        if extracted.is_empty() {
+            return Err(SpliceError::Missing { prefix: format!("{:?}", prefix) });
+        }
+        self.fit(prefix, &self.slot_types(prefix, &extracted[..])[..], &extracted[..])?;
+        Ok(extracted)
+    }
+
+    /// Grafts another neighborhood under the prefix. Only the grafted subtree
+    /// is checked, against the slot and along with the node whose offshoot
+    /// it is.
+    pub fn check_graft<P: AsRef<[T]>, Q: AsRef<[T]>>(&self, paths: &[P], prefix: &[T], other: &[Q]) -> Result<Vec<Vec<T>>, SpliceError> {
+        self.fit(prefix, &self.slot_types(prefix, &subtree(paths, prefix)[..])[..], other)?;
+        let grafted = graft(paths, prefix, other);
+        self.check_local(&grafted[..], prefix).map_err(SpliceError::Invalid)?;
+        Ok(grafted)
+    }
+
+    /// Replaces the subtree under the prefix with another neighborhood of the
+    /// same nonterminal. Only the new subtree is checked, along with the node
+    /// whose offshoot it is.
+    pub fn check_replace<P: AsRef<[T]>, Q: AsRef<[T]>>(&self, paths: &[P], prefix: &[T], other: &[Q]) -> Result<Vec<Vec<T>>, SpliceError> {
+        let replaced_subtree = subtree(paths, prefix);
+        if replaced_subtree.is_empty() {
+            return Err(SpliceError::Missing { prefix: format!("{:?}", prefix) });
+        }
+        self.fit(prefix, &self.slot_types(prefix, &replaced_subtree[..])[..], other)?;
+        let replaced = replace(paths, prefix, other);
+        self.check_local(&replaced[..], prefix).map_err(SpliceError::Invalid)?;
+        Ok(replaced)
+    }
+
+    /// The nonterminals a subtree under the prefix may derive from: those the
+    /// grammar predicts after the prefix, narrowed to the ones that the subtree
+    /// already there derives from, if any.
+    fn slot_types(&self, prefix: &[T], existing: &[Vec<T>]) -> Vec<String> {
+        let chart = self.chart();
+        let set = match self.scan_prefix(&chart, prefix) {
+            Some(set) => set,
+            None => return vec![],
+        };
+        // Nonterminals stand alone on the right-hand side of the rules that
+        // lower a slot, so those rules belong to no nonterminal.
+        let predicted: BTreeSet<Symbol> = chart.predictions(&set)
+            .filter(|&(lhs, _)| !self.nonterminals.values().any(|&sym| sym == lhs))
+            .map(|(_, sym)| sym)
+            .collect();
+        let expected: Vec<String> = self.nonterminals.iter()
+            .filter(|&(_, sym)| predicted.contains(sym))
+            .map(|(name, _)| name.clone())
+            .collect();
+        if existing.is_empty() {
+            return expected;
+        }
+        let narrowed: Vec<String> = expected.iter().filter(|name| self.derives(existing, name)).cloned().collect();
+        if narrowed.is_empty() {
+            expected
+        } else {
+            narrowed
+        }
+    }
+
+    /// Checks that the paths of another neighborhood derive from one of the
+    /// nonterminals expected under the prefix, whether or not it is allowed
+    /// as a whole tree. Where no nonterminal is expected, the paths are
+    /// validated after the prefix.
+    fn fit<Q: AsRef<[T]>>(&self, prefix: &[T], expected: &[String], other: &[Q]) -> Result<(), SpliceError> {
+        if expected.is_empty() {
+            return self.validate(&prefixed(prefix, other)[..]).map_err(|errors|
+                SpliceError::Invalid(errors.into_iter().map(NeighborhoodError::from).collect())
+            );
+        }
+        if expected.iter().any(|name| self.derives(other, name)) {
+            return Ok(());
+        }
+        let found = self.derived_from(other);
+        if found.is_empty() {
+            return Err(SpliceError::Invalid(self.check(other).err().unwrap_or_default()));
+        }
+        Err(SpliceError::Type {
+            prefix: format!("{:?}", prefix),
+            expected: expected.join(" or "),
+            found: found.join(" or "),
+        })
+    }
+
+    /// Checks the subtree under the prefix together with the node whose
+    /// offshoot it is, through one path of each of its other offshoots, and
+    /// returns the nonterminals of the subtrees among them. The nodes above
+    /// are left unchecked, since only some of their paths are among these.
+    pub(crate) fn check_local(&self, paths: &[Vec<T>], prefix: &[T]) -> Result<BTreeMap<Vec<T>, String>, Vec<NeighborhoodError>> {
+        let node = match prefix.split_last() {
+            Some((last, parent)) if last.trace().is_some() => parent,
+            _ => prefix,
+        };
+        let mut local = vec![];
+        let mut offshoots = BTreeSet::new();
+        for (i, path) in paths.iter().enumerate() {
+            if path.starts_with(prefix) {
+                local.push(i);
+            } else if node.len() < prefix.len() && path.len() > node.len() && path.starts_with(node) {
+                if offshoots.insert(&path[node.len()]) {
+                    local.push(i);
+                }
+            }
+        }
+        let local_paths: Vec<&Vec<T>> = local.iter().map(|&i| &paths[i]).collect();
+        let in_paths = |mut errors: Vec<NeighborhoodError>| {
+            for error in &mut errors {
+                let path = error.path_mut();
+                *path = local[*path];
+            }
+            errors
+        };
+        let derivations = self.unify(&local_paths[..]).map_err(&in_paths)?;
+        if let Err(errors) = self.check_arity(&local_paths[..], &derivations[..]) {
+            let errors: Vec<NeighborhoodError> = errors.into_iter().filter(|error| match error {
+                &NeighborhoodError::Arity(ref error) => error.step + 1 >= node.len(),
+                _ => true,
+            }).collect();
+            if !errors.is_empty() {
+                return Err(in_paths(errors));
+            }
+        }
+        let types = self.types_of(&local_paths[..], &derivations[..]).map_err(&in_paths)?;
+        Ok(types.into_iter().map(|(prefix, ty)| (prefix.to_vec(), ty)).collect())
+    }
+
+    /// Whether the paths form a tree derived from the nonterminal.
+    pub(crate) fn derives<Q: AsRef<[T]>>(&self, paths: &[Q], nonterminal: &str) -> bool {
+        self.validate_trie_from(&PathTrie::new(paths), self.nonterminals[nonterminal]).is_ok()
+    }
+
+    /// Every nonterminal the paths are derived from.
+    pub(crate) fn derived_from<Q: AsRef<[T]>>(&self, paths: &[Q]) -> Vec<&str> {
+        self.nonterminals.keys()
+            .filter(|name| self.derives(paths, name))
+            .map(|name| &name[..])
+            .collect()
+    }
+}
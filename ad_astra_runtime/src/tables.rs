@@ -0,0 +1,185 @@
+use std::fmt::Write;
+
+use cfg::Symbol;
+
+use super::{ExtPath, ExtStep, Matcher, NeighborhoodRuntime, Terminal, TerminalKind};
+
+/// The result of `process_rules`, in a form that can be embedded in a program
+/// as static data. `#[ast]` lowers its grammar while it expands and emits these
+/// tables, and the generated neighborhood is built from them alone, without
+/// its rules.
+///
+/// Symbols are numbered from 0, and types by their order in `types`.
+#[doc(hidden)]
+pub struct Tables<T: 'static> {
+    pub types: &'static [&'static str],
+    pub syms: usize,
+    pub start: usize,
+    pub nonterminals: &'static [(&'static str, usize)],
+    pub terminals: &'static [TerminalTable<T>],
+    pub rules: &'static [(usize, &'static [usize])],
+    pub productions: &'static [(usize, &'static str, usize)],
+    /// Symbols of positions in the rules, by rule and steps.
+    pub positions: &'static [(usize, &'static [ExtStep], usize)],
+    /// Whether the right-hand side of each rule is a choice of alternatives.
+    pub alternatives: &'static [bool],
+    /// The rules as `Display` writes them.
+    pub text: &'static str,
+}
+
+#[doc(hidden)]
+pub struct TerminalTable<T: 'static> {
+    pub sym: usize,
+    pub kind: TerminalKindTable<T>,
+    pub stmt: usize,
+    pub steps: &'static [ExtStep],
+    pub lhs: &'static str,
+    pub env: &'static [(&'static str, usize)],
+    pub first: bool,
+}
+
+#[doc(hidden)]
+pub enum TerminalKindTable<T: 'static> {
+    /// The index and arity of a trace.
+    Trace(usize, usize),
+    /// A leaf that matches a variant by name.
+    Variant(&'static str),
+    /// A leaf that matches a pattern, with the source of the pattern.
+    Pattern(&'static str, fn(&T) -> bool),
+}
+
+impl<T> NeighborhoodRuntime<T> {
+    /// Writes the processed grammar as a Rust expression of type `Tables`.
+    /// Patterns are written as they are named, so their names must be the
+    /// source of the patterns, such as `Step::Lit(_)`.
+    #[doc(hidden)]
+    pub fn tables_source(&self) -> String {
+        let start = self.start.expect("rules must be processed first");
+        let all_syms = self.rules.iter().flat_map(|&(lhs, ref rhs)| Some(lhs).into_iter().chain(rhs.iter().cloned()))
+            .chain(self.nonterminals.values().cloned())
+            .chain(self.terminals.iter().map(|&(sym, _)| sym))
+            .chain(self.external_syms.values().cloned());
+        let syms = all_syms.fold(start.usize() + 1, |syms, sym| syms.max(sym.usize() + 1));
+        let mut source = String::new();
+        writeln!(source, "::ad_astra_runtime::Tables {{").unwrap();
+        let types: Vec<String> = self.param_map.ids().map(|ty| format!("{:?}", self.param_map.get(ty))).collect();
+        writeln!(source, "types: &[{}],", types.join(", ")).unwrap();
+        writeln!(source, "syms: {},", syms).unwrap();
+        writeln!(source, "start: {},", start.usize()).unwrap();
+        writeln!(source, "nonterminals: &[").unwrap();
+        for (name, &sym) in &self.nonterminals {
+            writeln!(source, "({:?}, {}),", name, sym.usize()).unwrap();
+        }
+        writeln!(source, "],\nterminals: &[").unwrap();
+        for &(sym, ref terminal) in &self.terminals {
+            let kind = match terminal.kind {
+                TerminalKind::Trace { idx, arity } => format!("Trace({}, {})", idx, arity),
+                TerminalKind::Leaf(Matcher::Symbol(ref name)) => format!("Variant({:?})", name),
+                TerminalKind::Leaf(Matcher::Pattern { ref name, .. }) => {
+                    format!("Pattern({:?}, |val| match val {{ &{} => true, _ => false }})", name, name)
+                }
+                TerminalKind::Leaf(Matcher::ParamApply { .. }) => panic!("a type application is never a leaf"),
+            };
+            let env: Vec<String> = terminal.env.iter().map(|&(ref param, ty)| format!("({:?}, {})", param, ty)).collect();
+            writeln!(
+                source,
+                "::ad_astra_runtime::TerminalTable {{ sym: {}, kind: ::ad_astra_runtime::TerminalKindTable::{}, \
+                 stmt: {}, steps: {}, lhs: {:?}, env: &[{}], first: {} }},",
+                sym.usize(),
+                kind,
+                terminal.ext.stmt,
+                steps_source(&terminal.ext.steps),
+                terminal.lhs,
+                env.join(", "),
+                terminal.first,
+            ).unwrap();
+        }
+        writeln!(source, "],\nrules: &[").unwrap();
+        for &(lhs, ref rhs) in &self.rules {
+            let rhs: Vec<String> = rhs.iter().map(|sym| sym.usize().to_string()).collect();
+            writeln!(source, "({}, &[{}]),", lhs.usize(), rhs.join(", ")).unwrap();
+        }
+        writeln!(source, "],\nproductions: &[").unwrap();
+        for &(stmt, ref lhs, rhs) in &self.productions {
+            writeln!(source, "({}, {:?}, {}),", stmt, lhs, rhs.usize()).unwrap();
+        }
+        writeln!(source, "],\npositions: &[").unwrap();
+        for (ext, &sym) in &self.external_syms {
+            writeln!(source, "({}, {}, {}),", ext.stmt, steps_source(&ext.steps), sym.usize()).unwrap();
+        }
+        let alternatives: Vec<String> = self.alternatives.iter().map(|alternative| alternative.to_string()).collect();
+        writeln!(source, "],\nalternatives: &[{}],", alternatives.join(", ")).unwrap();
+        writeln!(source, "text: {:?},\n}}", self.to_string()).unwrap();
+        source
+    }
+
+}
+
+impl<T: 'static> NeighborhoodRuntime<T> {
+    /// Builds the processed grammar from tables, without any rules. The
+    /// runtime cannot take more rules, and writes the rules as the tables do.
+    #[doc(hidden)]
+    pub fn from_tables(tables: &Tables<T>) -> Self {
+        let mut runtime = NeighborhoodRuntime::new();
+        for ty in tables.types {
+            runtime.param_map.intern(ty);
+        }
+        let syms: Vec<Symbol> = (0 .. tables.syms).map(|_| runtime.external_grammar.sym()).collect();
+        for &(name, sym) in tables.nonterminals {
+            runtime.nonterminals.insert(name.to_string(), syms[sym]);
+        }
+        for terminal in tables.terminals {
+            let kind = match terminal.kind {
+                TerminalKindTable::Trace(idx, arity) => TerminalKind::Trace { idx, arity },
+                TerminalKindTable::Variant(name) => TerminalKind::Leaf(Matcher::variant(name)),
+                TerminalKindTable::Pattern(name, func) => TerminalKind::Leaf(Matcher::match_pattern(name, func)),
+            };
+            runtime.terminals.push((syms[terminal.sym], Terminal {
+                kind,
+                ext: ExtPath { stmt: terminal.stmt, steps: terminal.steps.to_vec() },
+                lhs: terminal.lhs.to_string(),
+                env: terminal.env.iter().map(|&(param, ty)| (param.to_string(), ty)).collect(),
+                first: terminal.first,
+            }));
+        }
+        for &(lhs, rhs) in tables.rules {
+            let rhs: Vec<Symbol> = rhs.iter().map(|&sym| syms[sym]).collect();
+            runtime.add_rule(syms[lhs], &rhs[..]);
+        }
+        for &(stmt, lhs, rhs) in tables.productions {
+            runtime.productions.push((stmt, lhs.to_string(), syms[rhs]));
+        }
+        for &(stmt, steps, sym) in tables.positions {
+            runtime.external_syms.insert(ExtPath { stmt, steps: steps.to_vec() }, syms[sym]);
+        }
+        runtime.alternatives = tables.alternatives.to_vec();
+        runtime.external_grammar.set_start(syms[tables.start]);
+        runtime.start = Some(syms[tables.start]);
+        runtime.precompiled = Some(tables.text);
+        runtime
+    }
+
+    /// Whether the grammar came from tables rather than from processing.
+    #[doc(hidden)]
+    pub fn is_precompiled(&self) -> bool {
+        self.precompiled.is_some()
+    }
+
+    /// The number of rules given to the runtime, none for precompiled tables.
+    #[doc(hidden)]
+    pub fn rule_count(&self) -> usize {
+        self.stmts.len() + self.allowed.len()
+    }
+}
+
+fn steps_source(steps: &[ExtStep]) -> String {
+    let steps: Vec<String> = steps.iter().map(|step| match step {
+        &ExtStep::Alternative(n) => format!("::ad_astra_runtime::ExtStep::Alternative({})", n),
+        &ExtStep::Idx(n) => format!("::ad_astra_runtime::ExtStep::Idx({})", n),
+        &ExtStep::Offshoot(n) => format!("::ad_astra_runtime::ExtStep::Offshoot({})", n),
+        &ExtStep::Repeat => "::ad_astra_runtime::ExtStep::Repeat".to_string(),
+        &ExtStep::IntroduceParam(ty) => format!("::ad_astra_runtime::ExtStep::IntroduceParam({})", ty),
+        &ExtStep::Rhs => "::ad_astra_runtime::ExtStep::Rhs".to_string(),
+    }).collect();
+    format!("&[{}]", steps.join(", "))
+}
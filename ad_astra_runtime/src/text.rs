@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path as FsPath;
+use std::rc::Rc;
+
+use super::{GrammarError, Matcher, Neighborhood, NeighborhoodRuntime};
+
+/// Step predicates that a grammar text refers to by name, such as `bool_value`,
+/// or by pattern, such as `Value(Value::Bool(_))`. Names are compared with
+/// whitespace removed.
+pub struct Predicates<T> {
+    predicates: BTreeMap<String, Rc<dyn Fn(&T) -> bool>>,
+}
+
+impl<T> Predicates<T> {
+    pub fn new() -> Self {
+        Predicates { predicates: BTreeMap::new() }
+    }
+
+    pub fn insert<F>(&mut self, name: &str, predicate: F) -> &mut Self
+        where F: Fn(&T) -> bool + 'static
+    {
+        self.predicates.insert(normalize(name), Rc::new(predicate));
+        self
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Rc<dyn Fn(&T) -> bool>> {
+        self.predicates.get(&normalize(name))
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// Whether the text after a step name reads as a Rust pattern, with a path or
+/// a wildcard, rather than as offshoots that follow the step.
+fn is_pattern(text: &str) -> bool {
+    text.contains("::") || text.split(|c: char| !(c.is_alphanumeric() || c == '_')).any(|word| word == "_")
+}
+
+impl<T: 'static> NeighborhoodRuntime<T> {
+    /// Builds and processes a grammar from text in the syntax of `neighborhood!`:
+    ///
+    /// ```text
+    /// Expr<bool> ::= Value(Value::Bool(_)) | LtExpr (Expr<isize> ^ Expr<isize>);
+    /// Expr<isize> ::= Value(Value::Int(_));
+    /// for<T> Expr<T> ::= IfExpr (Expr<bool> ^ Expr<T> ^ Expr<T>);
+    /// @allow for<T> Expr<T>
+    /// ```
+    ///
+    /// Rules may also be written in parentheses, and the semicolons may be
+    /// omitted. A step that is registered in `predicates` becomes a pattern;
+    /// any other name matches a step variant or a nonterminal.
+    pub fn from_grammar_str(text: &str, predicates: &Predicates<T>) -> Result<Self, Vec<GrammarError>> {
+        let mut runtime = NeighborhoodRuntime::new();
+        Parser { text, pos: 0, predicates }.grammar(&mut runtime).map_err(|error| vec![error])?;
+        runtime.process_rules()?;
+        Ok(runtime)
+    }
+
+    pub fn from_grammar_file<P: AsRef<FsPath>>(path: P, predicates: &Predicates<T>) -> Result<Self, Vec<GrammarError>> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(text) => NeighborhoodRuntime::from_grammar_str(&text, predicates),
+            Err(error) => Err(vec![GrammarError::Io {
+                path: path.display().to_string(),
+                message: error.to_string(),
+            }]),
+        }
+    }
+}
+
+struct Parser<'a, T: 'a> {
+    text: &'a str,
+    pos: usize,
+    predicates: &'a Predicates<T>,
+}
+
+impl<'a, T: 'static> Parser<'a, T> {
+    fn error(&self, message: String) -> GrammarError {
+        let before = &self.text[.. self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        GrammarError::Syntax { line, column, message }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos ..]
+    }
+
+    /// Skips whitespace and `//` comments, and returns the next character.
+    fn peek(&mut self) -> Option<char> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else {
+                return trimmed.chars().next();
+            }
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.peek();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), GrammarError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{}`", token)))
+        }
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        self.peek();
+        let rest = self.rest();
+        match rest.chars().next() {
+            Some(c) if c.is_alphabetic() || c == '_' => {}
+            _ => return None,
+        }
+        let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        self.pos += len;
+        Some(&rest[.. len])
+    }
+
+    fn expect_ident(&mut self) -> Result<&'a str, GrammarError> {
+        match self.ident() {
+            Some(ident) => Ok(ident),
+            None => Err(self.error("expected a name".to_string())),
+        }
+    }
+
+    /// The text up to the delimiter that closes the one just consumed.
+    fn delimited(&mut self, open: char, close: char) -> Result<&'a str, GrammarError> {
+        let rest = self.rest();
+        let mut depth = 1;
+        for (i, c) in rest.char_indices() {
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    self.pos += i + 1;
+                    return Ok(&rest[.. i]);
+                }
+            }
+        }
+        Err(self.error(format!("unclosed `{}`", open)))
+    }
+
+    fn grammar(&mut self, runtime: &mut NeighborhoodRuntime<T>) -> Result<(), GrammarError> {
+        while let Some(c) = self.peek() {
+            if c == ';' {
+                self.pos += 1;
+            } else if self.eat("(") {
+                self.stmt(runtime)?;
+                self.expect(")")?;
+            } else {
+                self.stmt(runtime)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn stmt(&mut self, runtime: &mut NeighborhoodRuntime<T>) -> Result<(), GrammarError> {
+        if self.eat("@") {
+            let keyword = self.expect_ident()?;
+            if keyword != "allow" {
+                return Err(self.error(format!("unknown directive `@{}`", keyword)));
+            }
+            runtime.allow(self.alternatives()?);
+            return Ok(());
+        }
+        let params = self.params()?;
+        let lhs = self.lhs()?;
+        self.expect("::=")?;
+        let mut rule = lhs.into_neighborhood().lhs_then(self.alternatives()?);
+        for param in params.into_iter().rev() {
+            rule = rule.introduce_param(param);
+        }
+        runtime.rule(rule);
+        Ok(())
+    }
+
+    fn params(&mut self) -> Result<Vec<&'a str>, GrammarError> {
+        let mut params = vec![];
+        loop {
+            let start = self.pos;
+            if self.ident() == Some("for") && self.eat("<") {
+                params.push(self.expect_ident()?);
+                self.expect(">")?;
+            } else {
+                self.pos = start;
+                return Ok(params);
+            }
+        }
+    }
+
+    fn lhs(&mut self) -> Result<Matcher<T>, GrammarError> {
+        let name = self.expect_ident()?;
+        if self.eat("<") {
+            let ty = self.delimited('<', '>')?;
+            Ok(Matcher::apply(name, ty.trim()))
+        } else {
+            Ok(Matcher::variant(name))
+        }
+    }
+
+    /// Whether a rule or a directive begins here, which ends the previous rule.
+    fn at_stmt(&mut self) -> bool {
+        let start = self.pos;
+        let at_stmt = self.peek() == Some('@') || (
+            self.params().is_ok() && self.lhs().is_ok() && self.eat("::=")
+        );
+        self.pos = start;
+        at_stmt
+    }
+
+    fn alternatives(&mut self) -> Result<Neighborhood<T>, GrammarError> {
+        let mut rhs = self.sequence()?;
+        while self.eat("|") {
+            rhs = rhs.or(self.sequence()?);
+        }
+        Ok(rhs)
+    }
+
+    fn sequence(&mut self) -> Result<Neighborhood<T>, GrammarError> {
+        let mut rhs = self.offshoots()?;
+        loop {
+            match self.peek() {
+                None | Some('|') | Some(')') | Some(';') => return Ok(rhs),
+                _ if self.at_stmt() => return Ok(rhs),
+                _ => rhs = rhs.then(self.offshoots()?),
+            }
+        }
+    }
+
+    fn offshoots(&mut self) -> Result<Neighborhood<T>, GrammarError> {
+        let mut rhs = self.repeat()?;
+        while self.eat("^") {
+            rhs = rhs.offshoot(self.repeat()?);
+        }
+        Ok(rhs)
+    }
+
+    fn repeat(&mut self) -> Result<Neighborhood<T>, GrammarError> {
+        let mut rhs = self.atom()?;
+        while self.eat("*") {
+            rhs = rhs.repeat();
+        }
+        Ok(rhs)
+    }
+
+    fn atom(&mut self) -> Result<Neighborhood<T>, GrammarError> {
+        if self.eat("(") {
+            let rhs = self.alternatives()?;
+            self.expect(")")?;
+            return Ok(rhs);
+        }
+        let start = self.pos;
+        let name = match self.ident() {
+            Some(name) => name,
+            None => return Err(self.error("expected a step, a nonterminal or `(`".to_string())),
+        };
+        if name == "for" && self.eat("<") {
+            let param = self.expect_ident()?;
+            self.expect(">")?;
+            return Ok(self.atom()?.introduce_param(param));
+        }
+        if self.eat("<") {
+            let ty = self.delimited('<', '>')?;
+            return Ok(Matcher::apply(name, ty.trim()).into_neighborhood());
+        }
+        let after_name = self.pos;
+        if self.eat("(") {
+            self.delimited('(', ')')?;
+            let pattern = &self.text[start .. self.pos];
+            if let Some(predicate) = self.predicates.get(pattern) {
+                return Ok(self.pattern(pattern, predicate.clone()));
+            }
+            if is_pattern(pattern) {
+                self.pos = start;
+                return Err(self.error(format!("unknown predicate `{}`", pattern)));
+            }
+            self.pos = after_name;
+        }
+        match self.predicates.get(name) {
+            Some(predicate) => Ok(self.pattern(name, predicate.clone())),
+            None => Ok(Matcher::variant(name).into_neighborhood()),
+        }
+    }
+
+    fn pattern(&self, name: &str, predicate: Rc<dyn Fn(&T) -> bool>) -> Neighborhood<T> {
+        Matcher::Pattern { name: normalize(name), func: predicate }.into_neighborhood()
+    }
+}
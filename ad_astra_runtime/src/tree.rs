@@ -0,0 +1,91 @@
+use super::{ConversionError, Traced};
+
+/// A node of a tree reconstructed from a neighborhood. A node holds the steps
+/// of a path up to the next trace, and its offshoots keyed by trace index.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tree<T> {
+    pub steps: Vec<T>,
+    pub children: Vec<(usize, Tree<T>)>,
+}
+
+/// Values that flatten into a tree of steps, such as typed enums generated from
+/// the grammar or ADTs with `#[derive(IntoNeighborhood)]`.
+pub trait IntoTree<T> {
+    fn to_tree(&self) -> Tree<T>;
+}
+
+/// Values that are rebuilt from a tree of steps.
+pub trait FromTree<T>: Sized {
+    fn from_tree(tree: &Tree<T>) -> Result<Self, ConversionError>;
+}
+
+impl<T: Traced + PartialEq + Clone> Tree<T> {
+    /// Builds the family of trees that the paths go through. Paths that diverge
+    /// before a trace lead to separate trees, and offshoots with the same trace
+    /// index that diverge lead to separate children with that index.
+    pub fn from_paths<P: AsRef<[T]>>(paths: &[P]) -> Vec<Tree<T>> {
+        build(paths.iter().map(|path| path.as_ref()).collect())
+    }
+
+    /// Emits one path per leaf, with a trace step before every offshoot.
+    pub fn to_paths(trees: &[Tree<T>]) -> Vec<Vec<T>> {
+        let mut paths = vec![];
+        for tree in trees {
+            tree.flatten(&mut vec![], &mut paths);
+        }
+        paths
+    }
+
+    fn flatten(&self, prefix: &mut Vec<T>, paths: &mut Vec<Vec<T>>) {
+        let len = prefix.len();
+        prefix.extend(self.steps.iter().cloned());
+        if self.children.is_empty() {
+            paths.push(prefix.clone());
+        }
+        for &(idx, ref child) in &self.children {
+            prefix.push(T::from_trace(idx));
+            child.flatten(prefix, paths);
+            prefix.pop();
+        }
+        prefix.truncate(len);
+    }
+}
+
+fn build<T: Traced + PartialEq + Clone>(paths: Vec<&[T]>) -> Vec<Tree<T>> {
+    // Each group holds a node's steps and the remainders of its paths, by trace.
+    let mut groups: Vec<(&[T], Option<Vec<(usize, Vec<&[T]>)>>)> = vec![];
+    for path in paths {
+        match path.iter().position(|step| step.trace().is_some()) {
+            None => groups.push((path, None)),
+            Some(i) => {
+                let idx = path[i].trace().unwrap();
+                let rest = &path[i + 1 ..];
+                let position = groups.iter().position(|&(steps, ref offshoots)|
+                    offshoots.is_some() && steps == &path[.. i]
+                );
+                let position = match position {
+                    Some(position) => position,
+                    None => {
+                        groups.push((&path[.. i], Some(vec![])));
+                        groups.len() - 1
+                    }
+                };
+                let offshoots = groups[position].1.as_mut().unwrap();
+                match offshoots.iter_mut().find(|&&mut (offshoot, _)| offshoot == idx) {
+                    Some(&mut (_, ref mut rests)) => rests.push(rest),
+                    None => offshoots.push((idx, vec![rest])),
+                }
+            }
+        }
+    }
+    groups.into_iter().map(|(steps, offshoots)| {
+        let mut offshoots = offshoots.unwrap_or_else(Vec::new);
+        offshoots.sort_by_key(|&(idx, _)| idx);
+        Tree {
+            steps: steps.to_vec(),
+            children: offshoots.into_iter().flat_map(|(idx, rests)|
+                build(rests).into_iter().map(move |child| (idx, child))
+            ).collect(),
+        }
+    }).collect()
+}
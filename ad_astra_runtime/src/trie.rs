@@ -0,0 +1,195 @@
+use std::fmt;
+
+use cfg::Symbol;
+
+use super::chart::Chart;
+use super::{NeighborhoodRuntime, Traced, ValidationError};
+
+/// Paths of a neighborhood with their common prefixes merged.
+pub struct PathTrie<'a, T: 'a> {
+    paths: Vec<&'a [T]>,
+    nodes: Vec<TrieNode>,
+    roots: Vec<usize>,
+    empty: Vec<usize>,
+}
+
+struct TrieNode {
+    path: usize,
+    depth: usize,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    ends: Vec<usize>,
+}
+
+impl<'a, T: PartialEq> PathTrie<'a, T> {
+    pub fn new<P: AsRef<[T]>>(paths: &'a [P]) -> Self {
+        let mut trie = PathTrie {
+            paths: paths.iter().map(|path| path.as_ref()).collect(),
+            nodes: vec![],
+            roots: vec![],
+            empty: vec![],
+        };
+        for path in 0 .. trie.paths.len() {
+            trie.insert(path);
+        }
+        trie
+    }
+
+    fn insert(&mut self, path: usize) {
+        let steps = self.paths[path];
+        if steps.is_empty() {
+            self.empty.push(path);
+            return;
+        }
+        let mut parent: Option<usize> = None;
+        for (depth, step) in steps.iter().enumerate() {
+            let siblings = match parent {
+                Some(node) => &self.nodes[node].children,
+                None => &self.roots,
+            };
+            let existing = siblings.iter().cloned().find(|&node| self.step(node) == step);
+            let node = match existing {
+                Some(node) => node,
+                None => {
+                    let node = self.nodes.len();
+                    self.nodes.push(TrieNode { path, depth, parent, children: vec![], ends: vec![] });
+                    match parent {
+                        Some(parent) => self.nodes[parent].children.push(node),
+                        None => self.roots.push(node),
+                    }
+                    node
+                }
+            };
+            parent = Some(node);
+        }
+        self.nodes[parent.unwrap()].ends.push(path);
+    }
+}
+
+impl<'a, T> PathTrie<'a, T> {
+    /// The number of distinct prefixes, which is the number of steps scanned
+    /// during validation.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.empty.is_empty()
+    }
+
+    pub(crate) fn step(&self, node: usize) -> &'a T {
+        let node = &self.nodes[node];
+        &self.paths[node.path][node.depth]
+    }
+
+    /// Index of a path that goes through the given node.
+    pub(crate) fn path(&self, node: usize) -> usize {
+        self.nodes[node].path
+    }
+
+    pub(crate) fn depth(&self, node: usize) -> usize {
+        self.nodes[node].depth
+    }
+
+    pub(crate) fn children(&self, node: usize) -> &[usize] {
+        &self.nodes[node].children[..]
+    }
+
+    pub(crate) fn parent(&self, node: usize) -> Option<usize> {
+        self.nodes[node].parent
+    }
+
+    pub(crate) fn root_nodes(&self) -> &[usize] {
+        &self.roots[..]
+    }
+
+    /// Indices of the paths that end at the given node.
+    pub(crate) fn ends(&self, node: usize) -> &[usize] {
+        &self.nodes[node].ends[..]
+    }
+
+    /// Steps before the given node.
+    pub(crate) fn prefix(&self, node: usize) -> &'a [T] {
+        let node = &self.nodes[node];
+        &self.paths[node.path][.. node.depth]
+    }
+
+    /// Steps from the first node down to the last one, inclusive.
+    pub(crate) fn steps(&self, first: usize, last: usize) -> &'a [T] {
+        let node = &self.nodes[last];
+        &self.paths[node.path][self.nodes[first].depth .. node.depth + 1]
+    }
+
+    /// Indices of the paths that go through the given node.
+    pub(crate) fn paths_under(&self, node: usize) -> Vec<usize> {
+        let mut paths = vec![];
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            paths.extend(self.nodes[node].ends.iter().cloned());
+            stack.extend(self.nodes[node].children.iter().cloned());
+        }
+        paths
+    }
+}
+
+impl<T: Traced + PartialEq + fmt::Debug> NeighborhoodRuntime<T> {
+    pub fn validate<P: AsRef<[T]>>(&self, paths: &[P]) -> Result<(), Vec<ValidationError>> {
+        self.validate_trie(&PathTrie::new(paths))
+    }
+
+    /// Validates every path of the trie. Each distinct prefix is scanned once,
+    /// into one Earley set shared by every path that goes through it.
+    pub fn validate_trie(&self, trie: &PathTrie<T>) -> Result<(), Vec<ValidationError>> {
+        self.validate_trie_from(trie, self.start.expect("rules must be processed first"))
+    }
+
+    /// Validates the paths as derived from the given symbol rather than from
+    /// the allowed nonterminals.
+    pub(crate) fn validate_trie_from(&self, trie: &PathTrie<T>, start: Symbol) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+        let chart = Chart::new(&self.rules[..], start);
+        let root = chart.root();
+        if !trie.empty.is_empty() && !chart.is_finished(&root, &root) {
+            let expected = self.expected(&chart, &root);
+            for &path in &trie.empty {
+                errors.push(ValidationError { path, step: 0, found: None, expected: expected.clone() });
+            }
+        }
+        let mut stack: Vec<_> = trie.roots.iter().map(|&node| (node, root.clone())).collect();
+        while let Some((node, set)) = stack.pop() {
+            let step = trie.step(node);
+            let depth = trie.nodes[node].depth;
+            let next = match chart.scan(&set, &self.terminal_syms(step)) {
+                Some(next) => next,
+                None => {
+                    let expected = self.expected(&chart, &set);
+                    for path in trie.paths_under(node) {
+                        errors.push(ValidationError {
+                            path,
+                            step: depth,
+                            found: Some(format!("{:?}", step)),
+                            expected: expected.clone(),
+                        });
+                    }
+                    continue;
+                }
+            };
+            let ends = &trie.nodes[node].ends;
+            if !ends.is_empty() && !chart.is_finished(&next, &root) {
+                let expected = self.expected(&chart, &next);
+                for &path in ends {
+                    errors.push(ValidationError { path, step: depth + 1, found: None, expected: expected.clone() });
+                }
+            }
+            for &child in &trie.nodes[node].children {
+                stack.push((child, next.clone()));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            errors.sort_by_key(|error| error.path);
+            Err(errors)
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::{AmbiguityError, Derivation, NeighborhoodError, NeighborhoodRuntime, Traced};
+
+impl<T: Traced + Ord + fmt::Debug> NeighborhoodRuntime<T> {
+    /// Infers the nonterminal of every subtree. Keys are the prefixes leading to
+    /// each subtree: the empty prefix for the root, and a prefix ending with a
+    /// trace for every offshoot.
+    pub fn types<'a, P: AsRef<[T]>>(&self, paths: &'a [P]) -> Result<BTreeMap<&'a [T], String>, Vec<NeighborhoodError>> {
+        let derivations = self.unify(paths)?;
+        self.types_of(paths, &derivations[..])
+    }
+
+    /// Infers the nonterminals from derivations the paths were unified with.
+    pub(crate) fn types_of<'a, P: AsRef<[T]>>(&self, paths: &'a [P], derivations: &[Vec<Derivation>]) -> Result<BTreeMap<&'a [T], String>, Vec<NeighborhoodError>> {
+        let mut candidates: BTreeMap<&'a [T], (usize, usize, BTreeSet<String>)> = BTreeMap::new();
+        for (p, (path, path_derivations)) in paths.iter().zip(derivations.iter()).enumerate() {
+            let steps = path.as_ref();
+            for derivation in path_derivations {
+                for (i, step) in derivation.steps.iter().enumerate() {
+                    if let Some(nonterminal) = step.starts.first() {
+                        candidates.entry(&steps[.. i])
+                            .or_insert_with(|| (p, i, BTreeSet::new()))
+                            .2.insert(nonterminal.clone());
+                    }
+                }
+            }
+        }
+        let mut types = BTreeMap::new();
+        let mut errors = vec![];
+        for (prefix, (path, step, nonterminals)) in candidates {
+            if nonterminals.len() == 1 {
+                types.insert(prefix, nonterminals.into_iter().next().unwrap());
+            } else {
+                errors.push(NeighborhoodError::from(AmbiguityError {
+                    path,
+                    step,
+                    types: nonterminals.into_iter().collect(),
+                }));
+            }
+        }
+        if errors.is_empty() {
+            Ok(types)
+        } else {
+            Err(errors)
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Range;
+
+use super::{Derivation, NeighborhoodError, NeighborhoodRuntime, PathTrie, Traced, UnificationError};
+
+impl<T: Traced + PartialEq + fmt::Debug> NeighborhoodRuntime<T> {
+    /// Checks that every node binds each of its `for<T>` parameters to a single
+    /// type, whichever offshoot is followed. Returns the derivations that agree
+    /// with these bindings.
+    pub fn unify<P: AsRef<[T]>>(&self, paths: &[P]) -> Result<Vec<Vec<Derivation>>, Vec<NeighborhoodError>> {
+        let mut derivations = self.derive(paths).map_err(|errors|
+            errors.into_iter().map(NeighborhoodError::from).collect::<Vec<_>>()
+        )?;
+        let mut nodes: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+        for (p, owners) in prefix_owners(paths).into_iter().enumerate() {
+            for (i, q) in owners.into_iter().enumerate() {
+                nodes.entry((q, i)).or_insert_with(Vec::new).push(p);
+            }
+        }
+        let num_types = self.param_map.ids().count();
+        // Narrowing the bindings of one node can narrow those of another that
+        // was visited earlier, so the nodes are visited until nothing changes.
+        loop {
+            let mut errors = vec![];
+            let mut changed = false;
+            for (&(q, i), through) in &nodes {
+                let mut candidates: BTreeMap<String, Vec<(usize, BTreeSet<String>)>> = BTreeMap::new();
+                for &p in through {
+                    let mut path_candidates: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+                    for derivation in &derivations[p] {
+                        if let Some(range) = application(derivation, i) {
+                            for (param, ty) in bindings(derivation, range) {
+                                path_candidates.entry(param).or_insert_with(BTreeSet::new).insert(ty);
+                            }
+                        }
+                    }
+                    for (param, tys) in path_candidates {
+                        candidates.entry(param).or_insert_with(Vec::new).push((p, tys));
+                    }
+                }
+                let mut allowed: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+                for (param, per_path) in candidates {
+                    let mut common = per_path[0].1.clone();
+                    for &(_, ref tys) in &per_path[1 ..] {
+                        common = common.intersection(tys).cloned().collect();
+                    }
+                    if common.is_empty() {
+                        let types: BTreeSet<String> = per_path.iter()
+                            .filter(|&&(_, ref tys)| tys.len() < num_types)
+                            .filter_map(|&(p, _)| derivations[p].first().map(|derivation| slot(derivation, i)))
+                            .collect();
+                        errors.push(NeighborhoodError::from(UnificationError {
+                            path: q,
+                            step: i,
+                            param,
+                            types: types.into_iter().collect(),
+                        }));
+                    } else {
+                        allowed.insert(param, common);
+                    }
+                }
+                for &p in through {
+                    let len = derivations[p].len();
+                    derivations[p].retain(|derivation| match application(derivation, i) {
+                        Some(range) => bindings(derivation, range).iter().all(|&(ref param, ref ty)|
+                            allowed.get(param).map_or(true, |tys| tys.contains(ty))
+                        ),
+                        None => true,
+                    });
+                    changed |= derivations[p].len() != len;
+                }
+            }
+            if !changed {
+                return if errors.is_empty() {
+                    Ok(derivations)
+                } else {
+                    Err(errors)
+                };
+            }
+        }
+    }
+}
+
+/// For every step of every path, finds the first path that shares the prefix
+/// ending at that step. Paths with equal owners at a step go through the same node.
+pub(crate) fn prefix_owners<T: PartialEq, P: AsRef<[T]>>(paths: &[P]) -> Vec<Vec<usize>> {
+    // Paths are inserted in order, so each node of the trie belongs to the
+    // first path that goes through it.
+    fn walk<T>(trie: &PathTrie<T>, node: usize, prefix: &mut Vec<usize>, owners: &mut Vec<Vec<usize>>) {
+        prefix.push(trie.path(node));
+        for &path in trie.ends(node) {
+            owners[path] = prefix.clone();
+        }
+        for &child in trie.children(node) {
+            walk(trie, child, prefix, owners);
+        }
+        prefix.pop();
+    }
+    let trie = PathTrie::new(paths);
+    let mut owners = vec![vec![]; paths.len()];
+    for &root in trie.root_nodes() {
+        walk(&trie, root, &mut vec![], &mut owners);
+    }
+    owners
+}
+
+/// The steps of a rule application that begins at the given step.
+fn application(derivation: &Derivation, step: usize) -> Option<Range<usize>> {
+    if derivation.steps[step].starts.is_empty() {
+        return None;
+    }
+    let end = (step + 1 .. derivation.steps.len())
+        .find(|&i| !derivation.steps[i].starts.is_empty())
+        .unwrap_or(derivation.steps.len());
+    Some(step .. end)
+}
+
+fn bindings(derivation: &Derivation, range: Range<usize>) -> BTreeSet<(String, String)> {
+    derivation.steps[range].iter().flat_map(|step| step.bindings.iter().cloned()).collect()
+}
+
+/// The nonterminal of the offshoot that the derivation follows from the given step.
+fn slot(derivation: &Derivation, step: usize) -> String {
+    let range = application(derivation, step).expect("no rule application");
+    match derivation.steps.get(range.end) {
+        Some(child) => child.starts[0].clone(),
+        None => derivation.steps[step].starts[0].clone(),
+    }
+}
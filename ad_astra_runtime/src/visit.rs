@@ -0,0 +1,117 @@
+use std::fmt;
+
+use super::{Derivation, NeighborhoodError, NeighborhoodRuntime, Step, StepDerivation, Traced, Tree};
+
+/// A node of a checked neighborhood, with the production it was derived under.
+/// Generated `Visitor` and `Fold` traits dispatch on `rule` and `alternative`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Node<T> {
+    pub steps: Vec<T>,
+    /// The nonterminal of the production, e.g. `Expr<bool>`.
+    pub lhs: String,
+    /// Index of the rule, in the order given to `NeighborhoodRuntime::rule`.
+    pub rule: usize,
+    /// The alternative of the rule taken, if its right-hand side has any.
+    pub alternative: Option<usize>,
+    /// Type parameters bound by `for<T>`, e.g. `("T", "bool")`.
+    pub bindings: Vec<(String, String)>,
+    /// The node of a nonterminal that ends the production without an
+    /// offshoot, such as `Expr<bool>` in `Not Expr<bool>`. It holds the rest
+    /// of the steps, and the offshoots go to it.
+    pub inner: Option<Box<Node<T>>>,
+    /// Offshoots in the order of their traces.
+    pub offshoots: Vec<(usize, Node<T>)>,
+}
+
+impl<T: Traced + PartialEq + Clone + fmt::Debug> NeighborhoodRuntime<T> {
+    /// Checks the neighborhood and rebuilds its trees, each node labeled with
+    /// its production.
+    pub fn nodes<P: AsRef<[T]>>(&self, paths: &[P]) -> Result<Vec<Node<T>>, Vec<NeighborhoodError>> {
+        self.check(paths)?;
+        let trees = Tree::from_paths(paths);
+        // Paths of the trees, in the order the trees are walked below.
+        let leaves = Tree::to_paths(&trees);
+        let derivations = self.unify(&leaves)?;
+        let mut leaf = 0;
+        Ok(trees.iter().map(|tree| self.node(tree, 0, &mut leaf, &derivations)).collect())
+    }
+
+    fn node(&self, tree: &Tree<T>, depth: usize, leaf: &mut usize, derivations: &[Vec<Derivation>]) -> Node<T> {
+        let end = depth + tree.steps.len();
+        let steps = &derivations[*leaf][0].steps;
+        // Each nonterminal that begins within the steps, rather than at an
+        // offshoot, starts an inner node.
+        let mut starts = vec![depth];
+        starts.extend((depth + 1 .. end).filter(|&i| !steps[i].starts.is_empty()));
+        // The innermost node takes the production of the trace before its
+        // offshoots, or that of its last step.
+        let last = starts[starts.len() - 1];
+        let step = if tree.children.is_empty() { end.saturating_sub(1) } else { end };
+        let mut node = self.labeled(tree.steps[last - depth ..].to_vec(), &steps[step]);
+        if tree.children.is_empty() {
+            *leaf += 1;
+        }
+        for &(idx, ref child) in &tree.children {
+            node.offshoots.push((idx, self.node(child, end + 1, leaf, derivations)));
+        }
+        // Outer nodes take the production of their last step.
+        for bounds in starts.windows(2).rev() {
+            let outer_steps = tree.steps[bounds[0] - depth .. bounds[1] - depth].to_vec();
+            let mut outer = self.labeled(outer_steps, &steps[bounds[1] - 1]);
+            outer.inner = Some(Box::new(node));
+            node = outer;
+        }
+        node
+    }
+
+    fn labeled(&self, steps: Vec<T>, derivation: &StepDerivation) -> Node<T> {
+        let alternative = if self.alternatives[derivation.rule] {
+            derivation.alternatives.first().cloned()
+        } else {
+            None
+        };
+        Node {
+            steps,
+            lhs: derivation.lhs.clone(),
+            rule: derivation.rule,
+            alternative,
+            bindings: derivation.bindings.clone(),
+            inner: None,
+            offshoots: vec![],
+        }
+    }
+}
+
+impl<T> NeighborhoodRuntime<T> {
+    /// Whether the right-hand side of the rule is a choice of alternatives.
+    /// Only the paths of the right-hand side count, and not the one of the
+    /// left-hand side, which has no `Rhs` step.
+    pub(crate) fn has_alternatives(&self, rule: usize) -> bool {
+        let mut rhs_paths = self.stmts[rule].paths.iter().filter_map(|path|
+            path.steps.iter().position(|step| *step == Step::Rhs).map(|rhs| &path.steps[rhs + 1 ..])
+        ).peekable();
+        rhs_paths.peek().is_some() && rhs_paths.all(|steps| steps.first().map_or(false, |step| step.is_alternative()))
+    }
+}
+
+impl<T: Traced + Clone> Node<T> {
+    pub fn to_tree(&self) -> Tree<T> {
+        match self.inner {
+            Some(ref inner) => {
+                let mut tree = inner.to_tree();
+                tree.steps = self.steps.iter().cloned().chain(tree.steps).collect();
+                tree
+            }
+            None => Tree {
+                steps: self.steps.clone(),
+                children: self.offshoots.iter().map(|&(idx, ref node)| (idx, node.to_tree())).collect(),
+            },
+        }
+    }
+
+    /// Emits one path per leaf, as `Tree::to_paths` does.
+    pub fn to_paths(nodes: &[Node<T>]) -> Vec<Vec<T>> {
+        let trees: Vec<Tree<T>> = nodes.iter().map(|node| node.to_tree()).collect();
+        Tree::to_paths(&trees)
+    }
+}
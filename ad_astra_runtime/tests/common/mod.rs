@@ -0,0 +1,44 @@
+//! The grammar of typed expressions shared by the tests of trees and their
+//! edits.
+
+#![allow(dead_code)]
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Value {
+    Bool(bool),
+    Int(isize),
+    Str(&'static str),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Step {
+    Value(Value),
+    IfExpr,
+    EqExpr,
+    LtExpr,
+    Trace(usize),
+}
+
+ast! {
+    Neighborhood, Path, Step, (for<T> Expr<T>) =>
+        ((Expr<bool>) ::=
+            (@m Step::Value(Value::Bool(_))) |
+            (EqExpr (for<T> ((Expr<T>) ^ (Expr<T>)))) |
+            (LtExpr ((Expr<isize>) ^ (Expr<isize>))));
+        ((Expr<isize>) ::=
+            (@m Step::Value(Value::Int(_))));
+        ((Expr<&'static str>) ::=
+            (@m Step::Value(Value::Str(_))));
+        (for<T> ((Expr<T>) ::=
+            (IfExpr ((Expr<bool>) ^ (Expr<T>) ^ (Expr<T>)))));
+}
+
+/// `(IfExpr (LtExpr 420 130) "a" "b")`
+pub fn neighborhood() -> Neighborhood {
+    Neighborhood::with_paths(vec![
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(130))],
+        path![Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("a"))],
+        path![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("b"))],
+    ])
+}
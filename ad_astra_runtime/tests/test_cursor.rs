@@ -0,0 +1,65 @@
+#[macro_use]
+extern crate ad_astra_runtime;
+
+#[macro_use]
+mod common;
+
+use self::common::*;
+
+#[test]
+fn test_cursor_navigation() {
+    let tree = neighborhood();
+    let roots = tree.roots();
+    assert_eq!(roots.len(), 1);
+    let root = roots[0].clone();
+    assert_eq!(root.step(), &Step::IfExpr);
+    assert_eq!(root.depth(), 0);
+    assert_eq!(root.parent(), None);
+    assert_eq!(root.next_sibling(), None);
+    let lt = root.child(0).unwrap();
+    assert_eq!(lt.step(), &Step::LtExpr);
+    assert_eq!(lt.prefix(), &[Step::IfExpr, Step::Trace(0)][..]);
+    assert_eq!(lt.parent(), Some(root.clone()));
+    let left = lt.child(0).unwrap();
+    assert_eq!(left.depth(), 2);
+    assert!(left.is_leaf());
+    assert_eq!(left.paths(), vec![0]);
+    assert_eq!(left.next_sibling().map(|cursor| cursor.step()), Some(&Step::Value(Value::Int(130))));
+    assert_eq!(left.parent().and_then(|parent| parent.parent()), Some(root.clone()));
+    let steps: Vec<&Step> = root.children().iter().map(|child| child.step()).collect();
+    assert_eq!(steps, vec![&Step::LtExpr, &Step::Value(Value::Str("a")), &Step::Value(Value::Str("b"))]);
+    let traces: Vec<Option<usize>> = root.children().iter().map(|child| child.trace()).collect();
+    assert_eq!(traces, vec![Some(0), Some(1), Some(2)]);
+    assert_eq!(root.child(2).unwrap().next_sibling(), None);
+    assert_eq!(root.child(3), None);
+}
+
+#[test]
+fn test_cursor_diverging_paths() {
+    use self::Step::*;
+    let tree = Neighborhood::with_paths(vec![
+        path![IfExpr, LtExpr, Trace(0), Value(self::Value::Int(1))],
+        path![IfExpr, Value(self::Value::Bool(true))],
+    ]);
+    let root = tree.roots()[0].clone();
+    assert_eq!(root.steps(), &[IfExpr][..]);
+    let children = root.children();
+    assert_eq!(children.len(), 2);
+    assert_eq!(children[0].steps(), &[LtExpr][..]);
+    assert_eq!(children[0].trace(), None);
+    assert_eq!(children[0].next_sibling(), Some(children[1].clone()));
+    assert_eq!(children[0].child(0).unwrap().depth(), 2);
+    assert_eq!(children[1].paths(), vec![1]);
+}
+
+#[test]
+fn test_cursor_at_prefix() {
+    let tree = neighborhood();
+    let left = tree.cursor(&[Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0)]).unwrap();
+    assert_eq!(left.step(), &Step::Value(Value::Int(420)));
+    assert_eq!(left.depth(), 2);
+    assert_eq!(left.parent().map(|parent| parent.steps()), Some(&[Step::LtExpr][..]));
+    assert_eq!(tree.cursor(&[]).map(|root| root.depth()), Some(0));
+    assert_eq!(tree.cursor(&[Step::IfExpr]), None);
+    assert_eq!(tree.cursor(&[Step::IfExpr, Step::Trace(3)]), None);
+}
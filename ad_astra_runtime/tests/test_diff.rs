@@ -0,0 +1,102 @@
+#[macro_use]
+extern crate ad_astra_runtime;
+
+#[macro_use]
+mod common;
+
+use ad_astra_runtime::{diff, patch, Edit, PatchError};
+
+use self::common::*;
+
+#[test]
+fn test_diff_shift_and_replace() {
+    let old = neighborhood();
+    let new = Neighborhood::with_paths(vec![
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(130))],
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(420))],
+        path![Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("c"))],
+        path![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("b"))],
+    ]);
+    let diff = old.diff(&new);
+    assert_eq!(diff.edits.len(), 3);
+    assert_eq!(diff.to_string(), concat!(
+        "  IfExpr\n",
+        "    Trace(0) LtExpr\n",
+        "~     Trace(0) -> Trace(1) Value(Int(420))\n",
+        "~     Trace(1) -> Trace(0) Value(Int(130))\n",
+        "-   Trace(1) Value(Str(\"a\"))\n",
+        "+   Trace(1) Value(Str(\"c\"))\n",
+    ));
+    assert_eq!(old.patch(&diff).unwrap().to_tree(), new.to_tree());
+    assert_eq!(new.patch(&diff).err(), Some(PatchError {
+        edit: 0,
+        message: "no path [IfExpr, Trace(0), LtExpr, Trace(0), Value(Int(420))]".to_string(),
+    }));
+    assert!(old.diff(&old).is_empty());
+}
+
+#[test]
+fn test_diff_insert_and_remove() {
+    let old = neighborhood().replace(&[Step::IfExpr, Step::Trace(2)], &Neighborhood::new());
+    let new = neighborhood();
+    let inserted = Edit::Insert {
+        prefix: vec![Step::IfExpr, Step::Trace(2)],
+        paths: vec![vec![Step::Value(Value::Str("b"))]],
+    };
+    let diff = old.diff(&new);
+    assert_eq!(diff.edits, vec![inserted]);
+    assert_eq!(diff.to_string(), "  IfExpr\n+   Trace(2) Value(Str(\"b\"))\n");
+    assert_eq!(old.patch(&diff).unwrap().to_tree(), new.to_tree());
+    let removed = Edit::Remove {
+        prefix: vec![Step::IfExpr, Step::Trace(2)],
+        paths: vec![vec![Step::Value(Value::Str("b"))]],
+    };
+    assert_eq!(new.diff(&old).edits, vec![removed]);
+}
+
+#[test]
+fn test_patch_inserts_among_siblings() {
+    let old = vec![
+        vec![Step::IfExpr, Step::Trace(0), Step::Value(Value::Bool(true))],
+        vec![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("b"))],
+        vec![Step::Value(Value::Str("c"))],
+    ];
+    let new = vec![
+        vec![Step::IfExpr, Step::Trace(0), Step::Value(Value::Bool(true))],
+        vec![Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("a"))],
+        vec![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("b"))],
+        vec![Step::Value(Value::Str("c"))],
+    ];
+    let diff = diff(&old, &new);
+    assert_eq!(diff.edits, vec![Edit::Insert {
+        prefix: vec![Step::IfExpr, Step::Trace(1)],
+        paths: vec![vec![Step::Value(Value::Str("a"))]],
+    }]);
+    assert_eq!(patch(&old, &diff), Ok(new));
+}
+
+#[test]
+fn test_diff_roots_by_position() {
+    let old = vec![
+        vec![Step::Value(Value::Str("b"))],
+        vec![Step::Value(Value::Str("c"))],
+    ];
+    let new = vec![
+        vec![Step::Value(Value::Str("a"))],
+        vec![Step::Value(Value::Str("b"))],
+        vec![Step::Value(Value::Str("d"))],
+    ];
+    let root = |name| vec![vec![Step::Value(Value::Str(name))]];
+    assert_eq!(diff(&old, &new).edits, vec![
+        Edit::Remove { prefix: vec![], paths: root("c") },
+        Edit::Insert { prefix: vec![], paths: root("a") },
+        Edit::Insert { prefix: vec![], paths: root("d") },
+    ]);
+    let new = vec![
+        vec![Step::Value(Value::Str("b"))],
+        vec![Step::Value(Value::Str("d"))],
+    ];
+    assert_eq!(diff(&old, &new).edits, vec![
+        Edit::Replace { prefix: vec![], old: root("c"), new: root("d") },
+    ]);
+}
@@ -0,0 +1,77 @@
+extern crate ad_astra_runtime;
+
+use ad_astra_runtime::{NeighborhoodRuntime, Predicates, Traced};
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Value {
+    Bool(bool),
+    Int(isize),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Step {
+    Value(Value),
+    IfExpr,
+    EqExpr,
+    LtExpr,
+    Trace(usize),
+}
+
+impl Traced for Step {
+    fn trace(&self) -> Option<usize> {
+        match self {
+            &Step::Trace(idx) => Some(idx),
+            _ => None,
+        }
+    }
+
+    fn from_trace(idx: usize) -> Self {
+        Step::Trace(idx)
+    }
+}
+
+const GRAMMAR: &str = "
+    Expr<bool> ::=
+        Value(Value::Bool(_)) |
+        EqExpr for<T> (Expr<T> ^ Expr<T>) |
+        LtExpr (Expr<isize> ^ Expr<isize>)
+    Expr<isize> ::= int_value
+    for<T> Expr<T> ::= IfExpr (Expr<bool> ^ Expr<T> ^ Expr<T>);
+    @allow for<T> Expr<T>
+";
+
+fn predicates() -> Predicates<Step> {
+    let mut predicates = Predicates::new();
+    predicates
+        .insert("Value(Value::Bool(_))", |step| match step {
+            &Step::Value(Value::Bool(_)) => true,
+            _ => false,
+        })
+        .insert("int_value", |step| match step {
+            &Step::Value(Value::Int(_)) => true,
+            _ => false,
+        });
+    predicates
+}
+
+fn step(name: &str) -> Step {
+    match name {
+        "int_value" => Step::Value(Value::Int(1)),
+        "Value(Value::Bool(_))" => Step::Value(Value::Bool(true)),
+        "IfExpr" => Step::IfExpr,
+        "EqExpr" => Step::EqExpr,
+        "LtExpr" => Step::LtExpr,
+        _ => panic!("unexpected step {}", name),
+    }
+}
+
+#[test]
+fn test_generate() {
+    let runtime = NeighborhoodRuntime::from_grammar_str(GRAMMAR, &predicates()).unwrap();
+    let mut seed = 7;
+    for depth in 0 .. 5 {
+        let paths = runtime.generate(depth, |n| { seed = (seed * 31 + 17) % 1009; seed % n }, step);
+        assert!(!paths.is_empty());
+        assert_eq!(runtime.check(&paths), Ok(()));
+    }
+}
@@ -0,0 +1,39 @@
+#[macro_use]
+extern crate ad_astra_runtime;
+
+use ad_astra_runtime::{GrammarError, NeighborhoodRuntime};
+
+pub enum Step {
+    Bool(bool),
+    Next,
+}
+
+#[test]
+fn test_grammar_errors() {
+    let mut runtime = NeighborhoodRuntime::<Step>::new();
+    runtime.rule(rule!(((Expr<bool>) ::= (@m Step::Bool(_)) | (Expr<char>))));
+    runtime.rule(rule!((Loop ::= (Next Loop))));
+    runtime.rule(rule!((for<T> ((Unused) ::= (@m Step::Bool(_))))));
+    runtime.allow(rule!((Expr<bool>)));
+    let errors = runtime.process_rules().unwrap_err();
+    assert!(errors.contains(&GrammarError::Undefined { name: "Expr<char>".to_string() }));
+    assert!(errors.contains(&GrammarError::Unproductive { rule: 1, lhs: "Loop".to_string() }));
+    assert!(errors.contains(&GrammarError::Unreachable { rule: 1, lhs: "Loop".to_string() }));
+    assert!(errors.contains(&GrammarError::Unreachable { rule: 2, lhs: "Unused".to_string() }));
+    assert!(errors.contains(&GrammarError::UnusedParam { rule: 2, param: "T".to_string() }));
+    assert_eq!(errors.len(), 5);
+}
+
+#[test]
+fn test_never_instantiated() {
+    let mut runtime = NeighborhoodRuntime::<Step>::new();
+    runtime.rule(rule!(((Expr<bool>) ::= (@m Step::Bool(_)))));
+    runtime.rule(rule!((for<T> ((Wrap<T>) ::= (Next (Item<T>))))));
+    runtime.rule(rule!((for<T> ((Item<T>) ::= (@m Step::Bool(_))))));
+    runtime.allow(rule!((Expr<bool>)));
+    let errors = runtime.process_rules().unwrap_err();
+    assert_eq!(errors, vec![
+        GrammarError::NeverInstantiated { rule: 1, lhs: "Wrap<T>".to_string() },
+        GrammarError::NeverInstantiated { rule: 2, lhs: "Item<T>".to_string() },
+    ]);
+}
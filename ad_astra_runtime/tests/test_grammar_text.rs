@@ -0,0 +1,155 @@
+extern crate ad_astra_runtime;
+
+use ad_astra_runtime::{GrammarError, Matcher, NeighborhoodRuntime, Predicates, Traced};
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Value {
+    Bool(bool),
+    Int(isize),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Step {
+    Value(Value),
+    IfExpr,
+    EqExpr,
+    LtExpr,
+    Trace(usize),
+}
+
+impl Traced for Step {
+    fn trace(&self) -> Option<usize> {
+        match self {
+            &Step::Trace(idx) => Some(idx),
+            _ => None,
+        }
+    }
+
+    fn from_trace(idx: usize) -> Self {
+        Step::Trace(idx)
+    }
+}
+
+const GRAMMAR: &str = "
+    // The README grammar, as text.
+    Expr<bool> ::=
+        Value(Value::Bool(_)) |
+        EqExpr for<T> (Expr<T> ^ Expr<T>) |
+        LtExpr (Expr<isize> ^ Expr<isize>)
+    Expr<isize> ::= int_value
+    for<T> Expr<T> ::= IfExpr (Expr<bool> ^ Expr<T> ^ Expr<T>);
+    @allow for<T> Expr<T>
+";
+
+fn predicates() -> Predicates<Step> {
+    let mut predicates = Predicates::new();
+    predicates
+        .insert("Value(Value::Bool(_))", |step| match step {
+            &Step::Value(Value::Bool(_)) => true,
+            _ => false,
+        })
+        .insert("int_value", |step| match step {
+            &Step::Value(Value::Int(_)) => true,
+            _ => false,
+        });
+    predicates
+}
+
+#[test]
+fn test_grammar_text() {
+    let runtime = NeighborhoodRuntime::from_grammar_str(GRAMMAR, &predicates()).unwrap();
+    let paths = vec![
+        vec![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+        vec![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(130))],
+        vec![Step::IfExpr, Step::Trace(1), Step::Value(Value::Bool(true))],
+        vec![Step::IfExpr, Step::Trace(2), Step::Value(Value::Bool(false))],
+    ];
+    assert_eq!(runtime.validate(&paths), Ok(()));
+    let invalid = vec![
+        vec![Step::LtExpr, Step::Trace(0), Step::Value(Value::Bool(true))],
+    ];
+    assert!(runtime.validate(&invalid).is_err());
+}
+
+#[test]
+fn test_grammar_text_parenthesized() {
+    let text = "
+        (Expr<bool> ::= Value(Value::Bool(_)) | LtExpr (Expr<isize> ^ Expr<isize>))
+        (Expr<isize> ::= int_value)
+        (@allow Expr<bool>)
+    ";
+    let runtime = NeighborhoodRuntime::from_grammar_str(text, &predicates()).unwrap();
+    let paths = vec![
+        vec![Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(1))],
+        vec![Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(2))],
+    ];
+    assert_eq!(runtime.validate(&paths), Ok(()));
+}
+
+#[test]
+fn test_grammar_text_errors() {
+    let error = NeighborhoodRuntime::from_grammar_str("Expr<bool> ::= int_value;\nExpr<isize> := int_value", &predicates())
+        .err().unwrap();
+    assert_eq!(error, vec![GrammarError::Syntax { line: 2, column: 13, message: "expected `::=`".to_string() }]);
+    assert_eq!(error[0].to_string(), "line 2, column 13: expected `::=`");
+    let error = NeighborhoodRuntime::from_grammar_str("Expr<bool> ::= LtExpr (Expr<isize>", &predicates())
+        .err().unwrap();
+    assert_eq!(error, vec![GrammarError::Syntax { line: 1, column: 35, message: "expected `)`".to_string() }]);
+    let error = NeighborhoodRuntime::from_grammar_str("Expr<bool> ::= Value(Value::Str(_))", &predicates())
+        .err().unwrap();
+    assert_eq!(error, vec![GrammarError::Syntax {
+        line: 1,
+        column: 16,
+        message: "unknown predicate `Value(Value::Str(_))`".to_string(),
+    }]);
+    let error = NeighborhoodRuntime::from_grammar_file("/nonexistent/grammar.ast", &predicates()).err().unwrap();
+    match error[0] {
+        GrammarError::Io { ref path, .. } => assert_eq!(path, "/nonexistent/grammar.ast"),
+        ref error => panic!("unexpected error: {}", error),
+    }
+}
+
+#[test]
+fn test_grammar_display() {
+    let runtime = NeighborhoodRuntime::from_grammar_str(GRAMMAR, &predicates()).unwrap();
+    let text = runtime.to_string();
+    assert_eq!(text, "\
+Expr<bool> ::= Value(Value::Bool(_)) | EqExpr for<T> (Expr<T> ^ Expr<T>) | LtExpr (Expr<isize> ^ Expr<isize>);
+Expr<isize> ::= int_value;
+for<T> Expr<T> ::= IfExpr (Expr<bool> ^ Expr<T> ^ Expr<T>);
+@allow for<T> Expr<T>;
+");
+    let reparsed = NeighborhoodRuntime::from_grammar_str(&text, &predicates()).unwrap();
+    assert_eq!(reparsed.to_string(), text);
+}
+
+#[test]
+fn test_grammar_display_macro_pattern() {
+    let matcher = Matcher::match_pattern(stringify!(Step::Value(Value::Bool(_))), |_: &Step| true);
+    assert_eq!(matcher.to_string(), "Value(Value::Bool(_))");
+}
+
+#[test]
+fn test_grammar_display_nested() {
+    let text = "
+        List<isize> ::= (int_value | (IfExpr | EqExpr) int_value)* ^ (List<isize> LtExpr)*;
+        @allow List<isize>
+    ";
+    let runtime = NeighborhoodRuntime::from_grammar_str(text, &predicates()).unwrap();
+    assert_eq!(runtime.to_string(), "\
+List<isize> ::= (int_value | (IfExpr | EqExpr) int_value)* ^ (List<isize> LtExpr)*;
+@allow List<isize>;
+");
+}
+
+#[test]
+fn test_dump_lowering() {
+    let text = "Expr<isize> ::= int_value | LtExpr (Expr<isize> ^ Expr<isize>); @allow Expr<isize>";
+    let runtime = NeighborhoodRuntime::from_grammar_str(text, &predicates()).unwrap();
+    let dump = runtime.dump_lowering();
+    assert!(dump.contains("<0> = isize\n"));
+    assert!(dump.contains(" = rule 0 / rhs / |1 / #1 / ^0\n"));
+    assert!(dump.lines().any(|line| line.starts_with("Expr<isize> ::= s")));
+    assert!(dump.lines().any(|line| line.contains(":trace(1)")));
+    assert!(dump.lines().any(|line| line.contains(":int_value")));
+}
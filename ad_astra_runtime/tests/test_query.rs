@@ -0,0 +1,82 @@
+extern crate ad_astra_runtime;
+
+use ad_astra_runtime::{Predicates, Query, QueryError, Traced};
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Value {
+    Bool(bool),
+    Int(isize),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Step {
+    Value(Value),
+    IfExpr,
+    LtExpr,
+    Trace(usize),
+}
+
+impl Traced for Step {
+    fn trace(&self) -> Option<usize> {
+        match self {
+            &Step::Trace(idx) => Some(idx),
+            _ => None,
+        }
+    }
+
+    fn from_trace(idx: usize) -> Self {
+        Step::Trace(idx)
+    }
+
+    fn variant_names() -> Option<&'static [&'static str]> {
+        Some(&["Value", "IfExpr", "LtExpr", "Trace"])
+    }
+}
+
+fn paths() -> Vec<Vec<Step>> {
+    use self::Step::*;
+    vec![
+        vec![IfExpr, Trace(0), LtExpr, Trace(0), Value(self::Value::Int(420))],
+        vec![IfExpr, Trace(0), LtExpr, Trace(1), Value(self::Value::Int(130))],
+        vec![IfExpr, Trace(1), LtExpr, Trace(0), Value(self::Value::Int(1))],
+        vec![IfExpr, Trace(1), LtExpr, Trace(1), Value(self::Value::Bool(true))],
+        vec![IfExpr, Trace(2), Value(self::Value::Int(0))],
+    ]
+}
+
+#[test]
+fn test_query_descendants() {
+    let paths = paths();
+    let query = Query::new("IfExpr/Trace(0)/**/LtExpr").unwrap();
+    assert_eq!(query.select(&paths), vec![&paths[0][.. 3]]);
+    let query = Query::new("**/LtExpr").unwrap();
+    assert_eq!(query.select(&paths), vec![&paths[0][.. 3], &paths[2][.. 3]]);
+    let query = Query::new("IfExpr/**").unwrap();
+    assert_eq!(query.select(&paths).len(), 15);
+}
+
+#[test]
+fn test_query_steps() {
+    let paths = paths();
+    let query = Query::new("**/LtExpr/Trace(1)/Value").unwrap();
+    assert_eq!(query.select(&paths), vec![&paths[1][..], &paths[3][..]]);
+    let query = Query::new("*/Trace(*)/Value(Int( 0 ))").unwrap();
+    assert_eq!(query.select(&paths), vec![&paths[4][..]]);
+    let mut predicates = Predicates::new();
+    predicates.insert("int", |step: &Step| match step { &Step::Value(Value::Int(_)) => true, _ => false });
+    let query = Query::with_predicates("**/LtExpr/Trace(1)/int", &predicates).unwrap();
+    assert_eq!(query.select(&paths), vec![&paths[1][..]]);
+    assert!(query.matches(&paths[1]));
+    assert!(!query.matches(&paths[1][.. 3]));
+}
+
+#[test]
+fn test_query_errors() {
+    let error = |text| Query::<Step>::new(text).err();
+    assert_eq!(error("IfExpr//LtExpr"), Some(QueryError { column: 8, message: "expected a step".to_string() }));
+    assert_eq!(error("Value(Int(1)"), Some(QueryError { column: 13, message: "expected `)`".to_string() }));
+    assert_eq!(error("Trace(x)"), Some(QueryError { column: 1, message: "expected a trace index".to_string() }));
+    assert_eq!(error("Value(Str(\"é\"))//LtExpr"), Some(QueryError { column: 17, message: "expected a step".to_string() }));
+    assert_eq!(error("IfExpr/**/LtExr"), Some(QueryError { column: 11, message: "unknown variant `LtExr`".to_string() }));
+    assert_eq!(error("Valeu(Int(1))"), Some(QueryError { column: 1, message: "unknown variant `Valeu`".to_string() }));
+}
@@ -0,0 +1,111 @@
+#[macro_use]
+extern crate ad_astra_runtime;
+
+#[macro_use]
+mod common;
+
+use ad_astra_runtime::{PatternStep, RewriteError};
+use ad_astra_runtime::PatternStep::Var;
+
+use self::common::*;
+
+fn step(step: Step) -> PatternStep<Step> {
+    PatternStep::Step(step)
+}
+
+#[test]
+fn test_rewrite_if_true() {
+    let tree = Neighborhood::with_paths(vec![
+        path![Step::IfExpr, Step::Trace(0), Step::Value(Value::Bool(true))],
+        path![Step::IfExpr, Step::Trace(1), Step::IfExpr, Step::Trace(0), Step::Value(Value::Bool(true))],
+        path![Step::IfExpr, Step::Trace(1), Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("x"))],
+        path![Step::IfExpr, Step::Trace(1), Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("y"))],
+        path![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("z"))],
+    ]);
+    let mut rewriter = tree.rewriter();
+    rewriter.rule(
+        vec![
+            vec![step(Step::IfExpr), step(Step::Trace(0)), step(Step::Value(Value::Bool(true)))],
+            vec![step(Step::IfExpr), step(Step::Trace(1)), PatternStep::var("a")],
+            vec![step(Step::IfExpr), step(Step::Trace(2)), PatternStep::var("b")],
+        ],
+        vec![vec![PatternStep::var("a")]],
+    ).unwrap();
+    let rewritten = tree.rewrite(&rewriter).unwrap();
+    let expected = Neighborhood::with_paths(vec![path![Step::Value(Value::Str("x"))]]);
+    assert_eq!(rewritten.to_tree(), expected.to_tree());
+}
+
+#[test]
+fn test_rewrite_renumber() {
+    let tree = Neighborhood::with_paths(vec![
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(130))],
+        path![Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("a"))],
+        path![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("b"))],
+    ]);
+    let mut rewriter = tree.rewriter();
+    rewriter.rule(
+        vec![
+            vec![step(Step::LtExpr), step(Step::Trace(0)), PatternStep::var("a")],
+            vec![step(Step::LtExpr), step(Step::Trace(1)), PatternStep::var("b")],
+        ],
+        vec![
+            vec![step(Step::EqExpr), step(Step::Trace(1)), PatternStep::var("b")],
+            vec![step(Step::EqExpr), step(Step::Trace(2)), PatternStep::var("a")],
+        ],
+    ).unwrap();
+    let rewritten = tree.rewrite(&rewriter).unwrap();
+    let expected = Neighborhood::with_paths(vec![
+        path![Step::IfExpr, Step::Trace(0), Step::EqExpr, Step::Trace(0), Step::Value(Value::Int(130))],
+        path![Step::IfExpr, Step::Trace(0), Step::EqExpr, Step::Trace(1), Step::Value(Value::Int(420))],
+        path![Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("a"))],
+        path![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("b"))],
+    ]);
+    assert_eq!(rewritten.to_tree(), expected.to_tree());
+    assert_eq!(rewritten.check(), Ok(()));
+}
+
+#[test]
+fn test_rewrite_type_error() {
+    let tree = neighborhood();
+    let mut rewriter = tree.rewriter();
+    rewriter.rule(
+        vec![
+            vec![step(Step::LtExpr), step(Step::Trace(0)), Var("a".to_string())],
+            vec![step(Step::LtExpr), step(Step::Trace(1)), Var("b".to_string())],
+        ],
+        vec![vec![Var("a".to_string())]],
+    ).unwrap();
+    match tree.rewrite(&rewriter) {
+        Err(RewriteError::Type { rule: 0, .. }) => {}
+        other => panic!("expected a type error, found {:?}", other.map(|tree| tree.to_tree())),
+    }
+}
+
+#[test]
+fn test_rewrite_invalid_result() {
+    let tree = neighborhood();
+    let mut rewriter = tree.rewriter();
+    rewriter.rule(
+        vec![
+            vec![step(Step::LtExpr), step(Step::Trace(0)), PatternStep::var("a")],
+            vec![step(Step::LtExpr), step(Step::Trace(1)), PatternStep::var("b")],
+        ],
+        vec![vec![step(Step::LtExpr), step(Step::Trace(0)), PatternStep::var("a")]],
+    ).unwrap();
+    match tree.rewrite(&rewriter) {
+        Err(RewriteError::Invalid(_)) => {}
+        other => panic!("expected an invalid neighborhood, found {:?}", other.map(|tree| tree.to_tree())),
+    }
+}
+
+#[test]
+fn test_rewrite_rule_errors() {
+    let tree = Neighborhood::new();
+    let mut rewriter = tree.rewriter();
+    let error = rewriter.rule(vec![vec![PatternStep::var("a"), step(Step::IfExpr)]], vec![]).err();
+    assert_eq!(error, Some(RewriteError::Rule { rule: 0, message: "variable `a` does not end its path".to_string() }));
+    let error = rewriter.rule(vec![vec![step(Step::IfExpr)]], vec![vec![PatternStep::var("a")]]).err();
+    assert_eq!(error, Some(RewriteError::Rule { rule: 0, message: "variable `a` is not bound by the pattern".to_string() }));
+}
@@ -0,0 +1,57 @@
+#![cfg(feature = "serde")]
+
+#[macro_use]
+extern crate ad_astra_runtime;
+
+use ad_astra_runtime::Validated;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum Value {
+    Bool(bool),
+    Int(isize),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+pub enum Step {
+    Value(Value),
+    LtExpr,
+    Trace(usize),
+}
+
+ast! {
+    Neighborhood, Path, Step, #[trace] Trace, (Expr<bool>) =>
+        ((Expr<bool>) ::=
+            (@m Step::Value(Value::Bool(_))) |
+            (LtExpr ((Expr<isize>) ^ (Expr<isize>))));
+        ((Expr<isize>) ::=
+            (@m Step::Value(Value::Int(_))));
+}
+
+ast_serde!(Neighborhood, Path, Step);
+
+#[test]
+fn test_serde_round_trip() {
+    let tree = Neighborhood::with_paths(vec![
+        path![Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+        path![Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(130))],
+    ]);
+    let json = serde_json::to_string(&tree).unwrap();
+    let tree: Neighborhood = serde_json::from_str(&json).unwrap();
+    assert_eq!(tree.validate(), Ok(()));
+    let tree: Validated<Neighborhood> = serde_json::from_str(&json).unwrap();
+    assert_eq!(tree.into_inner().to_tree().len(), 1);
+}
+
+#[test]
+fn test_serde_validated_invalid() {
+    let tree = Neighborhood::with_paths(vec![
+        path![Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+        path![Step::LtExpr, Step::Trace(1), Step::Value(Value::Bool(false))],
+    ]);
+    let json = serde_json::to_string(&tree).unwrap();
+    let tree: Neighborhood = serde_json::from_str(&json).unwrap();
+    assert!(tree.validate().is_err());
+    let error = serde_json::from_str::<Validated<Neighborhood>>(&json).err().unwrap();
+    assert!(error.to_string().starts_with("invalid neighborhood: path 1, step 2"));
+}
@@ -0,0 +1,101 @@
+#[macro_use]
+extern crate ad_astra_runtime;
+
+use std::fmt;
+use std::str::FromStr;
+
+use ad_astra_runtime::{from_sexpr, to_sexpr, SexprError, Tree};
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Value {
+    Int(isize),
+    Str(String),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Step {
+    Value(Value),
+    IfExpr,
+    LtExpr,
+    Trace(usize),
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Step::Value(Value::Int(value)) => write!(f, "{}", value),
+            &Step::Value(Value::Str(ref value)) => write!(f, "{:?}", value),
+            &Step::Trace(idx) => write!(f, "Trace({})", idx),
+            step => write!(f, "{:?}", step),
+        }
+    }
+}
+
+impl FromStr for Step {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, ()> {
+        match text {
+            "IfExpr" => Ok(Step::IfExpr),
+            "LtExpr" => Ok(Step::LtExpr),
+            _ if text.starts_with('"') && text.ends_with('"') && text.len() > 1 => {
+                Ok(Step::Value(Value::Str(text[1 .. text.len() - 1].to_string())))
+            }
+            _ => text.parse().map(|value| Step::Value(Value::Int(value))).map_err(|_| ()),
+        }
+    }
+}
+
+ast! {
+    Neighborhood, Path, Step, #[trace] Trace, (for<T> Expr<T>) =>
+        ((Expr<bool>) ::=
+            (LtExpr ((Expr<isize>) ^ (Expr<isize>))));
+        ((Expr<isize>) ::=
+            (@m Step::Value(Value::Int(_))));
+        ((Expr<String>) ::=
+            (@m Step::Value(Value::Str(_))));
+        (for<T> ((Expr<T>) ::=
+            (IfExpr ((Expr<bool>) ^ (Expr<T>) ^ (Expr<T>)))));
+}
+
+fn str(value: &str) -> Step {
+    Step::Value(Value::Str(value.to_string()))
+}
+
+#[test]
+fn test_sexpr_print() {
+    let tree = Neighborhood::with_paths(vec![
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(130))],
+        path![Step::IfExpr, Step::Trace(1), str("a")],
+        path![Step::IfExpr, Step::Trace(2), str("b")],
+    ]);
+    assert_eq!(tree.validate(), Ok(()));
+    assert_eq!(to_sexpr(&tree.to_tree()), r#"(IfExpr (LtExpr 420 130) "a" "b")"#);
+}
+
+#[test]
+fn test_sexpr_parse() {
+    let trees: Vec<Tree<Step>> = from_sexpr(r#"(IfExpr (LtExpr 420 130) "a b" "c")"#).unwrap();
+    let tree = Neighborhood::from_tree(&trees);
+    assert_eq!(tree.validate(), Ok(()));
+    assert_eq!(trees[0].children[1], (1, Tree { steps: vec![str("a b")], children: vec![] }));
+}
+
+#[test]
+fn test_sexpr_multiple_steps_and_indices() {
+    let text = "([IfExpr LtExpr] @1 2 3)\n[]";
+    let trees: Vec<Tree<Step>> = from_sexpr(text).unwrap();
+    assert_eq!(trees[0].steps, vec![Step::IfExpr, Step::LtExpr]);
+    assert_eq!(trees[0].children.iter().map(|&(idx, _)| idx).collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(trees[1], Tree { steps: vec![], children: vec![] });
+    assert_eq!(to_sexpr(&trees), text);
+}
+
+#[test]
+fn test_sexpr_errors() {
+    let error = from_sexpr::<Step>("(IfExpr\n  (LtExpr 1 x))").unwrap_err();
+    assert_eq!(error, SexprError { line: 2, column: 13, message: "invalid step `x`".to_string() });
+    let error = from_sexpr::<Step>("(IfExpr 1").unwrap_err();
+    assert_eq!(error.message, "unexpected end of input");
+}
@@ -11,15 +11,19 @@
 #[macro_use]
 extern crate ad_astra_runtime;
 
+use ad_astra_runtime::NeighborhoodError;
+
 type FragmentId = u32;
 type BindId = u32;
 
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Value {
     Bool(bool),
     Int(isize),
     Str(&'static str),
 }
 
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub enum Step {
     Value(Value),
     IfExpr,
@@ -52,5 +56,194 @@ fn test_simple_sexpr() {
             path![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("b"))],
         ]
     );
-    tree.validate();
+    assert_eq!(tree.validate(), Ok(()));
+}
+
+#[test]
+fn test_simple_sexpr_tree() {
+    let tree = Neighborhood::with_paths(
+        vec![
+            path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+            path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(130))],
+            path![Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("a"))],
+            path![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("b"))],
+        ]
+    );
+    let roots = tree.to_tree();
+    assert_eq!(roots.len(), 1);
+    assert_eq!(roots[0].steps, vec![Step::IfExpr]);
+    let traces: Vec<usize> = roots[0].children.iter().map(|&(idx, _)| idx).collect();
+    assert_eq!(traces, vec![0, 1, 2]);
+    assert_eq!(roots[0].children[0].1.steps, vec![Step::LtExpr]);
+    assert_eq!(roots[0].children[0].1.children.len(), 2);
+    assert_eq!(roots[0].children[2].1.steps, vec![Step::Value(Value::Str("b"))]);
+
+    let round_trip = Neighborhood::from_tree(&roots[..]);
+    let steps: Vec<_> = round_trip.paths.iter().map(|path| &path.steps).collect();
+    let expected: Vec<_> = tree.paths.iter().map(|path| &path.steps).collect();
+    assert_eq!(steps, expected);
+}
+
+#[test]
+fn test_simple_sexpr_types() {
+    let tree = Neighborhood::with_paths(
+        vec![
+            path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+            path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(130))],
+            path![Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("a"))],
+            path![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("b"))],
+        ]
+    );
+    let types = tree.types().unwrap();
+    assert_eq!(types.get(&[][..]).map(|ty| &ty[..]), Some("Expr<&'static str>"));
+    assert_eq!(types.get(&[Step::IfExpr, Step::Trace(0)][..]).map(|ty| &ty[..]), Some("Expr<bool>"));
+    assert_eq!(
+        types.get(&[Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(1)][..]).map(|ty| &ty[..]),
+        Some("Expr<isize>")
+    );
+    assert_eq!(types.len(), 6);
+}
+
+#[test]
+fn test_simple_sexpr_derivation() {
+    let tree = Neighborhood::with_paths(
+        vec![
+            path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+            path![Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("a"))],
+        ]
+    );
+    let derivations = tree.derive().unwrap();
+    assert!(!derivations[0].is_empty());
+    for derivation in &derivations[0] {
+        assert_eq!(derivation.steps[2].lhs, "Expr<bool>");
+        assert_eq!(derivation.steps[2].starts, vec!["Expr<bool>".to_string()]);
+        assert_eq!(derivation.steps[2].alternatives, vec![2]);
+        assert_eq!(derivation.steps[4].lhs, "Expr<isize>");
+    }
+    assert_eq!(derivations[1].len(), 1);
+    assert_eq!(derivations[1][0].steps[0].lhs, "Expr<&'static str>");
+    assert_eq!(
+        derivations[1][0].steps[0].bindings,
+        vec![("T".to_string(), "&'static str".to_string())]
+    );
+}
+
+#[test]
+fn test_simple_sexpr_unification() {
+    let tree = Neighborhood::with_paths(
+        vec![
+            path![Step::EqExpr, Step::Trace(0), Step::Value(Value::Int(1))],
+            path![Step::EqExpr, Step::Trace(1), Step::Value(Value::Int(2))],
+        ]
+    );
+    assert!(tree.unify().is_ok());
+
+    let tree = Neighborhood::with_paths(
+        vec![
+            path![Step::EqExpr, Step::Trace(0), Step::Value(Value::Bool(true))],
+            path![Step::EqExpr, Step::Trace(1), Step::Value(Value::Int(2))],
+        ]
+    );
+    assert_eq!(tree.validate(), Ok(()));
+    let errors = tree.unify().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    match errors[0] {
+        NeighborhoodError::Unification(ref error) => {
+            assert_eq!((error.path, error.step), (0, 0));
+            assert_eq!(error.param, "T");
+            assert_eq!(error.types, vec!["Expr<bool>".to_string(), "Expr<isize>".to_string()]);
+        }
+        ref other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[test]
+fn test_simple_sexpr_nested_unification() {
+    let paths = vec![
+        vec![Step::IfExpr, Step::Trace(0), Step::Value(Value::Bool(true))],
+        vec![Step::IfExpr, Step::Trace(1), Step::IfExpr, Step::Trace(0), Step::Value(Value::Bool(false))],
+        vec![Step::IfExpr, Step::Trace(1), Step::IfExpr, Step::Trace(1), Step::Value(Value::Int(1))],
+        vec![Step::IfExpr, Step::Trace(1), Step::IfExpr, Step::Trace(2), Step::Value(Value::Int(2))],
+        vec![Step::IfExpr, Step::Trace(2), Step::Value(Value::Int(3))],
+    ];
+    let tree = Neighborhood::with_paths(paths.iter().cloned().map(Path::with_steps).collect());
+    assert!(tree.unify().is_ok());
+
+    // The inner `IfExpr` settles its type from its last two offshoots, which
+    // conflict with the outer one. The order of the paths does not matter.
+    let mut paths = paths;
+    paths[4] = vec![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("a"))];
+    for paths in vec![paths.clone(), paths.into_iter().rev().collect()] {
+        let tree = Neighborhood::with_paths(paths.into_iter().map(Path::with_steps).collect());
+        assert_eq!(tree.validate(), Ok(()));
+        let errors = tree.unify().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        match errors[0] {
+            NeighborhoodError::Unification(ref error) => {
+                assert_eq!(error.step, 0);
+                assert_eq!(error.param, "T");
+            }
+            ref other => panic!("unexpected error: {}", other),
+        }
+    }
+}
+
+#[test]
+fn test_simple_sexpr_missing_offshoot() {
+    let tree = Neighborhood::with_paths(
+        vec![
+            path![Step::IfExpr, Step::Trace(0), Step::Value(Value::Bool(true))],
+            path![Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("a"))],
+        ]
+    );
+    assert_eq!(tree.validate(), Ok(()));
+    let errors = tree.check().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    match errors[0] {
+        NeighborhoodError::Arity(ref error) => {
+            assert_eq!((error.path, error.step), (0, 0));
+            assert_eq!(error.prefix, "[IfExpr]");
+            assert_eq!(error.arity, 3);
+            assert_eq!(error.missing, vec![2]);
+            assert!(error.extra.is_empty());
+        }
+        ref other => panic!("unexpected error: {}", other),
+    }
+}
+
+#[test]
+fn test_simple_sexpr_invalid() {
+    let tree = Neighborhood::with_paths(
+        vec![
+            path![Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+            path![Step::LtExpr, Step::Trace(1), Step::Value(Value::Bool(true))],
+            path![Step::LtExpr, Step::Trace(1)],
+        ]
+    );
+    let errors = tree.validate().unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!((errors[0].path, errors[0].step), (1, 2));
+    assert_eq!(errors[0].found, Some("Value(Bool(true))".to_string()));
+    assert!(errors[0].expected.contains("IfExpr"));
+    assert!(errors[0].expected.contains("Expr<isize>"));
+    assert_eq!((errors[1].path, errors[1].step), (2, 2));
+    assert_eq!(errors[1].found, None);
+}
+
+#[test]
+fn test_simple_sexpr_dot() {
+    let tree = Neighborhood::with_paths(
+        vec![
+            path![Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+            path![Step::LtExpr, Step::Trace(1), Step::Value(Value::Bool(true))],
+        ]
+    );
+    let dot = tree.to_dot();
+    assert!(dot.starts_with("digraph neighborhood {\n"));
+    assert!(dot.contains("    n0 [label=\"LtExpr\"];\n"));
+    assert!(dot.contains("    n1 [label=\"Value(Int(420))\"];\n    n0 -> n1 [label=\"0\"];\n"));
+    assert!(dot.contains("    n2 [label=\"Value(Bool(true))\", color=red, fontcolor=red];\n"));
+    let grammar = tree.grammar_to_dot();
+    assert!(grammar.contains("    \"Expr<bool>\" [shape=ellipse];\n"));
+    assert!(grammar.contains("[label=\"^0\"]"));
 }
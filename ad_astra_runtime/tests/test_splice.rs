@@ -0,0 +1,74 @@
+#[macro_use]
+extern crate ad_astra_runtime;
+
+#[macro_use]
+mod common;
+
+use ad_astra_runtime::SpliceError;
+
+use self::common::*;
+
+fn value(value: Value) -> Neighborhood {
+    Neighborhood::with_paths(vec![path![Step::Value(value)]])
+}
+
+#[test]
+fn test_splice_subtree() {
+    let tree = neighborhood();
+    let condition = tree.try_subtree(&[Step::IfExpr, Step::Trace(0)]).unwrap();
+    let expected = Neighborhood::with_paths(vec![
+        path![Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+        path![Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(130))],
+    ]);
+    assert_eq!(condition.to_tree(), expected.to_tree());
+    match tree.try_subtree(&[Step::IfExpr, Step::Trace(3)]) {
+        Err(SpliceError::Missing { prefix }) => assert_eq!(prefix, "[IfExpr, Trace(3)]"),
+        other => panic!("expected a missing subtree, found {:?}", other.map(|tree| tree.to_tree())),
+    }
+}
+
+#[test]
+fn test_splice_graft() {
+    let tree = neighborhood().replace(&[Step::IfExpr, Step::Trace(2)], &Neighborhood::new());
+    assert!(tree.check().is_err());
+    let grafted = tree.try_graft(&[Step::IfExpr, Step::Trace(2)], &value(Value::Str("c"))).unwrap();
+    assert_eq!(grafted.check(), Ok(()));
+    assert_eq!(grafted.subtree(&[Step::IfExpr, Step::Trace(2)]).to_tree(), value(Value::Str("c")).to_tree());
+    match tree.try_graft(&[Step::IfExpr, Step::Trace(2)], &value(Value::Int(1))) {
+        Err(SpliceError::Invalid(_)) => {}
+        other => panic!("expected an invalid neighborhood, found {:?}", other.map(|tree| tree.to_tree())),
+    }
+}
+
+#[test]
+fn test_splice_replace() {
+    let tree = neighborhood();
+    let replaced = tree.try_replace(&[Step::IfExpr, Step::Trace(1)], &value(Value::Str("c"))).unwrap();
+    let expected = Neighborhood::with_paths(vec![
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(0), Step::Value(Value::Int(420))],
+        path![Step::IfExpr, Step::Trace(0), Step::LtExpr, Step::Trace(1), Step::Value(Value::Int(130))],
+        path![Step::IfExpr, Step::Trace(1), Step::Value(Value::Str("c"))],
+        path![Step::IfExpr, Step::Trace(2), Step::Value(Value::Str("b"))],
+    ]);
+    assert_eq!(replaced.to_tree(), expected.to_tree());
+    match tree.try_replace(&[Step::IfExpr, Step::Trace(1)], &value(Value::Int(1))) {
+        Err(SpliceError::Type { expected, found, .. }) => assert_ne!(expected, found),
+        other => panic!("expected a type error, found {:?}", other.map(|tree| tree.to_tree())),
+    }
+    // A branch of the right type can't stand as the condition.
+    match tree.try_replace(&[Step::IfExpr, Step::Trace(0)], &tree.subtree(&[Step::IfExpr, Step::Trace(1)])) {
+        Err(SpliceError::Type { expected, found, .. }) => assert_ne!(expected, found),
+        other => panic!("expected a type error, found {:?}", other.map(|tree| tree.to_tree())),
+    }
+}
+
+#[test]
+fn test_splice_outside_of_slots() {
+    let tree = neighborhood();
+    let unchanged = tree.replace(&[Step::IfExpr, Step::Trace(3)], &value(Value::Str("c")));
+    assert_eq!(unchanged.to_tree(), tree.to_tree());
+    match tree.try_graft(&[Step::IfExpr, Step::Trace(3)], &value(Value::Str("c"))) {
+        Err(SpliceError::Invalid(errors)) => assert!(!errors.is_empty()),
+        other => panic!("expected an invalid neighborhood, found {:?}", other.map(|tree| tree.to_tree())),
+    }
+}
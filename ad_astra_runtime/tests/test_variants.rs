@@ -0,0 +1,53 @@
+#[macro_use]
+extern crate ad_astra_runtime;
+
+use std::fmt;
+
+use ad_astra_runtime::{Query, QueryError};
+
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Step {
+    Int(isize),
+    Sub,
+    Offshoot(usize),
+}
+
+/// Written the way a reader would, with no variant names.
+impl fmt::Debug for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Step::Int(n) => write!(f, "{}", n),
+            &Step::Sub => write!(f, "-"),
+            &Step::Offshoot(idx) => write!(f, "#{}", idx),
+        }
+    }
+}
+
+ast! {
+    #[variants(Int, Sub, Offshoot)]
+    Neighborhood, Path, Step, #[trace] Offshoot, (Expr) =>
+        (Expr ::= Int | (Sub (Expr ^ Expr)));
+}
+
+#[test]
+fn test_variants_with_custom_debug() {
+    let tree = Neighborhood::with_paths(vec![
+        path![Step::Sub, Step::Offshoot(0), Step::Int(1)],
+        path![Step::Sub, Step::Offshoot(1), Step::Sub, Step::Offshoot(0), Step::Int(2)],
+        path![Step::Sub, Step::Offshoot(1), Step::Sub, Step::Offshoot(1), Step::Int(3)],
+    ]);
+    assert_eq!(tree.validate(), Ok(()));
+    let tree = Neighborhood::with_paths(vec![path![Step::Offshoot(0), Step::Int(1)]]);
+    let errors = tree.validate().unwrap_err();
+    assert_eq!((errors[0].path, errors[0].step), (0, 0));
+    assert_eq!(errors[0].found, Some("#0".to_string()));
+}
+
+#[test]
+fn test_variants_in_queries() {
+    let paths = vec![vec![Step::Sub, Step::Offshoot(1), Step::Int(3)]];
+    let query = Query::<Step>::new("Sub/Trace(1)/Int").unwrap();
+    assert_eq!(query.select(&paths), vec![&paths[0][..]]);
+    let error = Query::<Step>::new("Sub/Trace(1)/Itn").err();
+    assert_eq!(error, Some(QueryError { column: 14, message: "unknown variant `Itn`".to_string() }));
+}